@@ -3,8 +3,8 @@ use dalbrack::{
     grid::dijkstra_map,
     input::map_event_in_game_state,
     map::builders::{
-        BuildConfig, BuildMap, CaRule, CellularAutomata, StartingPosition,
-        voronoi_regions_from_seeds, voronoi_seeds,
+        BuildConfig, BuildMap, CaRule, CellularAutomata, Pipeline, voronoi_regions_from_seeds,
+        voronoi_seeds,
     },
     player::Player,
     rng::RngHandle,
@@ -41,7 +41,7 @@ macro_rules! set {
 
 pub fn main() -> anyhow::Result<()> {
     let mut state = State::init(DisplayMode::Fixed(W as u32, H as u32, DXY), TITLE)?;
-    let mut builder = Box::new(parse_ca_rule()?) as Box<dyn BuildMap>;
+    let mut builder = Box::new(Pipeline::cave(parse_ca_rule()?)) as Box<dyn BuildMap>;
     let (pos, mut map) = builder.new_map(W as usize, H as usize, CFG, &mut state);
     map.explore_all();
     state.set_map(map);
@@ -89,7 +89,7 @@ pub fn main() -> anyhow::Result<()> {
         if FILE_CHANGED.swap(false, Ordering::Relaxed) {
             match parse_ca_rule() {
                 Ok(ca) => {
-                    set!(builder, ca, state);
+                    set!(builder, Pipeline::cave(ca), state);
                     seeds = voronoi_seeds(N_GROUPS, W as usize, H as usize, &mut rng);
                     update_ui(&seeds, &colors, use_voronoi, &mut state)?;
                     continue;
@@ -109,7 +109,7 @@ pub fn main() -> anyhow::Result<()> {
                     } => match parse_ca_rule() {
                         Ok(ca) => {
                             seeds = voronoi_seeds(N_GROUPS, W as usize, H as usize, &mut rng);
-                            set!(builder, ca, state);
+                            set!(builder, Pipeline::cave(ca), state);
                         }
                         Err(e) => println!("ERROR {e}"),
                     },
@@ -202,11 +202,9 @@ fn parse_ca_rule() -> anyhow::Result<CellularAutomata> {
     let survive = as_u8s(s.trim())?;
 
     Ok(CellularAutomata {
-        start_pos: StartingPosition::Center,
         p_initial_open,
         iterations,
         rule: CaRule::LifeLike { born, survive },
-        regions: Default::default(),
     })
 }
 
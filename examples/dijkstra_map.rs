@@ -2,7 +2,7 @@ use dalbrack::{
     Grid, Pos, TITLE,
     grid::dijkstra_map,
     input::map_event_in_game_state,
-    map::builders::{BspDungeon, BuildConfig, BuildMap, CellularAutomata},
+    map::builders::{BuildConfig, BuildMap, CellularAutomata, Pipeline},
     player::Player,
     state::State,
     tileset::Tile,
@@ -27,7 +27,7 @@ macro_rules! set {
 
 pub fn main() -> anyhow::Result<()> {
     let mut state = State::init(DisplayMode::Fixed(W, H, DXY), TITLE)?;
-    let mut builder = Box::new(BspDungeon::default()) as Box<dyn BuildMap>;
+    let mut builder = Box::new(Pipeline::dungeon()) as Box<dyn BuildMap>;
     let (pos, mut map) = builder.new_map(W as usize, H as usize, CFG, &mut state);
     map.explore_all();
     state.set_map(map);
@@ -48,12 +48,28 @@ pub fn main() -> anyhow::Result<()> {
                         repeat: false,
                         ..
                     } => match k {
-                        Keycode::Num1 => set!(builder, BspDungeon::default(), state),
-                        Keycode::Num2 => set!(builder, CellularAutomata::simple(), state),
-                        Keycode::Num3 => set!(builder, CellularAutomata::rogue_basin(), state),
-                        Keycode::Num4 => set!(builder, CellularAutomata::diamoeba(), state),
-                        Keycode::Num5 => set!(builder, CellularAutomata::invertamaze(), state),
-                        Keycode::Num6 => set!(builder, CellularAutomata::mazectric(), state),
+                        Keycode::Num1 => set!(builder, Pipeline::dungeon(), state),
+                        Keycode::Num2 => {
+                            set!(builder, Pipeline::cave(CellularAutomata::simple()), state)
+                        }
+                        Keycode::Num3 => set!(
+                            builder,
+                            Pipeline::cave(CellularAutomata::rogue_basin()),
+                            state
+                        ),
+                        Keycode::Num4 => {
+                            set!(builder, Pipeline::cave(CellularAutomata::diamoeba()), state)
+                        }
+                        Keycode::Num5 => set!(
+                            builder,
+                            Pipeline::cave(CellularAutomata::invertamaze()),
+                            state
+                        ),
+                        Keycode::Num6 => set!(
+                            builder,
+                            Pipeline::cave(CellularAutomata::mazectric()),
+                            state
+                        ),
 
                         Keycode::R => {
                             let (pos, mut map) =
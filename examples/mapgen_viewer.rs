@@ -1,6 +1,9 @@
 use dalbrack::{
     TITLE,
-    map::builders::{BspDungeon, BuildMap, CellularAutomata},
+    map::builders::{
+        BuildMap, CellularAutomata, DrunkardsWalk, Maze, MazeAlgorithm, NoiseTerrain, Pipeline,
+        RogueGrid, Town, Turmites, Wfc,
+    },
     state::State,
     ui::DisplayMode,
 };
@@ -22,7 +25,7 @@ macro_rules! set {
 
 pub fn main() -> anyhow::Result<()> {
     let mut state = State::init(DisplayMode::Fixed(W, H, DXY), TITLE)?;
-    let mut builder = Box::new(CellularAutomata::default()) as Box<dyn BuildMap>;
+    let mut builder = Box::new(Pipeline::cave(CellularAutomata::default())) as Box<dyn BuildMap>;
     let mut maps = builder.trace_build(W as usize, H as usize, &mut state);
     maps.reverse();
 
@@ -46,15 +49,92 @@ pub fn main() -> anyhow::Result<()> {
                     repeat: false,
                     ..
                 } => match k {
-                    Keycode::Num1 => set!(builder, BspDungeon::default(), maps, state),
-                    Keycode::Num2 => set!(builder, CellularAutomata::simple(), maps, state),
-                    Keycode::Num3 => set!(builder, CellularAutomata::rogue_basin(), maps, state),
-                    Keycode::Num4 => set!(builder, CellularAutomata::diamoeba(), maps, state),
-                    Keycode::Num5 => set!(builder, CellularAutomata::invertamaze(), maps, state),
-                    Keycode::Num6 => set!(builder, CellularAutomata::walled_cities(), maps, state),
-                    Keycode::Num7 => set!(builder, CellularAutomata::corrosion(), maps, state),
-                    Keycode::Num8 => set!(builder, CellularAutomata::ice_balls(), maps, state),
-                    Keycode::Num9 => set!(builder, CellularAutomata::coagulations(), maps, state),
+                    Keycode::Num1 => set!(builder, Pipeline::dungeon(), maps, state),
+                    Keycode::Num2 => set!(
+                        builder,
+                        Pipeline::cave(CellularAutomata::simple()),
+                        maps,
+                        state
+                    ),
+                    Keycode::Num3 => set!(
+                        builder,
+                        Pipeline::cave(CellularAutomata::rogue_basin()),
+                        maps,
+                        state
+                    ),
+                    Keycode::Num4 => set!(
+                        builder,
+                        Pipeline::cave(CellularAutomata::diamoeba()),
+                        maps,
+                        state
+                    ),
+                    Keycode::Num5 => set!(
+                        builder,
+                        Pipeline::cave(CellularAutomata::invertamaze()),
+                        maps,
+                        state
+                    ),
+                    Keycode::Num6 => set!(
+                        builder,
+                        Pipeline::cave(CellularAutomata::walled_cities()),
+                        maps,
+                        state
+                    ),
+                    Keycode::Num7 => set!(
+                        builder,
+                        Pipeline::cave(CellularAutomata::corrosion()),
+                        maps,
+                        state
+                    ),
+                    Keycode::Num8 => set!(
+                        builder,
+                        Pipeline::cave(CellularAutomata::ice_balls()),
+                        maps,
+                        state
+                    ),
+                    Keycode::Num9 => set!(
+                        builder,
+                        Pipeline::cave(CellularAutomata::coagulations()),
+                        maps,
+                        state
+                    ),
+                    Keycode::Num0 => set!(
+                        builder,
+                        Pipeline::drunkards_walk(DrunkardsWalk::winding_passages()),
+                        maps,
+                        state
+                    ),
+
+                    Keycode::M => set!(
+                        builder,
+                        Pipeline::maze(Maze {
+                            braid: 0.5,
+                            rooms: 3,
+                            ..Maze::new(MazeAlgorithm::Wilsons)
+                        }),
+                        maps,
+                        state
+                    ),
+
+                    Keycode::T => set!(
+                        builder,
+                        Pipeline::turmites(Turmites::default()),
+                        maps,
+                        state
+                    ),
+
+                    Keycode::G => set!(builder, Pipeline::rogue(RogueGrid::default()), maps, state),
+
+                    Keycode::W => set!(builder, Pipeline::wfc(Wfc::default()), maps, state),
+
+                    Keycode::O => set!(
+                        builder,
+                        Pipeline::overworld(NoiseTerrain::default()),
+                        maps,
+                        state
+                    ),
+
+                    Keycode::V => set!(builder, Pipeline::town(Town::default()), maps, state),
 
                     Keycode::R => {
                         maps = builder.trace_build(W as usize, H as usize, &mut state);
@@ -2,7 +2,7 @@ use dalbrack::{
     Pos, TITLE,
     input::map_event_in_game_state,
     map::{
-        builders::{BspDungeon, BuildConfig, BuildMap},
+        builders::{BuildConfig, BuildMap, Pipeline},
         fov::FovRange,
     },
     player::Player,
@@ -21,7 +21,7 @@ struct PathTile;
 
 pub fn main() -> anyhow::Result<()> {
     let mut state = State::init(DisplayMode::Fixed(W, H, DXY), TITLE)?;
-    let (pos, mut map) = BspDungeon::default().new_map(W as usize, H as usize, CFG, &mut state);
+    let (pos, mut map) = Pipeline::dungeon().new_map(W as usize, H as usize, CFG, &mut state);
     map.explore_all();
     state.set_map(map);
 
@@ -48,7 +48,7 @@ pub fn main() -> anyhow::Result<()> {
                         ..
                     } => match k {
                         Keycode::R => {
-                            let (pos, mut map) = BspDungeon::default()
+                            let (pos, mut map) = Pipeline::dungeon()
                                 .new_map(W as usize, H as usize, CFG, &mut state);
                             map.explore_all();
                             state.set_map(map);
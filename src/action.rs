@@ -22,6 +22,11 @@ impl AvailableActions {
         actions.into_iter().next()
     }
 
+    /// Descriptions of what each provider is currently doing (if they support it)
+    pub fn running_nodes(&self) -> Vec<String> {
+        self.0.iter().flat_map(|p| p.running_node()).collect()
+    }
+
     pub fn push<P>(&mut self, provider: P)
     where
         P: ActionProvider,
@@ -43,6 +48,11 @@ pub trait ActionProvider: Debug + Send + Sync + 'static {
 
     fn retain(&self) -> bool;
 
+    /// A description of what this provider is currently doing for debugging purposes
+    fn running_node(&self) -> Option<String> {
+        None
+    }
+
     fn into_single_action(mut self, entity: Entity, state: &State<'_>) -> Action
     where
         Self: Sized,
@@ -65,6 +75,17 @@ impl Action {
 }
 
 impl Action {
+    /// Combine multiple actions into a single action that runs each of them in turn
+    pub fn chain(actions: Vec<Action>) -> Self {
+        Self::from(move |state: &mut State<'_>| {
+            for action in actions.iter() {
+                (action.0)(state)?;
+            }
+
+            Ok(())
+        })
+    }
+
     pub fn run(self, state: &mut State<'_>) -> anyhow::Result<()> {
         (self.0)(state)
     }
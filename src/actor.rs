@@ -1,11 +1,21 @@
 use crate::{
     Pos,
     action::{Action, ActionProvider, AvailableActions},
-    map::fov::{Fov, Opacity},
+    ai::follow::is_following,
+    faction::Faction,
+    map::{
+        Map,
+        features::Feature,
+        fov::{Fov, Opacity},
+    },
+    mob::{BarkEvent, Barks},
+    overworld,
+    sound::{COMBAT_LOUDNESS, FOOTSTEP_LOUDNESS, Sound, SoundKind},
     state::State,
     tileset::Tile,
 };
 use hecs::{Bundle, Entity};
+use std::cmp::max;
 
 #[derive(Debug, Bundle)]
 pub struct Actor {
@@ -15,6 +25,19 @@ pub struct Actor {
     pub actions: AvailableActions,
 }
 
+/// The name an entity is referred to by
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Name(pub String);
+
+/// Core combat stats for an actor
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub hp: i32,
+    pub max_hp: i32,
+    pub attack: i32,
+    pub defence: i32,
+}
+
 impl Actor {
     pub fn wait(entity: Entity, state: &State<'_>) -> Option<Action> {
         state
@@ -28,8 +51,38 @@ impl Actor {
 
     pub fn try_move(dx: i32, dy: i32, entity: Entity, state: &State<'_>) -> Option<Action> {
         let pos = *state.world.get::<&Pos>(entity).unwrap() + Pos::new(dx, dy);
+
+        // bumping into another actor attacks it if it is a threat. Otherwise the player can swap
+        // places with it, as can anyone with one of their own followers.
+        if let Some(target) = state.actor_at(pos) {
+            let swap = is_following(target, entity, &state.world);
+            let attack = !swap && state.relationship(entity, target).is_threat();
+            let mut actions = state.world.get::<&mut AvailableActions>(entity).unwrap();
+            if attack {
+                actions.push(Attack(target));
+            } else if swap || entity == state.e_player {
+                actions.push(SwapPlaces(target));
+            }
+
+            return None;
+        }
+
         let map = state.mapset.current();
+        if entity == state.e_player && !map.contains_pos(pos) {
+            // walking off the edge of the map takes the player to the neighbouring region
+            return overworld::travel(pos, state);
+        }
+
         if map.tile_at(pos).blocks_movement() {
+            // bumping into a closed door opens it and the player can also operate levers
+            if entity == state.e_player && map.features.contains_key(&pos) {
+                return Some(Action::from(move |state: &mut State<'_>| {
+                    Feature::interact(pos, state).map(|_| ())
+                }));
+            } else if map.closed_door_at(pos) {
+                return Some(Actor::step_to(entity, pos));
+            }
+
             return None;
         }
 
@@ -42,6 +95,47 @@ impl Actor {
         None
     }
 
+    /// Attack whatever actor is in the given direction regardless of how we feel about it
+    pub fn force_attack(dx: i32, dy: i32, entity: Entity, state: &State<'_>) -> Option<Action> {
+        let pos = *state.world.get::<&Pos>(entity).unwrap() + Pos::new(dx, dy);
+        let target = state.actor_at(pos)?;
+        state
+            .world
+            .get::<&mut AvailableActions>(entity)
+            .unwrap()
+            .push(Attack(target));
+
+        None
+    }
+
+    /// Step to the neighbouring cell `pos`, or open it instead if it is a closed door
+    pub fn step_to(entity: Entity, pos: Pos) -> Action {
+        Action::from(move |state: &mut State<'_>| {
+            if state.mapset.current().closed_door_at(pos) {
+                Feature::open_door(pos, entity, state);
+                return Ok(());
+            }
+
+            *state.world.get::<&mut Pos>(entity)? = pos;
+            if let Ok(mut fov) = state.world.get::<&mut Fov>(entity) {
+                fov.dirty = true;
+            };
+
+            Self::footsteps(entity, pos, state)
+        })
+    }
+
+    /// Let anything in earshot hear `entity` stepping into `pos`: heavy going such as undergrowth
+    /// is louder to move through.
+    pub fn footsteps(entity: Entity, pos: Pos, state: &mut State<'_>) -> anyhow::Result<()> {
+        if state.mapset.is_empty() {
+            return Ok(());
+        }
+
+        let loudness = FOOTSTEP_LOUDNESS + state.mapset.current().tile_at(pos).move_weight as i32;
+        Sound::new(pos, loudness, SoundKind::Footsteps, Some(entity)).emit(state)
+    }
+
     pub fn path_to_in_player_explored(
         target: Pos,
         entity: Entity,
@@ -57,6 +151,87 @@ impl Actor {
 
         None
     }
+
+    /// Resolve a melee attack from `attacker` against `target`.
+    ///
+    /// Either entity may have been removed by the time this runs in which case it is a no-op.
+    pub fn attack(attacker: Entity, target: Entity, state: &mut State<'_>) -> anyhow::Result<()> {
+        let attack = match state.world.get::<&Stats>(attacker) {
+            Ok(stats) => stats.attack,
+            Err(_) => return Ok(()),
+        };
+
+        let hp = match state.world.get::<&mut Stats>(target) {
+            Ok(mut stats) => {
+                stats.hp -= max(1, attack - stats.defence);
+                stats.hp
+            }
+            Err(_) => return Ok(()),
+        };
+
+        let pos = *state.world.get::<&Pos>(target)?;
+        Sound::new(pos, COMBAT_LOUDNESS, SoundKind::Combat, Some(attacker)).emit(state)?;
+
+        let (a, t) = (state.describe(attacker), state.describe(target));
+        let verb = if attacker == state.e_player {
+            "hit"
+        } else {
+            "hits"
+        };
+        state.log(format!("{} {verb} {t}.", capitalise(&a)));
+
+        let factions = (
+            state.world.get::<&Faction>(attacker).map(|f| f.0.clone()),
+            state.world.get::<&Faction>(target).map(|f| f.0.clone()),
+        );
+        if let (Ok(fa), Ok(ft)) = factions {
+            let before = state.relationship(target, attacker);
+            state.relationships.on_attack(&fa, &ft);
+            if attacker == state.e_player && state.relationship(target, attacker) < before {
+                state.log(format!("The {ft} will remember that..."));
+            }
+        }
+
+        if hp <= 0 {
+            if target == state.e_player {
+                state.log("You have died...");
+                state.running = false;
+            } else {
+                state.log(format!("{} dies.", capitalise(&t)));
+                state.world.despawn(target)?;
+            }
+        } else {
+            Barks::trigger(target, BarkEvent::Hurt, state)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn capitalise(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(ch) => ch.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Attack another actor in melee
+#[derive(Debug)]
+pub struct Attack(pub Entity);
+
+impl ActionProvider for Attack {
+    fn retain(&self) -> bool {
+        false
+    }
+
+    fn available_actions(&mut self, entity: Entity, _state: &State<'_>) -> Option<Vec<Action>> {
+        let target = self.0;
+
+        Some(vec![Action::from(move |state: &mut State<'_>| {
+            Actor::attack(entity, target, state)
+        })])
+    }
 }
 
 #[derive(Debug)]
@@ -90,19 +265,66 @@ impl ActionProvider for Move1 {
                 fov.dirty = true;
             };
 
-            Ok(())
+            Actor::footsteps(entity, pos, state)
         })])
     }
 }
 
+/// Swap positions with another actor
 #[derive(Debug)]
+pub struct SwapPlaces(pub Entity);
+
+impl SwapPlaces {
+    pub fn action(entity: Entity, pos: Pos, other: Entity, other_pos: Pos) -> Option<Action> {
+        Some(Action::from(move |state: &mut State<'_>| {
+            for (e, p) in [(entity, other_pos), (other, pos)] {
+                *state.world.get::<&mut Pos>(e)? = p;
+                if let Ok(mut fov) = state.world.get::<&mut Fov>(e) {
+                    fov.dirty = true;
+                };
+            }
+
+            Ok(())
+        }))
+    }
+}
+
+impl ActionProvider for SwapPlaces {
+    fn retain(&self) -> bool {
+        false
+    }
+
+    fn available_actions(&mut self, entity: Entity, state: &State<'_>) -> Option<Vec<Action>> {
+        let pos = *state.world.get::<&Pos>(entity).ok()?;
+        let other_pos = *state.world.get::<&Pos>(self.0).ok()?;
+
+        Some(vec![Self::action(entity, pos, self.0, other_pos)?])
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct FollowPath {
     path: Vec<Pos>,
 }
 
 impl FollowPath {
+    /// The next position along the path if there is one
+    pub fn next_step(&self) -> Option<Pos> {
+        self.path.last().copied()
+    }
+
+    /// The final position of the path if there is one
+    pub fn target(&self) -> Option<Pos> {
+        self.path.first().copied()
+    }
+
     pub fn try_new_a_star(from: Pos, target: Pos, state: &State<'_>) -> Option<Self> {
-        let mut path = state.mapset.current().a_star(from, target);
+        Self::try_new_a_star_on(from, target, state.mapset.current())
+    }
+
+    pub fn try_new_a_star_on(from: Pos, target: Pos, map: &Map) -> Option<Self> {
+        let mut path = map.a_star(from, target);
+        path.retain(|&p| p != from);
 
         if path.is_empty() {
             None
@@ -113,6 +335,23 @@ impl FollowPath {
         }
     }
 
+    /// The position to step to this turn, or `None` if the path is now blocked.
+    pub fn advance(&mut self, map: &Map) -> Option<Pos> {
+        let pos = self.next_step()?;
+        if map.closed_door_at(pos) {
+            // opening the door uses up this turn and we step through it on the next one
+            return Some(pos);
+        }
+
+        self.path.pop();
+        if map.tile_at(pos).blocks_movement() {
+            self.path.clear();
+            return None;
+        }
+
+        Some(pos)
+    }
+
     pub fn try_new_a_star_in_player_explored(
         from: Pos,
         target: Pos,
@@ -122,6 +361,7 @@ impl FollowPath {
             .mapset
             .current()
             .a_star_in_player_explored(from, target);
+        path.retain(|&p| p != from);
 
         if path.is_empty() {
             None
@@ -139,20 +379,8 @@ impl ActionProvider for FollowPath {
     }
 
     fn available_actions(&mut self, entity: Entity, state: &State<'_>) -> Option<Vec<Action>> {
-        let pos = self.path.pop()?;
-        let map = state.mapset.current();
-        if map.tile_at(pos).blocks_movement() {
-            self.path.clear();
-            return None;
-        }
-
-        Some(vec![Action::from(move |state: &mut State<'_>| {
-            *state.world.get::<&mut Pos>(entity).unwrap() = pos;
-            if let Ok(mut fov) = state.world.get::<&mut Fov>(entity) {
-                fov.dirty = true;
-            };
+        let pos = self.advance(state.mapset.current())?;
 
-            Ok(())
-        })])
+        Some(vec![Actor::step_to(entity, pos)])
     }
 }
@@ -0,0 +1,224 @@
+//! Companions that trail along behind a leader
+use crate::{
+    Pos,
+    action::{Action, ActionProvider},
+    actor::{Actor, FollowPath, SwapPlaces},
+    map::Map,
+    state::State,
+};
+use hecs::{Entity, World};
+
+/// Marks an entity as a companion of `leader` that tries to stay within `leash` of them.
+#[derive(Debug, Clone, Copy)]
+pub struct Follower {
+    pub leader: Entity,
+    pub leash: f32,
+    /// Cleared when the follower has been told to wait where they are
+    pub following: bool,
+}
+
+impl Follower {
+    pub fn new(leader: Entity, leash: f32) -> Self {
+        Self {
+            leader,
+            leash,
+            following: true,
+        }
+    }
+}
+
+/// Whether `entity` is a follower of `leader`
+pub fn is_following(entity: Entity, leader: Entity, world: &World) -> bool {
+    world
+        .get::<&Follower>(entity)
+        .is_ok_and(|f| f.leader == leader)
+}
+
+/// An [ActionProvider] for keeping a [Follower] within their leash of their leader.
+///
+/// A* is used to catch back up once the leader gets too far away. Other followers of the same
+/// leader that are in the way are swapped places with rather than walked around.
+#[derive(Debug, Clone, Default)]
+pub struct FollowLeader {
+    path: Option<FollowPath>,
+}
+
+/// What a [Follower] has decided to do this turn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FollowStep {
+    /// Close enough to the leader, told to wait or unable to get any closer
+    Stay,
+    /// Move one step closer to the leader
+    StepTo(Pos),
+    /// Trade places with another follower of the same leader who is in the way
+    Swap(Entity, Pos),
+}
+
+impl FollowLeader {
+    /// Returns `None` if we are not currently following anyone and `Some(None)` if we are close
+    /// enough to our leader that we don't need to move.
+    pub fn step(&mut self, entity: Entity, state: &State<'_>) -> Option<Option<Action>> {
+        let map = state.mapset.current();
+        let pos = *state.world.get::<&Pos>(entity).ok()?;
+
+        let action = match self.plan(entity, &state.world, map, |p| state.actor_at(p))? {
+            FollowStep::Stay => None,
+            FollowStep::StepTo(p) => Some(Actor::step_to(entity, p)),
+            FollowStep::Swap(other, p) => SwapPlaces::action(entity, pos, other, p),
+        };
+
+        Some(action)
+    }
+
+    fn plan(
+        &mut self,
+        entity: Entity,
+        world: &World,
+        map: &Map,
+        actor_at: impl Fn(Pos) -> Option<Entity>,
+    ) -> Option<FollowStep> {
+        let follower = *world.get::<&Follower>(entity).ok()?;
+        if !follower.following {
+            self.path = None;
+            return Some(FollowStep::Stay);
+        }
+
+        let pos = *world.get::<&Pos>(entity).ok()?;
+        let leader_pos = *world.get::<&Pos>(follower.leader).ok()?;
+        if pos.fdist(leader_pos) <= follower.leash {
+            self.path = None;
+            return Some(FollowStep::Stay);
+        }
+
+        if self
+            .path
+            .as_ref()
+            .is_none_or(|fp| !fp.retain() || fp.target() != Some(leader_pos))
+        {
+            self.path = FollowPath::try_new_a_star_on(pos, leader_pos, map);
+        }
+
+        let fp = match self.path.as_mut() {
+            Some(fp) => fp,
+            None => return Some(FollowStep::Stay), // no way to reach them
+        };
+
+        let next = fp.next_step()?;
+        match actor_at(next) {
+            Some(other) if other == follower.leader => Some(FollowStep::Stay),
+            Some(other) if is_following(other, follower.leader, world) => {
+                self.path = None;
+                Some(FollowStep::Swap(other, next))
+            }
+            Some(_) => {
+                self.path = None;
+                Some(FollowStep::Stay) // something else is in the way
+            }
+            None => fp.advance(map).map(FollowStep::StepTo),
+        }
+    }
+}
+
+impl ActionProvider for FollowLeader {
+    fn retain(&self) -> bool {
+        true
+    }
+
+    fn available_actions(&mut self, entity: Entity, state: &State<'_>) -> Option<Vec<Action>> {
+        self.step(entity, state)?.map(|action| vec![action])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{features::Feature, map_tile::Terrain};
+    use sdl2::{pixels::Color, rect::Rect};
+
+    /// A single cell wide corridor running the length of the map
+    fn corridor_map() -> Map {
+        let terrain = Terrain::for_tests(
+            [("wall", None), ("floor", Some(1))]
+                .into_iter()
+                .chain(Feature::terrain_names().map(|name| (name, None))),
+        );
+        let mut map = Map::new(40, 5, terrain, Color::BLACK, Color::BLACK);
+        map.carve_rect(Rect::new(1, 2, 38, 1), 1);
+
+        map
+    }
+
+    fn actor_at(world: &World) -> impl Fn(Pos) -> Option<Entity> {
+        |p| {
+            world
+                .query::<&Pos>()
+                .iter()
+                .find(|(_, pos)| **pos == p)
+                .map(|(e, _)| e)
+        }
+    }
+
+    #[test]
+    fn recruited_followers_close_to_within_their_leash() {
+        let map = corridor_map();
+        let mut world = World::new();
+        let leader = world.spawn((Pos::new(30, 2),));
+        let e = world.spawn((Pos::new(2, 2), Follower::new(leader, 2.0)));
+        let mut follow = FollowLeader::default();
+
+        for _ in 0..40 {
+            match follow.plan(e, &world, &map, actor_at(&world)) {
+                Some(FollowStep::StepTo(p)) => *world.get::<&mut Pos>(e).unwrap() = p,
+                Some(FollowStep::Stay) => break,
+                step => panic!("unexpected step: {step:?}"),
+            }
+        }
+
+        let pos = *world.get::<&Pos>(e).unwrap();
+        assert!(pos.fdist(Pos::new(30, 2)) <= 2.0, "{pos:?}");
+    }
+
+    #[test]
+    fn unrecruited_followers_fail_the_follow_leaf() {
+        let map = corridor_map();
+        let mut world = World::new();
+        let e = world.spawn((Pos::new(2, 2),));
+
+        let step = FollowLeader::default().plan(e, &world, &map, actor_at(&world));
+
+        assert_eq!(step, None);
+    }
+
+    #[test]
+    fn followers_told_to_wait_stay_put() {
+        let map = corridor_map();
+        let mut world = World::new();
+        let leader = world.spawn((Pos::new(30, 2),));
+        let mut follower = Follower::new(leader, 2.0);
+        follower.following = false;
+        let e = world.spawn((Pos::new(2, 2), follower));
+
+        let step = FollowLeader::default().plan(e, &world, &map, actor_at(&world));
+
+        assert_eq!(step, Some(FollowStep::Stay));
+    }
+
+    #[test]
+    fn followers_swap_places_rather_than_blocking_each_other() {
+        let map = corridor_map();
+        let mut world = World::new();
+        let leader = world.spawn((Pos::new(30, 2),));
+        let e = world.spawn((Pos::new(2, 2), Follower::new(leader, 2.0)));
+        let other = world.spawn((Pos::new(3, 2), Follower::new(leader, 2.0)));
+
+        let step = FollowLeader::default().plan(e, &world, &map, actor_at(&world));
+        assert_eq!(step, Some(FollowStep::Swap(other, Pos::new(3, 2))));
+
+        let step = FollowLeader::default().plan(other, &world, &map, actor_at(&world));
+        assert_eq!(step, Some(FollowStep::StepTo(Pos::new(4, 2))));
+
+        world.spawn((Pos::new(4, 2),));
+        let step = FollowLeader::default().plan(other, &world, &map, actor_at(&world));
+        assert_eq!(step, Some(FollowStep::Stay));
+    }
+}
@@ -0,0 +1,77 @@
+//! What mobs remember about the things they have seen
+use crate::Pos;
+use hecs::Entity;
+use std::collections::HashMap;
+
+/// Number of ticks after which a sighting is forgotten
+pub const FORGET_AFTER: usize = 60;
+
+/// Where and when something was last seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sighting {
+    pub pos: Pos,
+    pub tick: usize,
+}
+
+/// The last known positions of the actors that a mob has seen along with the last thing it heard.
+///
+/// Sightings are refreshed each turn that the actor remains in line of sight so the recorded
+/// position is only ever out of date once they have been lost from view.
+#[derive(Debug, Default, Clone)]
+pub struct Memory {
+    sightings: HashMap<Entity, Sighting>,
+    heard: Option<Sighting>,
+}
+
+impl Memory {
+    pub fn see(&mut self, entity: Entity, pos: Pos, tick: usize) {
+        self.sightings.insert(entity, Sighting { pos, tick });
+    }
+
+    pub fn last_seen(&self, entity: Entity) -> Option<Sighting> {
+        self.sightings.get(&entity).copied()
+    }
+
+    pub fn forget(&mut self, entity: Entity) {
+        self.sightings.remove(&entity);
+    }
+
+    /// Remember roughly where the most recent sound we heard came from
+    pub fn hear(&mut self, pos: Pos, tick: usize) {
+        self.heard = Some(Sighting { pos, tick });
+    }
+
+    pub fn last_heard(&self) -> Option<Sighting> {
+        self.heard
+    }
+
+    pub fn forget_heard(&mut self) {
+        self.heard = None;
+    }
+
+    /// Drop any sightings that are too old to be worth acting on
+    pub fn forget_stale(&mut self, tick: usize) {
+        let is_fresh = |s: &Sighting| tick.saturating_sub(s.tick) <= FORGET_AFTER;
+        self.sightings.retain(|_, s| is_fresh(s));
+        self.heard = self.heard.filter(is_fresh);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_sightings_are_forgotten() {
+        let mut world = hecs::World::new();
+        let (a, b) = (world.spawn(()), world.spawn(()));
+        let mut memory = Memory::default();
+
+        memory.see(a, Pos::new(1, 1), 0);
+        memory.see(b, Pos::new(2, 2), 10);
+        memory.forget_stale(FORGET_AFTER + 5);
+
+        assert_eq!(memory.last_seen(a), None);
+        assert_eq!(memory.last_seen(b).map(|s| s.pos), Some(Pos::new(2, 2)));
+    }
+}
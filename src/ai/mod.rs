@@ -0,0 +1,237 @@
+//! Behaviour trees for composing mob AIs out of simple building blocks.
+//!   https://en.wikipedia.org/wiki/Behavior_tree_(artificial_intelligence,_robotics_and_control)
+//!   https://www.gamedeveloper.com/programming/behavior-trees-for-ai-how-they-work
+//!
+//! Trees are ticked once per turn and stop at the first leaf that produces an action. Conditions
+//! never act, they only succeed or fail in order to guard the nodes that follow them.
+use crate::{
+    action::{Action, ActionProvider},
+    state::State,
+};
+use hecs::Entity;
+
+pub mod follow;
+mod memory;
+mod nodes;
+pub mod perception;
+
+pub use memory::{FORGET_AFTER, Memory, Sighting};
+pub use nodes::{Condition, FleeFrom, Leaf, SearchProgress};
+
+/// The result of ticking a [Node]
+pub enum Status {
+    /// The node completed without needing to act this turn
+    Success,
+    /// The node was unable to run
+    Failure,
+    /// The node has produced the action to take this turn
+    Running(Action),
+}
+
+/// Shared state available to nodes while a tree is being ticked
+pub struct Ctx<'a, 'b> {
+    pub entity: Entity,
+    pub state: &'a State<'b>,
+    /// Actions to run alongside whatever action the tree ends up producing
+    pub effects: Vec<Action>,
+}
+
+impl AsMut<Vec<Action>> for Ctx<'_, '_> {
+    fn as_mut(&mut self) -> &mut Vec<Action> {
+        &mut self.effects
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Node {
+    /// Run children in order until one of them does not fail
+    Selector(Vec<Node>),
+    /// Run children in order until one of them does not succeed
+    Sequence(Vec<Node>),
+    /// Swap the success or failure of the child node
+    Not(Box<Node>),
+    Condition(Condition),
+    Leaf(Leaf),
+}
+
+impl Node {
+    pub fn label(&self) -> String {
+        match self {
+            Self::Selector(_) => "selector".to_string(),
+            Self::Sequence(_) => "sequence".to_string(),
+            Self::Not(node) => format!("not {}", node.label()),
+            Self::Condition(c) => c.label(),
+            Self::Leaf(l) => l.label(),
+        }
+    }
+
+    /// Tick this node, recording the path to the node that ends up running in `trail`
+    fn tick(&mut self, ctx: &mut Ctx<'_, '_>, trail: &mut Vec<String>) -> Status {
+        trail.push(self.label());
+
+        let status = match self {
+            Self::Selector(children) => {
+                tick_children(children, ctx, true, |child, ctx| child.tick(ctx, trail))
+            }
+
+            Self::Sequence(children) => {
+                tick_children(children, ctx, false, |child, ctx| child.tick(ctx, trail))
+            }
+
+            Self::Not(child) => {
+                let n_effects = ctx.effects.len();
+                match child.tick(ctx, trail) {
+                    Status::Success => {
+                        ctx.effects.truncate(n_effects);
+                        Status::Failure
+                    }
+                    Status::Failure => Status::Success,
+                    running => running,
+                }
+            }
+
+            Self::Condition(c) => {
+                if c.check(ctx.entity, ctx.state) {
+                    Status::Success
+                } else {
+                    Status::Failure
+                }
+            }
+
+            Self::Leaf(l) => l.run(ctx),
+        };
+
+        if !matches!(status, Status::Running(_)) {
+            trail.pop();
+        }
+
+        status
+    }
+}
+
+/// Tick the children of a selector (or sequence) in order until one of them does not fail (or
+/// does not succeed). Effects are only kept from branches that the tree settles on: those from a
+/// failed selector child or a sequence that fails part way through are discarded.
+fn tick_children<T, C>(
+    children: &mut [T],
+    ctx: &mut C,
+    selector: bool,
+    mut tick: impl FnMut(&mut T, &mut C) -> Status,
+) -> Status
+where
+    C: AsMut<Vec<Action>>,
+{
+    let n_effects = ctx.as_mut().len();
+
+    for child in children.iter_mut() {
+        let n_child_effects = ctx.as_mut().len();
+        match (tick(child, ctx), selector) {
+            (Status::Failure, true) => ctx.as_mut().truncate(n_child_effects),
+            (Status::Success, false) => (),
+            (Status::Failure, false) => {
+                ctx.as_mut().truncate(n_effects);
+                return Status::Failure;
+            }
+            (status, _) => return status,
+        }
+    }
+
+    if selector {
+        Status::Failure
+    } else {
+        Status::Success
+    }
+}
+
+/// An [ActionProvider] driven by a behaviour tree
+#[derive(Debug, Clone)]
+pub struct BehaviourTree {
+    root: Node,
+    running: Vec<String>,
+}
+
+impl BehaviourTree {
+    pub fn new(root: Node) -> Self {
+        Self {
+            root,
+            running: Vec::new(),
+        }
+    }
+}
+
+impl ActionProvider for BehaviourTree {
+    fn retain(&self) -> bool {
+        true
+    }
+
+    fn available_actions(&mut self, entity: Entity, state: &State<'_>) -> Option<Vec<Action>> {
+        if state.mapset.is_empty() {
+            return None;
+        }
+
+        let mut ctx = Ctx {
+            entity,
+            state,
+            effects: Vec::new(),
+        };
+        let mut trail = Vec::new();
+        let status = self.root.tick(&mut ctx, &mut trail);
+        self.running = trail;
+
+        let mut actions = ctx.effects;
+        if let Status::Running(action) = status {
+            actions.push(action);
+        }
+
+        if actions.is_empty() {
+            None
+        } else {
+            Some(vec![Action::chain(actions)])
+        }
+    }
+
+    fn running_node(&self) -> Option<String> {
+        if self.running.is_empty() {
+            None
+        } else {
+            Some(self.running.join(" > "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stand ins for tree nodes that don't need access to the game state
+    enum Scripted {
+        Bork,
+        Fail,
+    }
+
+    fn tick(node: &mut Scripted, effects: &mut Vec<Action>) -> Status {
+        match node {
+            Scripted::Bork => {
+                effects.push(Action::from(|_: &mut State<'_>| Ok(())));
+                Status::Success
+            }
+            Scripted::Fail => Status::Failure,
+        }
+    }
+
+    #[test]
+    fn effects_from_failed_branches_are_discarded() {
+        // sequence > bork > <failing condition>
+        let mut effects = Vec::new();
+        let mut children = [Scripted::Bork, Scripted::Fail];
+        let status = tick_children(&mut children, &mut effects, false, tick);
+        assert!(matches!(status, Status::Failure));
+        assert!(effects.is_empty());
+
+        // a selector keeps the effects of the child it settles on
+        let mut children = [Scripted::Fail, Scripted::Bork, Scripted::Bork];
+        let status = tick_children(&mut children, &mut effects, true, tick);
+        assert!(matches!(status, Status::Success));
+        assert_eq!(effects.len(), 1);
+    }
+}
@@ -0,0 +1,462 @@
+//! The conditions and leaf actions available to behaviour trees
+use crate::{
+    Pos,
+    action::{Action, ActionProvider},
+    actor::{Actor, FollowPath, Stats},
+    ai::{Ctx, Memory, Sighting, Status, follow::FollowLeader, perception::can_perceive},
+    faction::{Faction, Relationship},
+    map::{
+        MapId,
+        desire_maps::{Desire, DesireMap},
+    },
+    mob::{BarkEvent, Barks, Mob, RandomMoveAI, Resident},
+    state::State,
+};
+use hecs::Entity;
+use rand::seq::IndexedRandom;
+use sdl2::rect::Rect;
+
+/// How far from the last known position of the player a searching mob will wander
+const SEARCH_RADIUS: f32 = 3.0;
+/// The number of turns that residents spend at work before heading home and vice versa
+const SHIFT_LENGTH: usize = 300;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// The player is within our line of sight
+    CanSeePlayer,
+    /// There is something we consider to be a threat within our line of sight
+    SeesThreat,
+    /// Current hp is below the given fraction of max hp
+    Hurt(f32),
+    /// Another member of our faction is within the given range
+    NearAlly(u32),
+    /// Our faction has the given relationship with the player
+    Attitude(Relationship),
+    /// The player is within the given number of steps
+    PlayerWithin(i32),
+    /// The player has moved closer since the last time this was checked
+    PlayerApproaching { last_dist: i32 },
+    /// We have seen the player recently enough to still remember where they were
+    RemembersPlayer,
+    /// We have heard something recently that we have not yet investigated
+    HeardNoise,
+}
+
+impl Condition {
+    pub fn label(&self) -> String {
+        match self {
+            Self::CanSeePlayer => "can_see_player".to_string(),
+            Self::SeesThreat => "sees_threat".to_string(),
+            Self::Hurt(perc) => format!("hurt {perc}"),
+            Self::NearAlly(range) => format!("near_ally {range}"),
+            Self::Attitude(rel) => format!("attitude {}", format!("{rel:?}").to_lowercase()),
+            Self::PlayerWithin(steps) => format!("player_within {steps}"),
+            Self::PlayerApproaching { .. } => "player_approaching".to_string(),
+            Self::RemembersPlayer => "remembers_player".to_string(),
+            Self::HeardNoise => "heard_noise".to_string(),
+        }
+    }
+
+    pub fn check(&mut self, entity: Entity, state: &State<'_>) -> bool {
+        self.try_check(entity, state).unwrap_or(false)
+    }
+
+    fn try_check(&mut self, entity: Entity, state: &State<'_>) -> Option<bool> {
+        let pos = *state.world.get::<&Pos>(entity).ok()?;
+
+        let res = match self {
+            Self::CanSeePlayer => can_perceive(entity, state.e_player, state),
+
+            Self::SeesThreat => !visible_threats(entity, state).is_empty(),
+
+            Self::Hurt(perc) => {
+                let stats = state.world.get::<&Stats>(entity).ok()?;
+                (stats.hp as f32) < *perc * stats.max_hp as f32
+            }
+
+            Self::NearAlly(range) => {
+                let faction = state.world.get::<&Faction>(entity).ok()?;
+                let map_id = state.mapset.current().id;
+                state
+                    .world
+                    .query::<(&Pos, &Faction, Option<&MapId>)>()
+                    .with::<&Mob>()
+                    .iter()
+                    .any(|(e, (p, f, id))| {
+                        e != entity
+                            && f.0 == faction.0
+                            && id.is_none_or(|id| *id == map_id)
+                            && pos.fdist(*p) <= *range as f32
+                    })
+            }
+
+            Self::Attitude(rel) => state.relationship(entity, state.e_player) == *rel,
+
+            Self::PlayerWithin(steps) => player_dist(pos, state)? <= *steps,
+
+            Self::PlayerApproaching { last_dist } => {
+                let current = player_dist(pos, state)?;
+                let approaching = current < *last_dist;
+                *last_dist = current;
+                approaching
+            }
+
+            Self::RemembersPlayer => {
+                let memory = state.world.get::<&Memory>(entity).ok()?;
+                memory.last_seen(state.e_player).is_some()
+            }
+
+            Self::HeardNoise => {
+                let memory = state.world.get::<&Memory>(entity).ok()?;
+                memory.last_heard().is_some()
+            }
+        };
+
+        Some(res)
+    }
+}
+
+/// What a [Leaf::Flee] should be running from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FleeFrom {
+    Player,
+    Threats,
+}
+
+#[derive(Debug, Clone)]
+pub enum Leaf {
+    /// Potter around at random
+    Wander,
+    /// Move towards the player
+    Approach,
+    /// Attack the player if they are adjacent
+    Attack,
+    /// Try to stay a fixed number of steps away from the player
+    KeepAt(i32),
+    /// Run away
+    Flee(FleeFrom),
+    /// Path to the player using A* and follow that path to its end
+    FollowPath(Option<FollowPath>),
+    /// Path to where the player was last seen and then look around there for the given number
+    /// of turns before forgetting about them.
+    Search {
+        turns: u32,
+        progress: SearchProgress,
+    },
+    /// As [Leaf::Search] but for the last sound that we heard
+    Investigate {
+        turns: u32,
+        progress: SearchProgress,
+    },
+    /// Call out the given line or one of the mob's barks if no line is given.
+    ///
+    /// Barking does not use up the turn.
+    Bork(Option<String>),
+    /// Keep up with our leader if we are a follower, holding position while within our leash or
+    /// when told to wait.
+    Follow(FollowLeader),
+    /// Head to where a [Resident] should be at this time of day: work and home in alternating
+    /// shifts.
+    Commute(Option<FollowPath>),
+}
+
+impl Leaf {
+    pub fn label(&self) -> String {
+        match self {
+            Self::Wander => "wander".to_string(),
+            Self::Approach => "approach".to_string(),
+            Self::Attack => "attack".to_string(),
+            Self::KeepAt(steps) => format!("keep_at {steps}"),
+            Self::Flee(FleeFrom::Player) => "flee player".to_string(),
+            Self::Flee(FleeFrom::Threats) => "flee threats".to_string(),
+            Self::FollowPath(_) => "follow_path".to_string(),
+            Self::Search { turns, .. } => format!("search {turns}"),
+            Self::Investigate { turns, .. } => format!("investigate {turns}"),
+            Self::Bork(Some(msg)) => format!("bork {msg}"),
+            Self::Bork(None) => "bork".to_string(),
+            Self::Follow(_) => "follow".to_string(),
+            Self::Commute(_) => "commute".to_string(),
+        }
+    }
+
+    pub fn run(&mut self, ctx: &mut Ctx<'_, '_>) -> Status {
+        match self.try_run(ctx) {
+            Some(status) => status,
+            None => Status::Failure,
+        }
+    }
+
+    fn try_run(&mut self, ctx: &mut Ctx<'_, '_>) -> Option<Status> {
+        let (entity, state) = (ctx.entity, ctx.state);
+        let pos = *state.world.get::<&Pos>(entity).ok()?;
+
+        let action = match self {
+            Self::Wander => RandomMoveAI.available_actions(entity, state)?.remove(0),
+
+            Self::Approach => desire_move(
+                entity,
+                pos,
+                &[Desire::Towards(DesireMap::Player, 1.0)],
+                state,
+            )?,
+
+            Self::Attack => {
+                let target = state.e_player;
+                let player_pos = *state.world.get::<&Pos>(target).ok()?;
+                let map = state.mapset.current();
+                if !map.neighbouring_tiles(pos).any(|p| p == player_pos) {
+                    return None;
+                }
+
+                Action::from(move |state: &mut State<'_>| Actor::attack(entity, target, state))
+            }
+
+            Self::KeepAt(steps) => desire_move(
+                entity,
+                pos,
+                &[Desire::KeepAt(DesireMap::Player, *steps, 1.0)],
+                state,
+            )?,
+
+            Self::Flee(FleeFrom::Player) => desire_move(
+                entity,
+                pos,
+                &[Desire::Towards(DesireMap::FleePlayer, 1.0)],
+                state,
+            )?,
+
+            Self::Flee(FleeFrom::Threats) => {
+                let threats = visible_threats(entity, state);
+                let mut desires = Vec::new();
+                for (e, faction) in threats.iter() {
+                    let m = if *e == state.e_player {
+                        DesireMap::FleePlayer
+                    } else {
+                        DesireMap::FleeFaction(faction)
+                    };
+                    let desire = Desire::Towards(m, 1.0);
+
+                    if !desires.contains(&desire) {
+                        desires.push(desire);
+                    }
+                }
+
+                desire_move(entity, pos, &desires, state)?
+            }
+
+            Self::FollowPath(path) => {
+                let player_pos = *state.world.get::<&Pos>(state.e_player).ok()?;
+                travel_to(entity, pos, player_pos, path, state)?
+            }
+
+            Self::Search { turns, progress } => {
+                let sighting = state
+                    .world
+                    .get::<&Memory>(entity)
+                    .ok()?
+                    .last_seen(state.e_player);
+
+                match progress.step(entity, pos, sighting?, *turns, state) {
+                    Some(status) => return Some(status),
+                    None => {
+                        let mut memory = state.world.get::<&mut Memory>(entity).ok()?;
+                        memory.forget(state.e_player);
+                        return None; // give up
+                    }
+                }
+            }
+
+            Self::Investigate { turns, progress } => {
+                let heard = state.world.get::<&Memory>(entity).ok()?.last_heard();
+
+                match progress.step(entity, pos, heard?, *turns, state) {
+                    Some(status) => return Some(status),
+                    None => {
+                        state.world.get::<&mut Memory>(entity).ok()?.forget_heard();
+                        return None; // give up
+                    }
+                }
+            }
+
+            Self::Commute(path) => {
+                let resident = *state.world.get::<&Resident>(entity).ok()?;
+                let building = if (state.tick / SHIFT_LENGTH).is_multiple_of(2) {
+                    resident.work
+                } else {
+                    resident.home
+                };
+                if building.contains_point((pos.x, pos.y)) {
+                    *path = None;
+                    return Some(Status::Success);
+                }
+
+                let target = open_cell_in(building, state)?;
+                if path.as_ref().is_some_and(|fp| fp.target() != Some(target)) {
+                    *path = None;
+                }
+                travel_to(entity, pos, target, path, state)?
+            }
+
+            Self::Follow(follow) => match follow.step(entity, state)? {
+                Some(action) => action,
+                None => return Some(Status::Success),
+            },
+
+            Self::Bork(msg) => {
+                let msg = msg.clone();
+                ctx.effects.push(Action::from(move |state: &mut State<'_>| {
+                    match msg.clone() {
+                        Some(msg) => Barks::call_out(entity, pos, msg, state),
+                        None => Barks::trigger(entity, BarkEvent::Idle, state),
+                    }
+                }));
+
+                return Some(Status::Success);
+            }
+        };
+
+        if matches!(self, Self::Flee(_)) {
+            ctx.effects.push(Action::from(move |state: &mut State<'_>| {
+                Barks::trigger(entity, BarkEvent::Flee, state)
+            }));
+        }
+
+        Some(Status::Running(action))
+    }
+}
+
+/// How far a [Leaf::Search] or [Leaf::Investigate] has got in looking around a remembered spot
+#[derive(Debug, Clone, Default)]
+pub struct SearchProgress {
+    target: Option<Sighting>,
+    path: Option<FollowPath>,
+    searched: u32,
+}
+
+impl SearchProgress {
+    /// Head to the target position and then look around it for the given number of turns.
+    ///
+    /// Returns `None` once we have searched for long enough and should give up.
+    fn step(
+        &mut self,
+        entity: Entity,
+        pos: Pos,
+        target: Sighting,
+        turns: u32,
+        state: &State<'_>,
+    ) -> Option<Status> {
+        if self.target != Some(target) {
+            *self = Self {
+                target: Some(target),
+                ..Default::default()
+            };
+        }
+
+        if self.searched == 0
+            && pos != target.pos
+            && let Some(action) = travel_to(entity, pos, target.pos, &mut self.path, state)
+        {
+            return Some(Status::Running(action));
+        }
+
+        self.searched += 1;
+        if self.searched > turns {
+            *self = Self::default();
+            return None;
+        }
+
+        match search_step(entity, pos, target.pos, state) {
+            Some(action) => Some(Status::Running(action)),
+            None => Some(Status::Success),
+        }
+    }
+}
+
+/// Take the next step along an A* path to `to`, (re)computing the path if needed.
+///
+/// Returns `None` if there is no path or the next step is occupied.
+fn travel_to(
+    entity: Entity,
+    from: Pos,
+    to: Pos,
+    path: &mut Option<FollowPath>,
+    state: &State<'_>,
+) -> Option<Action> {
+    if path.as_ref().is_none_or(|fp| !fp.retain()) {
+        *path = FollowPath::try_new_a_star(from, to, state);
+    }
+
+    let fp = path.as_mut()?;
+    if fp.next_step().and_then(|p| state.actor_at(p)).is_some() {
+        *path = None;
+        return None;
+    }
+
+    fp.available_actions(entity, state)?.pop()
+}
+
+/// The open cell closest to the centre of the given building
+fn open_cell_in(r: Rect, state: &State<'_>) -> Option<Pos> {
+    let map = state.mapset.current();
+    let centre = Pos::new(r.center().x, r.center().y);
+
+    (r.left()..r.right())
+        .flat_map(|x| (r.top()..r.bottom()).map(move |y| Pos::new(x, y)))
+        .filter(|&p| map.contains_pos(p) && !map.tile_at(p).blocks_movement())
+        .min_by_key(|&p| (p.x - centre.x).pow(2) + (p.y - centre.y).pow(2))
+}
+
+/// Move to a random free neighbouring cell that stays close to `around`
+fn search_step(entity: Entity, pos: Pos, around: Pos, state: &State<'_>) -> Option<Action> {
+    let map = state.mapset.current();
+    let candidates: Vec<Pos> = map
+        .neighbouring_tiles(pos)
+        .filter(|&p| {
+            !map.tile_at(p).blocks_movement()
+                && p.fdist(around) <= SEARCH_RADIUS
+                && state.actor_at(p).is_none()
+        })
+        .collect();
+    let p = *candidates.choose(&mut rand::rng())?;
+
+    Some(Actor::step_to(entity, p))
+}
+
+/// Number of steps between the given position and the player
+fn player_dist(pos: Pos, state: &State<'_>) -> Option<i32> {
+    let dmaps = state.mapset.current().desire_maps.as_ref()?;
+
+    dmaps.value_at(DesireMap::Player, pos)
+}
+
+/// Entities within line of sight that we consider to be a threat along with their faction
+fn visible_threats(entity: Entity, state: &State<'_>) -> Vec<(Entity, String)> {
+    let map_id = state.mapset.current().id;
+
+    state
+        .world
+        .query::<(&Faction, Option<&MapId>)>()
+        .iter()
+        .filter(|(e, (_, id))| {
+            *e != entity
+                && id.is_none_or(|id| *id == map_id)
+                && state.relationship(entity, *e).is_threat()
+                && can_perceive(entity, *e, state)
+        })
+        .map(|(e, (f, _))| (e, f.0.clone()))
+        .collect()
+}
+
+/// Move to the neighbouring cell that best satisfies the given desires
+fn desire_move(
+    entity: Entity,
+    pos: Pos,
+    desires: &[Desire<'_>],
+    state: &State<'_>,
+) -> Option<Action> {
+    let map = state.mapset.current();
+    let p = map.desire_maps.as_ref()?.best_move(pos, desires, map)?;
+
+    Some(Actor::step_to(entity, p))
+}
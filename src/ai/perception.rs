@@ -0,0 +1,150 @@
+//! Light-aware perception: whether one entity can spot another.
+//!
+//! An observer's [FovRange] is the distance at which it can spot a brightly lit target in the
+//! open. That range shrinks in proportion to how dark the target's cell is, how small the target
+//! is (its [Opacity]) and how much cover there is between the two of them.
+use crate::{
+    Pos,
+    map::{
+        Map, MapId,
+        fov::{FovRange, LightSource, Opacity},
+    },
+    state::State,
+};
+use hecs::Entity;
+
+/// Light level (as a fraction of full brightness) at or above which a cell counts as fully lit
+const FULL_LIGHT: f32 = 0.5;
+/// Minimum light level for any cell, so that nothing is completely invisible in the dark
+const AMBIENT_LIGHT: f32 = 0.15;
+/// Targets within this distance are always noticed if there is a clear line of sight
+const TOUCH_RANGE: f32 = 1.5;
+
+/// How visible an entity is to observers from 0.0 (unseeable) to 1.0 (brightly lit in the open).
+///
+/// This ignores distance and cover which depend on the observer.
+pub fn visibility(target: Entity, state: &State<'_>) -> f32 {
+    let pos = match state.world.get::<&Pos>(target) {
+        Ok(pos) => *pos,
+        Err(_) => return 0.0,
+    };
+    let size = state
+        .world
+        .get::<&Opacity>(target)
+        .map(|o| o.0)
+        .unwrap_or(1.0);
+
+    light_level(pos, state) * (0.5 + 0.5 * size)
+}
+
+/// Whether `observer` is currently able to spot `target`
+pub fn can_perceive(observer: Entity, target: Entity, state: &State<'_>) -> bool {
+    let (from, range) = match state.world.query_one::<(&Pos, &FovRange)>(observer) {
+        Ok(mut q) => match q.get() {
+            Some((pos, range)) => (*pos, *range),
+            None => return false,
+        },
+        Err(_) => return false,
+    };
+    let to = match state.world.get::<&Pos>(target) {
+        Ok(pos) => *pos,
+        Err(_) => return false,
+    };
+
+    perceives_at(from, range, to, visibility(target, state), state)
+}
+
+/// Whether an observer at `from` with the given range can spot something at `to` with the given
+/// [visibility].
+pub fn perceives_at(
+    from: Pos,
+    range: FovRange,
+    to: Pos,
+    visibility: f32,
+    state: &State<'_>,
+) -> bool {
+    if state.mapset.is_empty() {
+        return false;
+    }
+
+    perceives_in(state.mapset.current(), from, range, to, visibility)
+}
+
+/// As [perceives_at] but for a specific map rather than the current one
+fn perceives_in(map: &Map, from: Pos, range: FovRange, to: Pos, visibility: f32) -> bool {
+    if !range.fast_has_los_in(map, from, to) {
+        return false;
+    }
+
+    let d = from.fdist(to);
+    if d <= TOUCH_RANGE {
+        return true;
+    }
+
+    let cover: f32 = map
+        .line_between(from, to)
+        .into_iter()
+        .filter(|&p| p != from && p != to)
+        .map(|p| map.tile_at(p).opacity)
+        .sum();
+
+    d <= range.0 as f32 * visibility * (1.0 - cover.min(1.0))
+}
+
+/// The light level at a position on the current map from [AMBIENT_LIGHT] to 1.0. This is worked
+/// out from the light sources on the map rather than the player's light map so that it doesn't
+/// depend on what the player is able to see.
+fn light_level(p: Pos, state: &State<'_>) -> f32 {
+    if state.mapset.is_empty() {
+        return AMBIENT_LIGHT;
+    }
+    let map = state.mapset.current();
+
+    let mut sources = state.world.query::<(&Pos, &LightSource, Option<&MapId>)>();
+    let sources = sources
+        .iter()
+        .filter(|(_, (_, _, id))| id.is_none_or(|id| *id == map.id))
+        .map(|(_, (pos, source, _))| (*pos, *source));
+
+    light_level_in(map, p, sources)
+}
+
+fn light_level_in(map: &Map, p: Pos, sources: impl Iterator<Item = (Pos, LightSource)>) -> f32 {
+    let brightness = sources
+        .map(|(from, source)| source.brightness_at(map, from, p))
+        .fold(0.0, f32::max);
+
+    (brightness / FULL_LIGHT).clamp(AMBIENT_LIGHT, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{fov::LightMap, map_tile::Terrain};
+    use sdl2::{pixels::Color, rect::Rect};
+    use std::collections::HashMap;
+
+    #[test]
+    fn lit_targets_outside_the_players_view_are_perceived() {
+        let terrain = Terrain::for_tests([("wall", None), ("floor", Some(1))]);
+        let mut map = Map::new(30, 10, terrain, Color::BLACK, Color::BLACK);
+        map.carve_rect(Rect::new(1, 1, 28, 8), 1);
+        // the player can't see any of the map
+        map.light_map = Some(LightMap {
+            points: HashMap::new(),
+            c_hidden: Color::BLACK,
+        });
+
+        let lamp = LightSource {
+            range: 5,
+            color: Color::WHITE,
+        };
+        let (observer, range) = (Pos::new(5, 5), FovRange(15));
+
+        for (target, lit) in [(Pos::new(18, 5), true), (Pos::new(10, 2), false)] {
+            let sources = [(Pos::new(20, 6), lamp)].into_iter();
+            let vis = light_level_in(&map, target, sources);
+            assert_eq!(perceives_in(&map, observer, range, target, vis), lit);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{
+    dialogue::{Choice, Condition, DialogueNode, DialogueTree, END, Effect},
+    player::{Disposition, VOCATIONS},
+};
+use anyhow::{Context, anyhow, bail};
+use std::{collections::HashMap, fs, path::Path};
+
+/// Parse dialogue trees keyed by name.
+///
+/// See data/dialogue for details of the expected format.
+pub fn parse_dialogues(path: impl AsRef<Path>) -> anyhow::Result<HashMap<String, DialogueTree>> {
+    let raw = fs::read_to_string(path).context("reading dialogue")?;
+
+    parse_dialogues_str(&raw)
+}
+
+/// A tree that is still being parsed along with the line numbers needed for validating it
+#[derive(Default)]
+struct Partial {
+    start: usize,
+    name: String,
+    tree: DialogueTree,
+    /// (line, target) for each choice so that missing nodes can be reported
+    targets: Vec<(usize, String)>,
+}
+
+impl Partial {
+    fn node(&mut self, n: usize) -> anyhow::Result<&mut DialogueNode> {
+        self.tree
+            .nodes
+            .last_mut()
+            .map(|(_, node)| node)
+            .ok_or_else(|| anyhow!("line {n}: expected a node declaration"))
+    }
+
+    fn finish(self, trees: &mut HashMap<String, DialogueTree>) -> anyhow::Result<()> {
+        if self.tree.nodes.is_empty() {
+            bail!("line {}: dialogue {:?} has no nodes", self.start, self.name);
+        }
+        for (n, target) in self.targets.iter() {
+            if target != END && !self.tree.nodes.contains_key(target) {
+                bail!("line {n}: unknown dialogue node: {target:?}");
+            }
+        }
+        trees.insert(self.name, self.tree);
+
+        Ok(())
+    }
+}
+
+fn parse_dialogues_str(raw: &str) -> anyhow::Result<HashMap<String, DialogueTree>> {
+    let mut trees = HashMap::new();
+    let mut current: Option<Partial> = None;
+
+    for (i, line) in raw.lines().enumerate() {
+        let n = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| anyhow!("line {n}: invalid dialogue header: {line:?}"))?
+                .trim();
+            if trees.contains_key(name) || current.as_ref().is_some_and(|p| p.name == name) {
+                bail!("line {n}: duplicate dialogue: {name:?}");
+            }
+            if let Some(partial) = current.take() {
+                partial.finish(&mut trees)?;
+            }
+            current = Some(Partial {
+                start: n,
+                name: name.to_string(),
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let partial = current
+            .as_mut()
+            .ok_or_else(|| anyhow!("line {n}: expected a dialogue header"))?;
+        let (key, val) = line
+            .split_once(char::is_whitespace)
+            .map(|(k, v)| (k, v.trim()))
+            .ok_or_else(|| anyhow!("line {n}: missing value for {line:?}"))?;
+
+        match key {
+            "node" => {
+                if val == END || partial.tree.nodes.contains_key(val) {
+                    bail!("line {n}: invalid or duplicate node id: {val:?}");
+                }
+                partial
+                    .tree
+                    .nodes
+                    .insert(val.to_string(), DialogueNode::default());
+            }
+
+            "say" => partial.node(n)?.text.push(val.to_string()),
+
+            "choice" => {
+                let (text, target) = val
+                    .rsplit_once("->")
+                    .map(|(t, target)| (t.trim(), target.trim()))
+                    .ok_or_else(|| anyhow!("line {n}: expected 'choice TEXT -> NODE'"))?;
+                partial.node(n)?.choices.push(Choice {
+                    text: text.to_string(),
+                    target: target.to_string(),
+                    conditions: Vec::new(),
+                    effects: Vec::new(),
+                });
+                partial.targets.push((n, target.to_string()));
+            }
+
+            "if" => {
+                let cond = parse_condition(val).with_context(|| format!("line {n}"))?;
+                partial
+                    .node(n)?
+                    .choices
+                    .last_mut()
+                    .ok_or_else(|| anyhow!("line {n}: conditions must follow a choice"))?
+                    .conditions
+                    .push(cond);
+            }
+
+            // effects apply to the last choice or to the node itself if there are no choices yet
+            "do" => {
+                let effect = parse_effect(val).with_context(|| format!("line {n}"))?;
+                let node = partial.node(n)?;
+                match node.choices.last_mut() {
+                    Some(choice) => choice.effects.push(effect),
+                    None => node.effects.push(effect),
+                }
+            }
+
+            _ => bail!("line {n}: unknown dialogue property: {key:?}"),
+        }
+    }
+
+    if let Some(partial) = current.take() {
+        partial.finish(&mut trees)?;
+    }
+
+    Ok(trees)
+}
+
+fn parse_condition(s: &str) -> anyhow::Result<Condition> {
+    let (kind, arg) = s
+        .split_once(char::is_whitespace)
+        .map(|(k, v)| (k, v.trim()))
+        .ok_or_else(|| anyhow!("invalid condition: {s:?}"))?;
+
+    let cond = match kind {
+        "not" => Condition::Not(Box::new(parse_condition(arg)?)),
+        "disposition" => Condition::Disposition(
+            Disposition::from_name(arg).ok_or_else(|| anyhow!("unknown disposition: {arg:?}"))?,
+        ),
+        "vocation" => {
+            if !VOCATIONS.contains(&arg) {
+                bail!("unknown vocation: {arg:?}");
+            }
+            Condition::Vocation(arg.to_string())
+        }
+        "has" => Condition::HasItem(arg.to_string()),
+        "flag" => Condition::Flag(arg.to_string()),
+        _ => bail!("unknown condition: {kind:?}"),
+    };
+
+    Ok(cond)
+}
+
+fn parse_effect(s: &str) -> anyhow::Result<Effect> {
+    let words: Vec<&str> = s.split_whitespace().collect();
+
+    let effect = match words.as_slice() {
+        ["give", item] => Effect::GiveItem(item.to_string()),
+        ["take", item] => Effect::TakeItem(item.to_string()),
+        ["flag", flag] => Effect::SetFlag(flag.to_string()),
+        ["recruit"] => Effect::Recruit,
+        ["relationship", delta] => Effect::Relationship(None, parse_delta(delta)?),
+        ["relationship", faction, delta] => {
+            Effect::Relationship(Some(faction.to_string()), parse_delta(delta)?)
+        }
+        _ => bail!("invalid effect: {s:?}"),
+    };
+
+    Ok(effect)
+}
+
+fn parse_delta(s: &str) -> anyhow::Result<i32> {
+    s.trim_start_matches('+')
+        .parse()
+        .with_context(|| format!("invalid relationship change: {s:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_works() {
+        let trees = parse_dialogues("data/dialogue").unwrap();
+        let snoot = &trees["snoot"];
+
+        assert!(!snoot.nodes[0].choices.is_empty());
+    }
+
+    #[test]
+    fn unknown_targets_report_line_numbers() {
+        let raw = "[pixie]\nnode start\nsay hello\nchoice Bye -> end\nchoice Hmm -> missing\n";
+        let err = parse_dialogues_str(raw).unwrap_err();
+
+        assert!(err.to_string().starts_with("line 5"), "{err}");
+    }
+}
@@ -0,0 +1,77 @@
+use crate::faction::{Relationship, Relationships};
+use anyhow::{Context, anyhow, bail};
+use std::{fs, path::Path};
+
+/// Parse faction declarations and the relationships between them.
+///
+/// See data/factions for details of the expected format.
+pub fn parse_factions(path: impl AsRef<Path>) -> anyhow::Result<Relationships> {
+    let raw = fs::read_to_string(path).context("reading factions")?;
+
+    parse_factions_str(&raw)
+}
+
+fn parse_factions_str(raw: &str) -> anyhow::Result<Relationships> {
+    let mut rels = Relationships::new();
+
+    for (i, line) in raw.lines().enumerate() {
+        let n = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["faction", name] => rels.add_faction(*name, false),
+            ["faction", name, "faerie"] => rels.add_faction(*name, true),
+            ["faction", ..] => bail!("line {n}: invalid faction declaration: {line:?}"),
+
+            [from, to, rel] => {
+                for name in [from, to] {
+                    if !rels.contains(name) {
+                        bail!("line {n}: unknown faction: {name:?}");
+                    }
+                }
+                let rel = parse_relationship(rel)
+                    .ok_or_else(|| anyhow!("line {n}: unknown relationship: {rel:?}"))?;
+                rels.set(from, to, rel);
+            }
+
+            _ => bail!("line {n}: invalid line: {line:?}"),
+        }
+    }
+
+    Ok(rels)
+}
+
+pub(super) fn parse_relationship(s: &str) -> Option<Relationship> {
+    match s {
+        "hostile" => Some(Relationship::Hostile),
+        "wary" => Some(Relationship::Wary),
+        "neutral" => Some(Relationship::Neutral),
+        "friendly" => Some(Relationship::Friendly),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_works() {
+        let rels = parse_factions("data/factions").unwrap();
+
+        assert!(rels.is_faerie("pixies"));
+        assert_eq!(rels.get("snoots", "pixies"), Relationship::Wary);
+    }
+
+    #[test]
+    fn unknown_factions_are_an_error() {
+        let raw = "faction pixies\n\npixies goblins hostile\n";
+        let err = parse_factions_str(raw).unwrap_err();
+
+        assert!(err.to_string().starts_with("line 3"), "{err}");
+    }
+}
@@ -0,0 +1,394 @@
+use crate::{
+    actor::Stats,
+    ai::{Condition, FleeFrom, Leaf, Node, SearchProgress, follow::FollowLeader},
+    data_files::factions::parse_relationship,
+    mob::{AiType, BarkEvent, MobSpec},
+    ui::ColorPalette,
+};
+use anyhow::{Context, anyhow, bail};
+use indexmap::IndexMap;
+use std::{fs, path::Path, str::FromStr};
+
+/// Parse a set of mob definitions keyed by mob name, looking up any named colours in the given
+/// palette.
+///
+/// See data/mobs for details of the expected format.
+pub fn parse_mob_defs(
+    path: impl AsRef<Path>,
+    palette: &ColorPalette,
+) -> anyhow::Result<IndexMap<String, MobSpec>> {
+    let raw = fs::read_to_string(path).context("reading mob defs")?;
+
+    parse_mob_defs_str(&raw, palette)
+}
+
+fn parse_mob_defs_str(
+    raw: &str,
+    palette: &ColorPalette,
+) -> anyhow::Result<IndexMap<String, MobSpec>> {
+    let mut specs = IndexMap::new();
+    let mut current: Option<(usize, MobSpec)> = None;
+    let mut tree: Option<(usize, Vec<TreeLine<'_>>)> = None;
+
+    for (i, line) in raw.lines().enumerate() {
+        let n = i + 1;
+        let indent = line.len() - line.trim_start().len();
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // indented lines following "ai tree" make up the body of the tree
+        if let Some((_, lines)) = tree.as_mut()
+            && indent > 0
+        {
+            lines.push((n, indent, line));
+            continue;
+        }
+
+        if let Some((start, lines)) = tree.take() {
+            let (_, spec) = current.as_mut().unwrap();
+            spec.ai = AiType::Tree(parse_tree(start, &lines)?);
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| anyhow!("line {n}: invalid mob header: {line:?}"))?
+                .trim();
+            if specs.contains_key(name) {
+                bail!("line {n}: duplicate mob definition: {name:?}");
+            }
+            if let Some((start, spec)) = current.take() {
+                insert_checked(start, spec, &mut specs)?;
+            }
+            current = Some((n, MobSpec::new(name)));
+            continue;
+        }
+
+        let (_, spec) = current
+            .as_mut()
+            .ok_or_else(|| anyhow!("line {n}: property given before any mob header"))?;
+
+        if line.split_whitespace().eq(["ai", "tree"]) {
+            tree = Some((n, Vec::new()));
+            continue;
+        }
+
+        parse_property(line, spec, palette).with_context(|| format!("line {n}"))?;
+    }
+
+    if let Some((start, lines)) = tree.take() {
+        let (_, spec) = current.as_mut().unwrap();
+        spec.ai = AiType::Tree(parse_tree(start, &lines)?);
+    }
+
+    if let Some((start, spec)) = current.take() {
+        insert_checked(start, spec, &mut specs)?;
+    }
+
+    if specs.is_empty() {
+        bail!("no mobs defined")
+    }
+
+    Ok(specs)
+}
+
+fn insert_checked(
+    start: usize,
+    spec: MobSpec,
+    specs: &mut IndexMap<String, MobSpec>,
+) -> anyhow::Result<()> {
+    if spec.ident.is_empty() {
+        bail!("line {start}: mob {:?} has no glyph", spec.name);
+    }
+    specs.insert(spec.name.clone(), spec);
+
+    Ok(())
+}
+
+fn parse_property(line: &str, spec: &mut MobSpec, palette: &ColorPalette) -> anyhow::Result<()> {
+    let (key, val) = line
+        .split_once(char::is_whitespace)
+        .map(|(k, v)| (k, v.trim()))
+        .ok_or_else(|| anyhow!("missing value for {line:?}"))?;
+
+    match key {
+        "glyph" => spec.ident = val.to_string(),
+        "color" => spec.color = palette.parse(val)?,
+        "fov" => spec.fov_range = val.parse().context("invalid fov")?,
+        "opacity" => {
+            spec.opacity = val.parse().context("invalid opacity")?;
+            if !(0.0..=1.0).contains(&spec.opacity) {
+                bail!("opacity must be between 0.0 and 1.0");
+            }
+        }
+        "faction" => spec.faction = Some(val.to_string()),
+        "ai" => spec.ai = parse_ai(val)?,
+        "stats" => spec.stats = parse_stats(val)?,
+        "bark" => {
+            let (event, line) = val
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| anyhow!("expected 'bark EVENT TEXT'"))?;
+            let event = BarkEvent::from_name(event)
+                .ok_or_else(|| anyhow!("unknown bark event: {event:?}"))?;
+            spec.barks
+                .entry(event)
+                .or_default()
+                .push(line.trim().to_string());
+        }
+        "dialogue" => spec.dialogue = Some(val.to_string()),
+        "spawn" => {
+            spec.spawn_anywhere = match val {
+                "anywhere" => true,
+                "named" => false,
+                _ => bail!("spawn must be either anywhere or named, got {val:?}"),
+            }
+        }
+        "follow" => {
+            for kv in params(val) {
+                match kv? {
+                    ("leash", v) => spec.leash = Some(v.parse().context("invalid leash")?),
+                    (k, _) => bail!("unknown follow parameter: {k:?}"),
+                }
+            }
+            if spec.leash.is_none() {
+                bail!("follow requires a leash");
+            }
+        }
+        _ => bail!("unknown mob property: {key:?}"),
+    }
+
+    Ok(())
+}
+
+/// Parse whitespace separated key=value pairs
+fn params(s: &str) -> impl Iterator<Item = anyhow::Result<(&str, &str)>> {
+    s.split_whitespace().map(|kv| {
+        kv.split_once('=')
+            .ok_or_else(|| anyhow!("expected key=value, got {kv:?}"))
+    })
+}
+
+fn parse_ai(s: &str) -> anyhow::Result<AiType> {
+    let (kind, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+    let ai = match kind {
+        "random" => AiType::Random,
+        _ => bail!("unknown ai type: {kind:?}"),
+    };
+
+    if let Some(kv) = params(rest).next() {
+        let (k, _) = kv?;
+        bail!("unknown parameter for {kind} ai: {k:?}");
+    }
+
+    Ok(ai)
+}
+
+/// A line within a behaviour tree: (line number, indent, content)
+type TreeLine<'a> = (usize, usize, &'a str);
+
+/// Parse the indented lines following an "ai tree" property into a behaviour tree.
+///
+/// Each line is a single node and the children of a node are the lines directly below it that
+/// are indented further than it is.
+fn parse_tree(start: usize, lines: &[TreeLine<'_>]) -> anyhow::Result<Node> {
+    if lines.is_empty() {
+        bail!("line {start}: empty behaviour tree");
+    }
+
+    let mut i = 0;
+    let root = parse_node(lines, &mut i)?;
+    if let Some((n, _, _)) = lines.get(i) {
+        bail!("line {n}: behaviour trees must have a single root node");
+    }
+
+    Ok(root)
+}
+
+fn parse_node(lines: &[TreeLine<'_>], i: &mut usize) -> anyhow::Result<Node> {
+    let (n, indent, line) = lines[*i];
+    *i += 1;
+
+    let mut children = Vec::new();
+    while *i < lines.len() && lines[*i].1 > indent {
+        children.push(parse_node(lines, i)?);
+    }
+
+    build_node(line, children).with_context(|| format!("line {n}"))
+}
+
+fn build_node(line: &str, mut children: Vec<Node>) -> anyhow::Result<Node> {
+    let (kind, arg) = line
+        .split_once(char::is_whitespace)
+        .map(|(k, v)| (k, v.trim()))
+        .unwrap_or((line, ""));
+
+    match kind {
+        "selector" | "sequence" => {
+            if !arg.is_empty() {
+                bail!("{kind} does not take any arguments");
+            } else if children.is_empty() {
+                bail!("{kind} has no children");
+            }
+
+            return Ok(if kind == "selector" {
+                Node::Selector(children)
+            } else {
+                Node::Sequence(children)
+            });
+        }
+
+        "not" => {
+            let child = if !arg.is_empty() {
+                build_node(arg, children)?
+            } else if children.len() == 1 {
+                children.remove(0)
+            } else {
+                bail!("not requires exactly one child");
+            };
+
+            return Ok(Node::Not(Box::new(child)));
+        }
+
+        _ if !children.is_empty() => bail!("{kind} can not have children"),
+        "can_see_player" | "sees_threat" | "player_approaching" | "remembers_player"
+        | "heard_noise" | "wander" | "approach" | "attack" | "follow_path" | "follow"
+            if !arg.is_empty() =>
+        {
+            bail!("{kind} does not take any arguments")
+        }
+        _ => (),
+    }
+
+    fn parse_arg<T: FromStr>(kind: &str, arg: &str, what: &str) -> anyhow::Result<T>
+    where
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        arg.parse()
+            .with_context(|| format!("{kind} expects {what}, got {arg:?}"))
+    }
+
+    let node = match kind {
+        "can_see_player" => Node::Condition(Condition::CanSeePlayer),
+        "sees_threat" => Node::Condition(Condition::SeesThreat),
+        "hurt" => Node::Condition(Condition::Hurt(
+            arg.parse()
+                .with_context(|| format!("hurt expects a fraction, got {arg:?}"))?,
+        )),
+        "near_ally" => Node::Condition(Condition::NearAlly(parse_arg(kind, arg, "a range")?)),
+        "attitude" => Node::Condition(Condition::Attitude(
+            parse_relationship(arg).ok_or_else(|| anyhow!("unknown relationship: {arg:?}"))?,
+        )),
+        "player_within" => Node::Condition(Condition::PlayerWithin(parse_arg(
+            kind,
+            arg,
+            "a step count",
+        )?)),
+        "player_approaching" => Node::Condition(Condition::PlayerApproaching {
+            last_dist: i32::MAX,
+        }),
+        "remembers_player" => Node::Condition(Condition::RemembersPlayer),
+        "heard_noise" => Node::Condition(Condition::HeardNoise),
+
+        "wander" => Node::Leaf(Leaf::Wander),
+        "approach" => Node::Leaf(Leaf::Approach),
+        "attack" => Node::Leaf(Leaf::Attack),
+        "keep_at" => Node::Leaf(Leaf::KeepAt(parse_arg(kind, arg, "a step count")?)),
+        "flee" => match arg {
+            "player" => Node::Leaf(Leaf::Flee(FleeFrom::Player)),
+            "threats" | "" => Node::Leaf(Leaf::Flee(FleeFrom::Threats)),
+            _ => bail!("can only flee from the player or threats, got {arg:?}"),
+        },
+        "follow_path" => Node::Leaf(Leaf::FollowPath(None)),
+        "follow" => Node::Leaf(Leaf::Follow(FollowLeader::default())),
+        "commute" => Node::Leaf(Leaf::Commute(None)),
+        "search" => Node::Leaf(Leaf::Search {
+            turns: parse_arg(kind, arg, "a number of turns")?,
+            progress: SearchProgress::default(),
+        }),
+        "investigate" => Node::Leaf(Leaf::Investigate {
+            turns: parse_arg(kind, arg, "a number of turns")?,
+            progress: SearchProgress::default(),
+        }),
+        "bork" if arg.is_empty() => Node::Leaf(Leaf::Bork(None)),
+        "bork" => Node::Leaf(Leaf::Bork(Some(arg.to_string()))),
+
+        _ => bail!("unknown behaviour tree node: {kind:?}"),
+    };
+
+    Ok(node)
+}
+
+fn parse_stats(s: &str) -> anyhow::Result<Stats> {
+    let mut stats = Stats::default();
+
+    for kv in params(s) {
+        let (k, v) = kv?;
+        let n: i32 = v
+            .parse()
+            .with_context(|| format!("invalid value for {k}"))?;
+        match k {
+            "hp" => {
+                stats.hp = n;
+                stats.max_hp = n;
+            }
+            "attack" => stats.attack = n,
+            "defence" => stats.defence = n,
+            _ => bail!("unknown stat: {k:?}"),
+        }
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_files::parse_color_palette;
+
+    #[test]
+    fn parse_works() {
+        let specs = parse_mob_defs("data/mobs", &parse_color_palette().unwrap()).unwrap();
+
+        assert!(specs.contains_key("pixie"));
+        assert!(specs.contains_key("snoot"));
+        assert!(specs["pixie"].spawn_anywhere);
+        assert!(!specs["townsfolk"].spawn_anywhere);
+    }
+
+    #[test]
+    fn trees_are_parsed_from_indentation() {
+        let raw = "[pixie]\nglyph pi\nai tree\n  selector\n    sequence\n      not can_see_player\n      wander\n    keep_at 2\nfov 3\n";
+        let specs = parse_mob_defs_str(raw, &ColorPalette::default()).unwrap();
+        let spec = &specs["pixie"];
+
+        assert_eq!(spec.fov_range, 3);
+        match &spec.ai {
+            AiType::Tree(Node::Selector(children)) => {
+                assert_eq!(children.len(), 2);
+                assert_eq!(children[0].label(), "sequence");
+                assert_eq!(children[1].label(), "keep_at 2");
+            }
+            ai => panic!("unexpected ai: {ai:?}"),
+        }
+    }
+
+    #[test]
+    fn errors_report_line_numbers() {
+        let raw = "# comment\n[pixie]\nglyph pi\nfov four\n";
+        let err = parse_mob_defs_str(raw, &ColorPalette::default()).unwrap_err();
+
+        assert!(format!("{err:#}").starts_with("line 4"), "{err:#}");
+
+        let raw = "[pixie]\nglyph pi\ncolor notAColour\n";
+        let err = parse_mob_defs_str(raw, &ColorPalette::default()).unwrap_err();
+
+        assert!(format!("{err:#}").starts_with("line 3"), "{err:#}");
+
+        let raw = "[pixie]\nglyph pi\nai tree\n  search -3\n";
+        let err = parse_mob_defs_str(raw, &ColorPalette::default()).unwrap_err();
+
+        assert!(format!("{err:#}").starts_with("line 4"), "{err:#}");
+    }
+}
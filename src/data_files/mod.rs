@@ -1,7 +1,17 @@
+mod dialogue;
+mod factions;
+mod mobs;
 mod palette;
 mod prefab;
 mod tile_map;
+mod tiles;
+mod turmites;
 
+pub use dialogue::parse_dialogues;
+pub use factions::parse_factions;
+pub use mobs::parse_mob_defs;
 pub use palette::parse_color_palette;
-pub use prefab::parse_cp437_prefab;
+pub use prefab::{parse_cp437_prefab, parse_prefab, parse_prefabs};
 pub use tile_map::{parse_cp437_tileset, parse_tile_map};
+pub use tiles::parse_tile_defs;
+pub use turmites::parse_turmite_rules;
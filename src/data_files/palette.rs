@@ -1,36 +1,35 @@
+use crate::ui::{ColorPalette, parse_hex};
 use anyhow::{Context, anyhow, bail};
-use sdl2::pixels::Color;
 use std::{collections::HashMap, fs};
 
-pub fn parse_color_palette() -> anyhow::Result<HashMap<String, Color>> {
+pub fn parse_color_palette() -> anyhow::Result<ColorPalette> {
     let raw = fs::read_to_string("data/color.palette").context("reading colour.palette")?;
+
+    parse_color_palette_str(&raw)
+}
+
+fn parse_color_palette_str(raw: &str) -> anyhow::Result<ColorPalette> {
     let mut palette = HashMap::new();
 
-    for line in raw.lines() {
+    for (i, line) in raw.lines().enumerate() {
+        let n = i + 1;
         if line.is_empty() || line.starts_with("--") {
             continue;
         }
 
         let (hex, ident) = line
             .split_once(' ')
-            .ok_or_else(|| anyhow!("invalid color def: {line:?}"))?;
-        let hex = hex
-            .strip_prefix('#')
-            .ok_or_else(|| anyhow!("invalid colour hex: {hex:?}"))?;
+            .ok_or_else(|| anyhow!("line {n}: invalid color def: {line:?}"))?;
+        let color = parse_hex(hex).ok_or_else(|| anyhow!("line {n}: invalid color {hex:?}"))?;
 
-        let [_, r, g, b] = match u32::from_str_radix(hex, 16) {
-            Ok(n) => n.to_be_bytes(),
-            Err(e) => bail!("invalid color {hex:?}: {e}"),
-        };
-
-        palette.insert(ident.to_string(), Color::RGB(r, g, b));
+        palette.insert(ident.trim().to_string(), color);
     }
 
     if palette.is_empty() {
         bail!("no colours defined")
     }
 
-    Ok(palette)
+    Ok(ColorPalette::new(palette))
 }
 
 #[cfg(test)]
@@ -39,6 +38,17 @@ mod tests {
 
     #[test]
     fn parse_works() {
-        assert!(parse_color_palette().is_ok());
+        let palette = parse_color_palette().unwrap();
+
+        assert!(palette.get("fadedPurple").is_some());
+        assert!(palette.parse("#9775a6").is_ok());
+        assert!(palette.parse("notAColour").is_err());
+    }
+
+    #[test]
+    fn errors_report_line_numbers() {
+        let err = parse_color_palette_str("-- comment\n#123456 ok\n#12345g bad\n").unwrap_err();
+
+        assert!(format!("{err:#}").starts_with("line 3"), "{err:#}");
     }
 }
@@ -1,15 +1,116 @@
 use crate::{
     Grid,
+    map::prefab::{Prefab, PrefabCell},
     tileset::{Tile, TileSet},
-    ui::palette,
+    ui::ColorPalette,
 };
 use anyhow::{Context, anyhow, bail};
-use sdl2::pixels::Color;
 use std::{collections::HashMap, fs, path::Path};
 
-pub fn parse_cp437_prefab(path: impl AsRef<Path>, ts: &TileSet<'_>) -> anyhow::Result<Grid<Tile>> {
+/// Parse every "*.prefab" file in the given directory into a [Prefab] keyed by file stem.
+pub fn parse_prefabs(dir: impl AsRef<Path>) -> anyhow::Result<HashMap<String, Prefab>> {
+    let mut prefabs = HashMap::new();
+
+    for entry in fs::read_dir(dir).context("reading prefab dir")? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "prefab") {
+            continue;
+        }
+        let prefab = parse_prefab(&path).with_context(|| format!("{}", path.display()))?;
+        prefabs.insert(prefab.name.clone(), prefab);
+    }
+
+    Ok(prefabs)
+}
+
+/// Parse the semantic layer of a prefab: what each character becomes when the prefab is stamped
+/// into a map.
+///
+/// See data/prefabs/README for details of the expected format.
+pub fn parse_prefab(path: impl AsRef<Path>) -> anyhow::Result<Prefab> {
+    let path = path.as_ref();
     let raw = fs::read_to_string(path).context("reading prefab")?;
-    let mut lines = raw.lines().peekable();
+    let name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    parse_prefab_str(name, &raw)
+}
+
+fn parse_prefab_str(name: String, raw: &str) -> anyhow::Result<Prefab> {
+    let mut lines = raw.lines().enumerate().peekable();
+    let mut floor = None;
+    let mut defs = HashMap::from([('@', PrefabCell::Start), ('D', PrefabCell::Mob(None))]);
+
+    // parse defs
+    loop {
+        let (n, line) = match lines.next() {
+            None => bail!("invalid prefab: no map provided"),
+            Some((_, "")) => break,
+            Some((i, line)) => (i + 1, line),
+        };
+        if let Some(terrain) = line.strip_prefix("floor ") {
+            floor = Some(terrain.trim().to_string());
+            continue;
+        }
+
+        let mut chars = line.chars();
+        let ch = chars
+            .next()
+            .ok_or_else(|| anyhow!("line {n}: invalid tile def: {line:?}"))?;
+        if let Some((_, meaning)) = line.split_once("=>") {
+            defs.insert(ch, PrefabCell::from_name(meaning.trim()));
+        } else if !defs.contains_key(&ch) {
+            bail!("line {n}: no map meaning given for {ch:?}");
+        }
+    }
+
+    let mut cells = Grid {
+        cells: Vec::new(),
+        w: 0,
+        h: 0,
+    };
+    for (i, line) in lines {
+        let n = i + 1;
+        if cells.w == 0 {
+            cells.w = line.chars().count();
+        } else if line.chars().count() != cells.w {
+            bail!("line {n}: expected {} characters", cells.w);
+        }
+
+        for ch in line.chars() {
+            let cell = match ch {
+                ' ' => match floor.as_ref() {
+                    Some(terrain) => PrefabCell::Terrain(terrain.clone()),
+                    None => PrefabCell::Keep,
+                },
+                _ => defs
+                    .get(&ch)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("line {n}: no def for {ch:?}"))?,
+            };
+            cells.cells.push(cell);
+        }
+        cells.h += 1;
+    }
+
+    if cells.is_empty() {
+        bail!("invalid prefab: no map provided");
+    }
+
+    Ok(Prefab { name, cells, floor })
+}
+
+/// Parse the display layer of a prefab into tiles from the given tileset, looking up any named
+/// colours in the given palette.
+pub fn parse_cp437_prefab(
+    path: impl AsRef<Path>,
+    ts: &TileSet<'_>,
+    palette: &ColorPalette,
+) -> anyhow::Result<Grid<Tile>> {
+    let raw = fs::read_to_string(path).context("reading prefab")?;
+    let mut lines = raw.lines().enumerate().peekable();
     let mut grid = Grid::default();
 
     let mut defs = HashMap::new();
@@ -17,16 +118,18 @@ pub fn parse_cp437_prefab(path: impl AsRef<Path>, ts: &TileSet<'_>) -> anyhow::R
 
     // parse defs
     loop {
-        let line = match lines.next() {
+        let (n, line) = match lines.next() {
             None => bail!("invalid prefab: no map provided"),
-            Some("") => break,
-            Some(line) => line,
+            Some((_, "")) => break,
+            Some((_, line)) if line.starts_with("floor ") => continue,
+            Some((i, line)) => (i + 1, line),
         };
         let (ch, color, ident) =
-            parse_tile_def(line).ok_or_else(|| anyhow!("invalid tile def: {line:?}"))?;
+            parse_tile_def(line).ok_or_else(|| anyhow!("line {n}: invalid tile def: {line:?}"))?;
+        let color = palette.parse(color).with_context(|| format!("line {n}"))?;
         let idx = ts
             .tile_index(ident)
-            .ok_or_else(|| anyhow!("unknown tile ident: {ident}"))?;
+            .ok_or_else(|| anyhow!("line {n}: unknown tile ident: {ident}"))?;
 
         defs.insert(ch, Tile::new_with_color(idx, color));
     }
@@ -35,12 +138,15 @@ pub fn parse_cp437_prefab(path: impl AsRef<Path>, ts: &TileSet<'_>) -> anyhow::R
     grid.w = lines
         .peek()
         .ok_or_else(|| anyhow!("invalid prefab: no map provided"))?
+        .1
         .len();
 
     // parse the prefab into tiles
-    for line in lines {
+    for (i, line) in lines {
         for ch in line.chars() {
-            let tile = defs.get(&ch).ok_or_else(|| anyhow!("no def for {ch:?}"))?;
+            let tile = defs
+                .get(&ch)
+                .ok_or_else(|| anyhow!("line {}: no def for {ch:?}", i + 1))?;
             grid.cells.push(*tile);
         }
         grid.h += 1;
@@ -49,9 +155,11 @@ pub fn parse_cp437_prefab(path: impl AsRef<Path>, ts: &TileSet<'_>) -> anyhow::R
     Ok(grid)
 }
 
-fn parse_tile_def(line: &str) -> Option<(char, Color, &str)> {
+fn parse_tile_def(line: &str) -> Option<(char, &str, &str)> {
+    // the map meaning of the tile isn't needed for display
+    let line = line.split_once("=>").map_or(line, |(def, _)| def);
     let (char, tail) = line.split_once(' ')?;
-    let (color, ident) = tail.split_once(' ')?;
+    let (color, ident) = tail.trim_start().split_once(' ')?;
 
     let mut chars = char.chars();
     let ch = chars.next()?;
@@ -59,8 +167,30 @@ fn parse_tile_def(line: &str) -> Option<(char, Color, &str)> {
         return None;
     }
 
-    let color = color.trim().strip_prefix('#')?;
-    let color = palette::from_hex(color);
+    Some((ch, color.trim(), ident.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_works() {
+        let prefabs = parse_prefabs("data/prefabs").unwrap();
+        let room = &prefabs["room"];
 
-    Some((ch, color, ident.trim()))
+        assert_eq!((room.cells.w, room.cells.h), (19, 12));
+        assert_eq!(room.floor.as_deref(), Some("stone_floor"));
+        assert_eq!(room.cells.cells[0], PrefabCell::Keep);
+        let starts = room.cells.cells.iter().filter(|&c| *c == PrefabCell::Start);
+        assert_eq!(starts.count(), 4);
+    }
+
+    #[test]
+    fn errors_report_line_numbers() {
+        let raw = "# grey16 shade-dark => keep\n. grey13 shade-light\n\n#.#\n";
+        let err = parse_prefab_str("test".to_string(), raw).unwrap_err();
+
+        assert!(format!("{err:#}").starts_with("line 2"), "{err:#}");
+    }
 }
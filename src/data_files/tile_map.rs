@@ -14,7 +14,7 @@ pub fn parse_cp437_tileset<'a>(
     d: u16,
     bg: Option<Color>,
 ) -> anyhow::Result<TileSet<'a>> {
-    let mut ts = TileSet::new(path, d, d, Pos::new(0, 0), 0, bg)?;
+    let mut ts = TileSet::new(path, "df", d, d, Pos::new(0, 0), 0, bg)?;
     let raw = fs::read_to_string("data/tilesets/df/tile.map").context("reading tile.map")?;
     let lines = raw.lines().peekable();
 
@@ -42,6 +42,7 @@ pub fn parse_cp437_tileset<'a>(
 
 /// Parse an arbitrary tilemap using a tile.map file
 pub fn parse_tile_map<'a>(path: impl AsRef<Path>) -> anyhow::Result<TileSet<'a>> {
+    let family = family(path.as_ref());
     let raw = fs::read_to_string(path).context("reading tile.map")?;
     let mut lines = raw.lines().peekable();
 
@@ -53,7 +54,7 @@ pub fn parse_tile_map<'a>(path: impl AsRef<Path>) -> anyhow::Result<TileSet<'a>>
         start,
         bg,
     } = try_parse_header(&mut lines).ok_or_else(|| anyhow!("invalid tile.map header"))?;
-    let mut ts = TileSet::new(path, dx, dy, start, gap, bg)?;
+    let mut ts = TileSet::new(path, family, dx, dy, start, gap, bg)?;
 
     for line in lines {
         if line.is_empty() || line.starts_with('#') {
@@ -73,6 +74,14 @@ pub fn parse_tile_map<'a>(path: impl AsRef<Path>) -> anyhow::Result<TileSet<'a>>
     Ok(ts)
 }
 
+/// Tilesets are grouped into families by the directory their tile.map is in
+fn family(path: &Path) -> String {
+    path.parent()
+        .and_then(|p| p.file_name())
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
 #[derive(Debug, PartialEq, Eq)]
 struct Header<'a> {
     path: &'a str,
@@ -0,0 +1,179 @@
+use crate::{map::map_tile::TileDef, ui::ColorPalette};
+use anyhow::{Context, anyhow, bail};
+use indexmap::IndexMap;
+use std::{fs, path::Path};
+
+/// Parse a set of terrain definitions keyed by terrain name, looking up any named colours in the
+/// given palette.
+///
+/// See data/tiles for details of the expected format.
+pub fn parse_tile_defs(
+    path: impl AsRef<Path>,
+    palette: &ColorPalette,
+) -> anyhow::Result<IndexMap<String, TileDef>> {
+    let raw = fs::read_to_string(path).context("reading tile defs")?;
+
+    parse_tile_defs_str(&raw, palette)
+}
+
+fn parse_tile_defs_str(
+    raw: &str,
+    palette: &ColorPalette,
+) -> anyhow::Result<IndexMap<String, TileDef>> {
+    let mut defs = IndexMap::new();
+    let mut current: Option<(usize, TileDef, Option<u8>)> = None;
+
+    for (i, line) in raw.lines().enumerate() {
+        let n = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| anyhow!("line {n}: invalid terrain header: {line:?}"))?
+                .trim();
+            if defs.contains_key(name) {
+                bail!("line {n}: duplicate terrain definition: {name:?}");
+            }
+            if let Some((start, def, move_weight)) = current.take() {
+                insert_checked(start, def, move_weight, &mut defs)?;
+            }
+            current = Some((n, TileDef::new(name), None));
+            continue;
+        }
+
+        let (_, def, move_weight) = current
+            .as_mut()
+            .ok_or_else(|| anyhow!("line {n}: property given before any terrain header"))?;
+
+        parse_property(line, def, move_weight, palette).with_context(|| format!("line {n}"))?;
+    }
+
+    if let Some((start, def, move_weight)) = current.take() {
+        insert_checked(start, def, move_weight, &mut defs)?;
+    }
+
+    if defs.is_empty() {
+        bail!("no terrain defined")
+    }
+
+    Ok(defs)
+}
+
+fn insert_checked(
+    start: usize,
+    mut def: TileDef,
+    move_weight: Option<u8>,
+    defs: &mut IndexMap<String, TileDef>,
+) -> anyhow::Result<()> {
+    if def.ident.is_empty() {
+        bail!("line {start}: terrain {:?} has no glyph", def.name);
+    }
+    // impassable terrain defaults to being as hard as possible to move through
+    def.move_weight = match (move_weight, def.path_cost) {
+        (Some(w), _) => w,
+        (None, None) => u8::MAX,
+        (None, Some(_)) => 1,
+    };
+    defs.insert(def.name.clone(), def);
+
+    Ok(())
+}
+
+fn parse_property(
+    line: &str,
+    def: &mut TileDef,
+    move_weight: &mut Option<u8>,
+    palette: &ColorPalette,
+) -> anyhow::Result<()> {
+    let (key, val) = line
+        .split_once(char::is_whitespace)
+        .map(|(k, v)| (k, v.trim()))
+        .ok_or_else(|| anyhow!("missing value for {line:?}"))?;
+
+    match key {
+        "glyph" => {
+            let mut words = val.split_whitespace();
+            def.ident = words.next().unwrap().to_string();
+            for kv in words {
+                let (family, ident) = kv
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("expected FAMILY=IDENT, got {kv:?}"))?;
+                def.idents.insert(family.to_string(), ident.to_string());
+            }
+        }
+        "color" => def.color = palette.parse(val)?,
+        "bg" => def.bg = Some(palette.parse(val)?),
+        "path_cost" => {
+            def.path_cost = match val {
+                "none" => None,
+                _ => Some(val.parse().context("invalid path_cost")?),
+            }
+        }
+        "move_weight" => *move_weight = Some(val.parse().context("invalid move_weight")?),
+        "opacity" => {
+            def.opacity = val.parse().context("invalid opacity")?;
+            if !(0.0..=1.0).contains(&def.opacity) {
+                bail!("opacity must be between 0.0 and 1.0");
+            }
+        }
+        "flags" => {
+            for flag in val.split_whitespace() {
+                match flag {
+                    "water" => def.water = true,
+                    "flammable" => def.flammable = true,
+                    _ => bail!("unknown terrain flag: {flag:?}"),
+                }
+            }
+        }
+        _ => bail!("unknown terrain property: {key:?}"),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_files::parse_color_palette;
+    use sdl2::pixels::Color;
+
+    #[test]
+    fn parse_works() {
+        let palette = parse_color_palette().unwrap();
+        let defs = parse_tile_defs("data/tiles", &palette).unwrap();
+
+        let tree = &defs["tree"];
+        assert_eq!(tree.ident, "club");
+        assert_eq!(tree.idents["urizen"], "tree1");
+        assert_eq!(tree.path_cost, None);
+        assert_eq!(tree.move_weight, u8::MAX);
+        assert_eq!(tree.bg, palette.get("forestBG"));
+        assert!(tree.flammable && !tree.water);
+
+        assert_eq!(defs["earth"].move_weight, 1);
+        assert!(defs["water"].water);
+    }
+
+    #[test]
+    fn errors_report_line_numbers() {
+        let palette = ColorPalette::new([("grey".to_string(), Color::GREY)].into());
+        let cases = [
+            ("[wall]\nglyph #\ncolor grey\nbg gray\n", "line 4"),
+            ("[wall]\nglyph #\nflags sticky\n", "line 3"),
+            ("[wall]\ncolor grey\n", "line 1"),
+            ("glyph #\n", "line 1"),
+        ];
+
+        for (raw, expected) in cases {
+            let err = parse_tile_defs_str(raw, &palette).unwrap_err();
+            assert!(
+                format!("{err:#}").starts_with(expected),
+                "{raw:?} gave {err:#}"
+            );
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use crate::map::builders::{Transition, TurmiteRule, Turn};
+use anyhow::{Context, anyhow, bail};
+use indexmap::IndexMap;
+use std::{collections::HashMap, fs, path::Path};
+
+/// Parse a set of turmite rules keyed by rule name.
+///
+/// See data/turmites for details of the expected format.
+pub fn parse_turmite_rules(
+    path: impl AsRef<Path>,
+) -> anyhow::Result<IndexMap<String, TurmiteRule>> {
+    let raw = fs::read_to_string(path).context("reading turmite rules")?;
+
+    parse_turmite_rules_str(&raw)
+}
+
+fn parse_turmite_rules_str(raw: &str) -> anyhow::Result<IndexMap<String, TurmiteRule>> {
+    let mut rules = IndexMap::new();
+
+    for (i, line) in raw.lines().enumerate() {
+        let n = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap().to_string();
+        if rules.contains_key(&name) {
+            bail!("line {n}: duplicate turmite rule: {name:?}");
+        }
+
+        let mut transitions = HashMap::new();
+        for s in words {
+            let (key, t) = parse_transition(s).with_context(|| format!("line {n}"))?;
+            if transitions.insert(key, t).is_some() {
+                bail!("line {n}: duplicate transition for {s:?}");
+            }
+        }
+
+        // every state the turmite can reach needs to handle both kinds of cell
+        let mut states: Vec<u8> = transitions.values().map(|t| t.next).collect();
+        states.push(0);
+        for state in states {
+            for cell in [0, 1] {
+                if !transitions.contains_key(&(state, cell)) {
+                    bail!("line {n}: {name:?} has no transition for {state}.{cell}");
+                }
+            }
+        }
+
+        rules.insert(name.clone(), TurmiteRule { name, transitions });
+    }
+
+    Ok(rules)
+}
+
+fn parse_transition(s: &str) -> anyhow::Result<((u8, usize), Transition)> {
+    let invalid = || anyhow!("invalid transition: {s:?}");
+    let (from, to) = s.split_once('=').ok_or_else(invalid)?;
+    let (state, cell) = from.split_once('.').ok_or_else(invalid)?;
+    let mut parts = to.split('.');
+    let (write, turn, next) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(write), Some(turn), Some(next), None) => (write, turn, next),
+        _ => return Err(invalid()),
+    };
+
+    let cell_state = |c: &str| match c {
+        "0" => Ok(0),
+        "1" => Ok(1),
+        _ => Err(anyhow!("invalid cell state {c:?}: expected 0 or 1")),
+    };
+    let turn = match turn {
+        "L" => Turn::Left,
+        "R" => Turn::Right,
+        "N" => Turn::None,
+        "U" => Turn::UTurn,
+        _ => bail!("invalid turn {turn:?}: expected one of L, R, N or U"),
+    };
+
+    let key = (state.parse().context("invalid state")?, cell_state(cell)?);
+    let t = Transition {
+        write: cell_state(write)?,
+        turn,
+        next: next.parse().context("invalid state")?,
+    };
+
+    Ok((key, t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_works() {
+        let rules = parse_turmite_rules("data/turmites").unwrap();
+        let ant = &rules["langtons_ant"];
+
+        assert_eq!(ant.transitions.len(), 2);
+        assert_eq!(
+            ant.get(0, 0),
+            Some(&Transition {
+                write: 1,
+                turn: Turn::Right,
+                next: 0
+            })
+        );
+    }
+
+    #[test]
+    fn errors_report_line_numbers() {
+        let cases = [
+            ("# comment\nant 0.0=1.R.0 0.1=0.X.0\n", "line 2"),
+            (
+                "ant 0.0=1.R.0 0.1=0.L.0\n\nant 0.0=1.R.0 0.1=0.L.0\n",
+                "line 3",
+            ),
+            ("ant 0.0=1.R.1 0.1=0.L.0\n", "line 1"),
+        ];
+
+        for (raw, expected) in cases {
+            let err = parse_turmite_rules_str(raw).unwrap_err();
+            assert!(
+                format!("{err:#}").starts_with(expected),
+                "{raw:?} gave {err:#}"
+            );
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Conversations with NPCs, loaded from data/dialogue
+use crate::{
+    Pos,
+    ai::follow::Follower,
+    faction::{Faction, PLAYER_FACTION},
+    map::MapId,
+    mob::Recruitable,
+    player::{Disposition, Inventory, Vocation},
+    state::State,
+};
+use hecs::Entity;
+use indexmap::IndexMap;
+
+/// The target for a [Choice] that ends the conversation
+pub const END: &str = "end";
+
+/// Leash used when recruiting a speaker that doesn't specify one
+const DEFAULT_LEASH: f32 = 2.0;
+
+/// Checks against the player that control whether or not a [Choice] is available
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Disposition(Disposition),
+    Vocation(String),
+    HasItem(String),
+    Flag(String),
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    pub fn check(&self, state: &State<'_>) -> bool {
+        let e = state.e_player;
+        match self {
+            Self::Disposition(d) => state.world.get::<&Disposition>(e).is_ok_and(|p| *p == *d),
+            Self::Vocation(v) => state.world.get::<&Vocation>(e).is_ok_and(|p| p.0 == *v),
+            Self::HasItem(item) => state
+                .world
+                .get::<&Inventory>(e)
+                .is_ok_and(|inv| inv.contains(item)),
+            Self::Flag(flag) => state.quest_flags.contains(flag),
+            Self::Not(c) => !c.check(state),
+        }
+    }
+}
+
+/// Things that happen as a result of a conversation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// Give the player an item
+    GiveItem(String),
+    /// Take an item from the player
+    TakeItem(String),
+    /// Shift how a faction (or the speaker's faction if none is given) feels about the player
+    Relationship(Option<String>, i32),
+    SetFlag(String),
+    /// The speaker starts following the player
+    Recruit,
+}
+
+impl Effect {
+    pub fn apply(&self, speaker: Entity, state: &mut State<'_>) -> anyhow::Result<()> {
+        let e = state.e_player;
+        match self {
+            Self::GiveItem(item) => {
+                if let Ok(mut inv) = state.world.get::<&mut Inventory>(e) {
+                    inv.0.push(item.clone());
+                }
+                state.log(format!("You receive a {item}."));
+            }
+
+            Self::TakeItem(item) => {
+                let taken = state
+                    .world
+                    .get::<&mut Inventory>(e)
+                    .is_ok_and(|mut inv| inv.take(item));
+                if taken {
+                    state.log(format!("You hand over a {item}."));
+                }
+            }
+
+            Self::Relationship(faction, delta) => {
+                let faction = match faction {
+                    Some(f) => f.clone(),
+                    None => match state.world.get::<&Faction>(speaker) {
+                        Ok(f) => f.0.clone(),
+                        Err(_) => return Ok(()),
+                    },
+                };
+                state.relationships.shift(&faction, PLAYER_FACTION, *delta);
+            }
+
+            Self::SetFlag(flag) => {
+                state.quest_flags.insert(flag.clone());
+            }
+
+            Self::Recruit => {
+                let leash = state
+                    .world
+                    .get::<&Recruitable>(speaker)
+                    .map(|r| r.leash)
+                    .unwrap_or(DEFAULT_LEASH);
+                state.world.insert_one(speaker, Follower::new(e, leash))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Choice {
+    pub text: String,
+    /// The id of the node to move to or [END]
+    pub target: String,
+    pub conditions: Vec<Condition>,
+    pub effects: Vec<Effect>,
+}
+
+impl Choice {
+    pub fn is_available(&self, state: &State<'_>) -> bool {
+        self.conditions.iter().all(|c| c.check(state))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DialogueNode {
+    /// What the speaker says, one paragraph per entry
+    pub text: Vec<String>,
+    /// Effects that are applied when the node is reached
+    pub effects: Vec<Effect>,
+    pub choices: Vec<Choice>,
+}
+
+/// A single conversation: the first node is where the conversation starts.
+#[derive(Debug, Clone, Default)]
+pub struct DialogueTree {
+    pub nodes: IndexMap<String, DialogueNode>,
+}
+
+/// Which dialogue tree (from data/dialogue) a mob uses when the player talks to it
+#[derive(Debug, Clone)]
+pub struct HasDialogue(pub String);
+
+/// A conversation that is currently in progress
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    pub speaker: Entity,
+    pub tree: String,
+    pub node: String,
+}
+
+impl Conversation {
+    /// Start a conversation with an adjacent mob that has something to say.
+    ///
+    /// Returns `false` if there is nobody to talk to.
+    pub fn start_adjacent(state: &mut State<'_>) -> anyhow::Result<bool> {
+        let pos = *state.world.get::<&Pos>(state.e_player)?;
+        let map_id = state.mapset.current().id;
+        let speaker = state
+            .world
+            .query::<(&Pos, &HasDialogue, Option<&MapId>)>()
+            .iter()
+            .find(|(_, (p, _, id))| p.fdist(pos) < 1.5 && id.is_none_or(|id| *id == map_id))
+            .map(|(e, (_, d, _))| (e, d.0.clone()));
+
+        let (speaker, tree) = match speaker {
+            Some(speaker) => speaker,
+            None => return Ok(false),
+        };
+        let node = match state
+            .dialogues
+            .get(&tree)
+            .and_then(|t| t.nodes.keys().next())
+        {
+            Some(node) => node.clone(),
+            None => return Ok(false),
+        };
+
+        state.conversation = Some(Self {
+            speaker,
+            tree,
+            node: String::new(),
+        });
+        Self::goto(node, state)?;
+
+        Ok(true)
+    }
+
+    pub fn current_node<'a>(&self, state: &'a State<'_>) -> Option<&'a DialogueNode> {
+        state.dialogues.get(&self.tree)?.nodes.get(&self.node)
+    }
+
+    /// The choices that the player can currently pick from
+    pub fn choices<'a>(&self, state: &'a State<'_>) -> Vec<&'a Choice> {
+        match self.current_node(state) {
+            Some(node) => node
+                .choices
+                .iter()
+                .filter(|c| c.is_available(state))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Pick the nth available choice, ending the conversation if there are no choices.
+    pub fn choose(n: usize, state: &mut State<'_>) -> anyhow::Result<()> {
+        let conv = match state.conversation.as_ref() {
+            Some(conv) => conv,
+            None => return Ok(()),
+        };
+
+        let (effects, target) = {
+            let choices = conv.choices(state);
+            if choices.is_empty() {
+                state.conversation = None;
+                return Ok(());
+            }
+            match choices.get(n) {
+                Some(c) => (c.effects.clone(), c.target.clone()),
+                None => return Ok(()),
+            }
+        };
+
+        let speaker = conv.speaker;
+        for effect in effects.iter() {
+            effect.apply(speaker, state)?;
+        }
+
+        if target == END {
+            state.conversation = None;
+            return Ok(());
+        }
+
+        Self::goto(target, state)
+    }
+
+    fn goto(node: String, state: &mut State<'_>) -> anyhow::Result<()> {
+        let conv = match state.conversation.as_mut() {
+            Some(conv) => conv,
+            None => return Ok(()),
+        };
+        conv.node = node;
+        let speaker = conv.speaker;
+
+        let effects = match state
+            .conversation
+            .as_ref()
+            .and_then(|c| c.current_node(state))
+        {
+            Some(node) => node.effects.clone(),
+            None => Vec::new(),
+        };
+        for effect in effects.iter() {
+            effect.apply(speaker, state)?;
+        }
+
+        Ok(())
+    }
+}
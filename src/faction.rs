@@ -0,0 +1,170 @@
+//! Factions and how they feel about one another
+use crate::player::Disposition;
+use hecs::{Entity, World};
+use std::collections::{HashMap, HashSet};
+
+/// The faction that the player character belongs to
+pub const PLAYER_FACTION: &str = "player";
+
+/// Score change towards an attacker for the faction that was attacked
+const ATTACK_PENALTY: i32 = 40;
+
+/// The faction that an entity belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Faction(pub String);
+
+impl Faction {
+    pub fn player() -> Self {
+        Self(PLAYER_FACTION.to_string())
+    }
+}
+
+/// How one faction feels about another, ordered from worst to best.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Relationship {
+    Hostile,
+    Wary,
+    Neutral,
+    Friendly,
+}
+
+impl Relationship {
+    pub fn from_score(score: i32) -> Self {
+        match score {
+            ..=-50 => Self::Hostile,
+            -49..=-15 => Self::Wary,
+            -14..30 => Self::Neutral,
+            _ => Self::Friendly,
+        }
+    }
+
+    /// The starting score used for relationships of this kind
+    pub fn base_score(&self) -> i32 {
+        match self {
+            Self::Hostile => -75,
+            Self::Wary => -30,
+            Self::Neutral => 0,
+            Self::Friendly => 60,
+        }
+    }
+
+    pub fn is_threat(&self) -> bool {
+        *self <= Self::Wary
+    }
+}
+
+/// The relationship table between all known factions (including the player).
+///
+/// Relationships are one-directional and stored as scores so that they can shift gradually over
+/// time in response to what the player does. Any pair that has not been given an explicit
+/// relationship is treated as neutral.
+#[derive(Debug, Default, Clone)]
+pub struct Relationships {
+    factions: HashSet<String>,
+    faerie: HashSet<String>,
+    scores: HashMap<(String, String), i32>,
+}
+
+impl Relationships {
+    pub fn new() -> Self {
+        let mut r = Self::default();
+        r.add_faction(PLAYER_FACTION, false);
+
+        r
+    }
+
+    pub fn add_faction(&mut self, name: impl Into<String>, faerie: bool) {
+        let name = name.into();
+        if faerie {
+            self.faerie.insert(name.clone());
+        }
+        self.factions.insert(name);
+    }
+
+    pub fn contains(&self, faction: &str) -> bool {
+        self.factions.contains(faction)
+    }
+
+    /// Whether or not the given faction belongs to the faerie world
+    pub fn is_faerie(&self, faction: &str) -> bool {
+        self.faerie.contains(faction)
+    }
+
+    pub fn set(&mut self, from: &str, to: &str, rel: Relationship) {
+        self.scores
+            .insert((from.to_string(), to.to_string()), rel.base_score());
+    }
+
+    pub fn score(&self, from: &str, to: &str) -> i32 {
+        self.scores
+            .get(&(from.to_string(), to.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, from: &str, to: &str) -> Relationship {
+        if from == to {
+            return Relationship::Friendly;
+        }
+
+        Relationship::from_score(self.score(from, to))
+    }
+
+    /// Adjust how `from` feels about `to` by the given amount
+    pub fn shift(&mut self, from: &str, to: &str, delta: i32) {
+        let score = self
+            .scores
+            .entry((from.to_string(), to.to_string()))
+            .or_default();
+        *score = (*score + delta).clamp(-100, 100);
+    }
+
+    /// Update relationships in response to a member of `attacker` attacking a member of `target`
+    pub fn on_attack(&mut self, attacker: &str, target: &str) {
+        if attacker != target {
+            self.shift(target, attacker, -ATTACK_PENALTY);
+        }
+    }
+
+    /// How the entity `from` feels about the entity `to`.
+    ///
+    /// Entities without a [Faction] are neutral towards everything. Faerie factions have their
+    /// view of anyone with a [Disposition] adjusted by that disposition.
+    pub fn between(&self, world: &World, from: Entity, to: Entity) -> Relationship {
+        let (Ok(f_from), Ok(f_to)) = (world.get::<&Faction>(from), world.get::<&Faction>(to))
+        else {
+            return Relationship::Neutral;
+        };
+        if f_from.0 == f_to.0 {
+            return Relationship::Friendly;
+        }
+
+        let mut score = self.score(&f_from.0, &f_to.0);
+        if self.is_faerie(&f_from.0)
+            && let Ok(disposition) = world.get::<&Disposition>(to)
+        {
+            score += disposition.faerie_modifier();
+        }
+
+        Relationship::from_score(score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attacks_sour_relationships() {
+        let mut r = Relationships::new();
+        r.add_faction("pixies", true);
+        r.set("pixies", PLAYER_FACTION, Relationship::Neutral);
+
+        r.on_attack(PLAYER_FACTION, "pixies");
+        assert_eq!(r.get("pixies", PLAYER_FACTION), Relationship::Wary);
+
+        r.on_attack(PLAYER_FACTION, "pixies");
+        assert_eq!(r.get("pixies", PLAYER_FACTION), Relationship::Hostile);
+        assert_eq!(r.get(PLAYER_FACTION, "pixies"), Relationship::Neutral);
+    }
+}
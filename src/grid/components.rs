@@ -0,0 +1,61 @@
+//! https://en.wikipedia.org/wiki/Connected-component_labeling
+use crate::grid::{Grid, Pos};
+
+/// Label the groups of cells in the grid that are connected to one another through neighbouring
+/// cells (including diagonals) for which `open` is true. Each group is returned as a list of its
+/// cells, largest group first.
+pub fn connected_components<T, F>(grid: &Grid<T>, open: F) -> Vec<Vec<Pos>>
+where
+    F: Fn(Pos) -> bool,
+{
+    let mut labelled = Grid::new(grid.w, grid.h, false);
+    let mut components = Vec::new();
+
+    for i in 0..grid.len() {
+        let p = Pos::new((i % grid.w) as i32, (i / grid.w) as i32);
+        if labelled[p] || !open(p) {
+            continue;
+        }
+
+        labelled[p] = true;
+        let mut component = Vec::new();
+        let mut stack = vec![p];
+        while let Some(p) = stack.pop() {
+            component.push(p);
+            for q in grid.neighbouring_tiles(p) {
+                if !labelled[q] && open(q) {
+                    labelled[q] = true;
+                    stack.push(q);
+                }
+            }
+        }
+        components.push(component);
+    }
+
+    components.sort_by_key(|c| std::cmp::Reverse(c.len()));
+
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn components_are_separated_by_closed_cells() {
+        // ..#.
+        // ..#.
+        // ###.
+        // .##.
+        let grid = Grid {
+            cells: "..#...#.###..##.".chars().collect(),
+            w: 4,
+            h: 4,
+        };
+        let components = connected_components(&grid, |p| grid[p] == '.');
+        let sizes: Vec<usize> = components.iter().map(|c| c.len()).collect();
+
+        assert_eq!(sizes, vec![4, 4, 1]);
+        assert!(components[2].contains(&Pos::new(0, 3)));
+    }
+}
@@ -8,20 +8,50 @@ pub fn dijkstra_map<T, F>(grid: &Grid<T>, targets: &[(Pos, i32)], cost_fn: F) ->
 where
     F: Fn(Pos) -> Option<i32>,
 {
-    let mut cost_map = IndexMap::new();
+    dijkstra_map_within(grid, targets, i32::MAX, cost_fn)
+}
+
+/// As [dijkstra_map] but stops flooding once the cost exceeds `max_cost`, leaving any cells beyond
+/// that point as unreachable.
+pub fn dijkstra_map_within<T, F>(
+    grid: &Grid<T>,
+    targets: &[(Pos, i32)],
+    max_cost: i32,
+    cost_fn: F,
+) -> Grid<i32>
+where
+    F: Fn(Pos) -> Option<i32>,
+{
+    let mut cost_map: IndexMap<Pos, (usize, i32)> = IndexMap::new();
     let mut open_set = BinaryHeap::new();
 
-    for (index, &(pos, cost)) in targets.iter().enumerate() {
-        cost_map.insert(pos, (usize::MAX, cost)); // parent and cost
+    for &(pos, cost) in targets.iter() {
+        let index = match cost_map.entry(pos) {
+            Entry::Vacant(e) => {
+                let index = e.index();
+                e.insert((usize::MAX, cost)); // parent and cost
+                index
+            }
+
+            Entry::Occupied(mut e) => {
+                if cost >= e.get().1 {
+                    continue; // duplicate target with a worse starting cost
+                }
+                let index = e.index();
+                e.insert((usize::MAX, cost));
+                index
+            }
+        };
+
         open_set.push(Candidate {
-            estimate: 0,
+            estimate: cost,
             cost,
             index,
         });
     }
 
     while let Some(Candidate {
-        mut cost,
+        cost,
         index: parent,
         ..
     }) = open_set.pop()
@@ -32,9 +62,12 @@ where
         }
 
         for pos in grid.neighbouring_tiles(*pos) {
-            match (cost_fn)(pos) {
-                Some(c) => cost += c,
+            let cost = match (cost_fn)(pos) {
+                Some(c) => cost.saturating_add(c),
                 None => continue, // blocked
+            };
+            if cost > max_cost {
+                continue;
             }
 
             let index = match cost_map.entry(pos) {
@@ -69,3 +102,74 @@ where
 
     dmap
 }
+
+/// The path from `from` down to the nearest target of a map produced by [dijkstra_map], not
+/// including the target itself. Steps to the lowest neighbouring cell until no neighbour is lower.
+pub fn descend(dmap: &Grid<i32>, from: Pos) -> Vec<Pos> {
+    let mut path = Vec::new();
+    let mut p = from;
+
+    while let Some(q) = dmap
+        .neighbouring_tiles(p)
+        .filter(|&q| dmap[q] < dmap[p])
+        .min_by_key(|&q| dmap[q])
+    {
+        path.push(p);
+        p = q;
+    }
+
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances_are_computed_from_the_nearest_target() {
+        let grid = Grid::new(5, 1, ());
+        let dmap = dijkstra_map(&grid, &[(Pos::new(0, 0), 0), (Pos::new(4, 0), 0)], |_| {
+            Some(1)
+        });
+
+        assert_eq!(dmap.cells, vec![0, 1, 2, 1, 0]);
+    }
+
+    #[test]
+    fn blocked_cells_are_unreachable() {
+        let grid = Grid::new(3, 1, ());
+        let dmap = dijkstra_map(&grid, &[(Pos::new(0, 0), 0)], |p| {
+            if p.x == 1 { None } else { Some(1) }
+        });
+
+        assert_eq!(dmap.cells, vec![0, i32::MAX, i32::MAX]);
+    }
+
+    #[test]
+    fn floods_stop_at_the_cost_limit() {
+        let grid = Grid::new(5, 1, ());
+        let dmap = dijkstra_map_within(&grid, &[(Pos::new(0, 0), 0)], 2, |_| Some(1));
+
+        assert_eq!(dmap.cells, vec![0, 1, 2, i32::MAX, i32::MAX]);
+    }
+
+    #[test]
+    fn starting_costs_are_respected() {
+        let grid = Grid::new(4, 1, ());
+        let dmap = dijkstra_map(&grid, &[(Pos::new(0, 0), -10), (Pos::new(3, 0), 0)], |_| {
+            Some(1)
+        });
+
+        assert_eq!(dmap.cells, vec![-10, -9, -8, -7]);
+    }
+
+    #[test]
+    fn descending_leads_to_the_nearest_target() {
+        let grid = Grid::new(5, 1, ());
+        let dmap = dijkstra_map(&grid, &[(Pos::new(0, 0), 0)], |_| Some(1));
+        let path = descend(&dmap, Pos::new(3, 0));
+
+        assert_eq!(path, vec![Pos::new(3, 0), Pos::new(2, 0), Pos::new(1, 0)]);
+        assert!(descend(&dmap, Pos::new(0, 0)).is_empty());
+    }
+}
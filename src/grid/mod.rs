@@ -5,10 +5,14 @@ use std::{
 };
 
 mod astar;
+mod components;
 mod dijkstra_map;
+mod noise_field;
 
 pub use astar::a_star;
-pub use dijkstra_map::dijkstra_map;
+pub use components::connected_components;
+pub use dijkstra_map::{descend, dijkstra_map, dijkstra_map_within};
+pub use noise_field::{NoiseField, NoiseKind};
 
 const NEIGHBOURS: [(i32, i32); 8] = [
     (-1, 0),
@@ -201,6 +205,37 @@ where
             h,
         }
     }
+
+    /// A copy of this grid rotated a quarter turn clockwise
+    pub fn rotated_cw(&self) -> Self {
+        let mut cells = Vec::with_capacity(self.cells.len());
+        for y in 0..self.w {
+            for x in 0..self.h {
+                cells.push(self.cells[self.idx(y, self.h - 1 - x)].clone());
+            }
+        }
+
+        Self {
+            cells,
+            w: self.h,
+            h: self.w,
+        }
+    }
+
+    /// A copy of this grid mirrored left to right
+    pub fn mirrored(&self) -> Self {
+        let cells = self
+            .cells
+            .chunks(self.w)
+            .flat_map(|row| row.iter().rev().cloned())
+            .collect();
+
+        Self {
+            cells,
+            w: self.w,
+            h: self.h,
+        }
+    }
 }
 
 impl<T> Index<usize> for Grid<T> {
@@ -231,3 +266,27 @@ impl<T> IndexMut<Pos> for Grid<T> {
         &mut self.cells[idx]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_and_mirroring_work() {
+        // ab
+        // cd
+        // ef
+        let g = Grid {
+            cells: vec!['a', 'b', 'c', 'd', 'e', 'f'],
+            w: 2,
+            h: 3,
+        };
+
+        let r = g.rotated_cw();
+        assert_eq!((r.w, r.h), (3, 2));
+        assert_eq!(r.cells, vec!['e', 'c', 'a', 'f', 'd', 'b']);
+        assert_eq!(r.rotated_cw().rotated_cw().rotated_cw().cells, g.cells);
+
+        assert_eq!(g.mirrored().cells, vec!['b', 'a', 'd', 'c', 'f', 'e']);
+    }
+}
@@ -0,0 +1,233 @@
+//! Coherent noise for terrain generation
+//!   https://en.wikipedia.org/wiki/Value_noise
+//!   https://en.wikipedia.org/wiki/Perlin_noise
+//!   https://en.wikipedia.org/wiki/Simplex_noise
+//!   https://iquilezles.org/articles/fbm/
+//!   https://iquilezles.org/articles/warp/
+use crate::{Grid, Pos};
+
+// skewing factors for 2D simplex noise: (sqrt(3) - 1) / 2 and (3 - sqrt(3)) / 6
+const F2: f32 = 0.366_025_42;
+const G2: f32 = 0.211_324_87;
+const GRADIENTS: [(f32, f32); 8] = [
+    (1.0, 0.0),
+    (-1.0, 0.0),
+    (0.0, 1.0),
+    (0.0, -1.0),
+    (0.707_106_77, 0.707_106_77),
+    (-0.707_106_77, 0.707_106_77),
+    (0.707_106_77, -0.707_106_77),
+    (-0.707_106_77, -0.707_106_77),
+];
+
+/// The underlying noise function sampled for each octave
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseKind {
+    /// Smoothly interpolated random values at each lattice point: blocky but cheap
+    Value,
+    /// Interpolated random gradients at each lattice point
+    Perlin,
+    /// Gradients on a triangular lattice: fewer directional artifacts than Perlin
+    Simplex,
+}
+
+/// Fractal noise built from summing octaves of a [NoiseKind] with optional domain warping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseField {
+    pub kind: NoiseKind,
+    pub seed: u32,
+    /// The size in cells of the largest features
+    pub scale: f32,
+    pub octaves: usize,
+    /// How much the frequency increases with each octave
+    pub lacunarity: f32,
+    /// How much the amplitude decreases with each octave
+    pub gain: f32,
+    /// How far in cells to distort sample positions using a second noise field: 0.0 disables
+    /// domain warping
+    pub warp: f32,
+}
+
+impl NoiseField {
+    pub fn new(kind: NoiseKind, seed: u32) -> Self {
+        Self {
+            kind,
+            seed,
+            scale: 32.0,
+            octaves: 4,
+            lacunarity: 2.0,
+            gain: 0.5,
+            warp: 0.0,
+        }
+    }
+
+    /// Sample the field at the given cell position, giving a value in the range -1.0..=1.0
+    pub fn sample(&self, x: f32, y: f32) -> f32 {
+        let (x, y) = (x / self.scale, y / self.scale);
+        if self.warp == 0.0 {
+            return self.fbm(x, y, self.seed);
+        }
+
+        let d = self.warp / self.scale;
+        let qx = self.fbm(x, y, self.seed.wrapping_add(1));
+        let qy = self.fbm(x + 5.2, y + 1.3, self.seed.wrapping_add(2));
+
+        self.fbm(x + d * qx, y + d * qy, self.seed)
+    }
+
+    /// Sample the field over a w x h grid with its top left corner at `origin`, rescaled from
+    /// -1.0..=1.0 to 0.0..=1.0. Values don't depend on the size of the grid so neighbouring grids
+    /// of the same field join up, but summing octaves means that they rarely get near either end
+    /// of the range.
+    pub fn grid(&self, origin: Pos, w: usize, h: usize) -> Grid<f32> {
+        let cells = (0..w * h)
+            .map(|i| {
+                let (x, y) = (origin.x + (i % w) as i32, origin.y + (i / w) as i32);
+                (self.sample(x as f32, y as f32) + 1.0) / 2.0
+            })
+            .collect();
+
+        Grid { cells, w, h }
+    }
+
+    fn fbm(&self, x: f32, y: f32, seed: u32) -> f32 {
+        let (mut freq, mut amp) = (1.0, 1.0);
+        let (mut total, mut max) = (0.0, 0.0);
+
+        for octave in 0..self.octaves.max(1) {
+            let seed = seed.wrapping_add(octave as u32 * 131);
+            let v = match self.kind {
+                NoiseKind::Value => value(x * freq, y * freq, seed),
+                NoiseKind::Perlin => perlin(x * freq, y * freq, seed),
+                NoiseKind::Simplex => simplex(x * freq, y * freq, seed),
+            };
+            total += v * amp;
+            max += amp;
+            freq *= self.lacunarity;
+            amp *= self.gain;
+        }
+
+        total / max
+    }
+}
+
+/// A well mixed hash of a lattice point
+fn hash(x: i32, y: i32, seed: u32) -> u32 {
+    let mut h = seed ^ (x as u32).wrapping_mul(0x27d4_eb2d) ^ (y as u32).wrapping_mul(0x1656_67b1);
+    h = (h ^ (h >> 15)).wrapping_mul(0x85eb_ca6b);
+    h = (h ^ (h >> 13)).wrapping_mul(0xc2b2_ae35);
+
+    h ^ (h >> 16)
+}
+
+/// Quintic smoothing curve so that interpolation has continuous first and second derivatives
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn gradient(x: i32, y: i32, seed: u32) -> (f32, f32) {
+    GRADIENTS[(hash(x, y, seed) & 7) as usize]
+}
+
+fn value(x: f32, y: f32, seed: u32) -> f32 {
+    let (x0, y0) = (x.floor() as i32, y.floor() as i32);
+    let (tx, ty) = (fade(x - x0 as f32), fade(y - y0 as f32));
+    let v = |i, j| hash(x0 + i, y0 + j, seed) as f32 / u32::MAX as f32 * 2.0 - 1.0;
+
+    lerp(lerp(v(0, 0), v(1, 0), tx), lerp(v(0, 1), v(1, 1), tx), ty)
+}
+
+fn perlin(x: f32, y: f32, seed: u32) -> f32 {
+    let (x0, y0) = (x.floor() as i32, y.floor() as i32);
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let dot = |i: i32, j: i32| {
+        let (gx, gy) = gradient(x0 + i, y0 + j, seed);
+        gx * (fx - i as f32) + gy * (fy - j as f32)
+    };
+    let (tx, ty) = (fade(fx), fade(fy));
+
+    // the result is within +/- sqrt(2) / 2 so scale it to cover -1..=1
+    let v = lerp(
+        lerp(dot(0, 0), dot(1, 0), tx),
+        lerp(dot(0, 1), dot(1, 1), tx),
+        ty,
+    );
+
+    (v * std::f32::consts::SQRT_2).clamp(-1.0, 1.0)
+}
+
+fn simplex(x: f32, y: f32, seed: u32) -> f32 {
+    // skew into simplex space to find which triangle we are in
+    let s = (x + y) * F2;
+    let (i, j) = ((x + s).floor() as i32, (y + s).floor() as i32);
+    let t = (i + j) as f32 * G2;
+    let (x0, y0) = (x - (i as f32 - t), y - (j as f32 - t));
+    let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+
+    let corners = [
+        (x0, y0, i, j),
+        (x0 - i1 as f32 + G2, y0 - j1 as f32 + G2, i + i1, j + j1),
+        (x0 - 1.0 + 2.0 * G2, y0 - 1.0 + 2.0 * G2, i + 1, j + 1),
+    ];
+
+    let v: f32 = corners
+        .iter()
+        .map(|&(dx, dy, ci, cj)| {
+            let t = 0.5 - dx * dx - dy * dy;
+            if t < 0.0 {
+                0.0
+            } else {
+                let (gx, gy) = gradient(ci, cj, seed);
+                t.powi(4) * (gx * dx + gy * dy)
+            }
+        })
+        .sum();
+
+    // empirically scales the result to roughly cover -1..=1
+    (v * 70.0).clamp(-1.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grids_are_normalised_and_deterministic() {
+        let origin = Pos::new(0, 0);
+        for kind in [NoiseKind::Value, NoiseKind::Perlin, NoiseKind::Simplex] {
+            let mut field = NoiseField::new(kind, 42);
+            field.warp = 8.0;
+            let g = field.grid(origin, 40, 30);
+
+            assert!(g.cells.iter().all(|v| (0.0..=1.0).contains(v)), "{kind:?}");
+            assert_eq!(g.cells, field.grid(origin, 40, 30).cells, "{kind:?}");
+
+            field.seed = 43;
+            assert_ne!(g.cells, field.grid(origin, 40, 30).cells, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn neighbouring_grids_join_up() {
+        let field = NoiseField::new(NoiseKind::Simplex, 7);
+        let whole = field.grid(Pos::new(0, 0), 40, 30);
+        let part = field.grid(Pos::new(25, 10), 10, 5);
+
+        for y in 0..5 {
+            for x in 0..10 {
+                assert_eq!(part[Pos::new(x, y)], whole[Pos::new(25 + x, 10 + y)]);
+            }
+        }
+    }
+
+    #[test]
+    fn gradient_noise_is_zero_on_lattice_points() {
+        for (x, y) in [(0.0, 0.0), (3.0, -7.0), (12.0, 5.0)] {
+            assert_eq!(perlin(x, y, 7), 0.0);
+        }
+    }
+}
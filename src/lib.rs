@@ -1,12 +1,17 @@
 pub mod action;
 pub mod actor;
+pub mod ai;
 pub mod data_files;
+pub mod dialogue;
+pub mod faction;
 pub mod grid;
 pub mod input;
 pub mod map;
 pub mod mob;
+pub mod overworld;
 pub mod player;
 pub mod rng;
+pub mod sound;
 pub mod state;
 pub mod tileset;
 pub mod ui;
@@ -1,12 +1,10 @@
 use dalbrack::{
-    TITLE,
-    map::{
-        builders::{BuildMap, Forest},
-        fov::{FovRange, LightSource},
-    },
+    Pos, TITLE,
+    map::fov::{FovRange, LightSource},
+    overworld::{OVERWORLD_H, OVERWORLD_W, Overworld, enter_region},
     player::Player,
     state::{LocalMap, State},
-    ui::{DisplayMode, MAP_H, MAP_W},
+    ui::DisplayMode,
 };
 use sdl2::pixels::Color;
 
@@ -14,18 +12,14 @@ pub fn main() -> anyhow::Result<()> {
     let mut state = State::init(DisplayMode::FullScreen, TITLE)?;
     // let mut state = State::init(DisplayMode::Fixed(W as u32, SCREEN_H as u32, 16), TITLE)?;
 
-    let (pos, map) = Forest::default().new_map(
-        MAP_W as usize,
-        MAP_H as usize,
-        Default::default(),
-        &mut state,
-    );
-    state.set_map(map);
+    let overworld = Overworld::new(OVERWORLD_W, OVERWORLD_H, &mut *state.rng);
+    let start = overworld.current;
+    state.overworld = Some(overworld);
     state.log("You enter the woods of Dalbrack, in search of the Snoot");
     state.log("Where could it be?...");
 
     state.e_player = state.world.spawn(
-        Player::new_base_bundle(pos, FovRange(75), &state)
+        Player::new_base_bundle(Pos::default(), FovRange(75), &state)
             .add(LightSource {
                 range: 12,
                 // color: Color::RGB(80, 50, 20),
@@ -33,6 +27,7 @@ pub fn main() -> anyhow::Result<()> {
             })
             .build(),
     );
+    enter_region(start, None, &mut state)?;
 
     state.run_mode(LocalMap)?;
 
@@ -1,18 +1,15 @@
 use crate::{
     Pos,
     map::{
-        Map, MapTile,
-        builders::{BuildMap, Snapshots},
-        map_tile::FLOOR,
+        Map,
+        builders::{BuildData, MapPass, Pipeline, PlaceExit, Populate, RoomDoors, Snapshots},
     },
-    mob::{Mob, PIXIE},
     rng::RngHandle,
     state::State,
     ui::palette,
 };
-use hecs::Entity;
-use rand::{Rng, rngs::ThreadRng};
-use sdl2::{pixels::Color, rect::Rect};
+use rand::Rng;
+use sdl2::rect::Rect;
 use std::cmp::{max, min};
 
 /// min split position as a %
@@ -24,47 +21,48 @@ const MAX_DEPTH: usize = 4;
 /// minimum room ratio
 const MIN_RAT: f32 = 0.45;
 
+/// Initial [MapPass] that recursively splits the map into rooms joined by corridors, recording
+/// the rooms and starting in one of them.
 #[derive(Default, Debug)]
 pub struct BspDungeon {
     rooms: Vec<Rect>,
 }
 
-impl BuildMap for BspDungeon {
-    fn bg_and_tiles(&self, state: &State<'_>) -> (Color, Vec<MapTile>) {
-        let bg = palette::BLACK;
-        let tiles = MapTile::dungeon_tiles(&state.ts);
-
-        (bg, tiles)
-    }
-
-    fn build(
+impl MapPass for BspDungeon {
+    fn run(
         &mut self,
-        mut map: Map,
-        _: &mut State<'_>,
+        data: &mut BuildData,
+        state: &mut State<'_>,
         snapshots: &mut Snapshots,
-    ) -> Option<(Pos, Map)> {
-        let mut rng = RngHandle::new();
+    ) -> Option<()> {
+        let floor = data.floor;
+        let map = &mut data.map;
+        self.rooms.clear();
         let starting_room = self.split_and_connect(
             Rect::new(0, 0, map.w as u32, map.h as u32),
             0,
-            &mut rng,
-            &mut map,
+            &mut state.rng,
+            floor,
+            map,
             snapshots,
         );
 
         let p = starting_room.center();
+        data.start = Some(Pos::new(p.x, p.y));
+        data.rooms = std::mem::take(&mut self.rooms);
 
-        Some((Pos::new(p.x, p.y), map))
+        Some(())
     }
+}
 
-    fn populate(&mut self, state: &mut State<'_>) -> Vec<Entity> {
-        self.rooms
-            .iter()
-            .map(|r| {
-                let c = r.center();
-                Mob::spawn_spec(PIXIE, c.x, c.y, state)
-            })
-            .collect()
+impl Pipeline {
+    /// Rooms and corridors with doors, an exit and a pixie in every room
+    pub fn dungeon() -> Self {
+        Self::new(palette::BLACK, "stone_wall", "stone_floor")
+            .then(BspDungeon::default())
+            .then(RoomDoors)
+            .then(PlaceExit)
+            .then(Populate::EachRoom("pixie"))
     }
 }
 
@@ -74,20 +72,21 @@ impl BspDungeon {
         r: Rect,
         depth: usize,
         rng: &mut RngHandle,
+        floor: usize,
         map: &mut Map,
         snapshots: &mut Snapshots,
     ) -> Rect {
         if depth == MAX_DEPTH {
-            let r = position_and_carve(r, rng, map, snapshots);
+            let r = position_and_carve(r, rng, floor, map, snapshots);
             self.rooms.push(r);
             return r;
         }
 
         let (r1, r2) = split(r, rng);
-        let r2 = self.split_and_connect(r2, depth + 1, rng, map, snapshots);
-        let r1 = self.split_and_connect(r1, depth + 1, rng, map, snapshots);
+        let r2 = self.split_and_connect(r2, depth + 1, rng, floor, map, snapshots);
+        let r1 = self.split_and_connect(r1, depth + 1, rng, floor, map, snapshots);
 
-        connect(r1, r2, rng, map, snapshots);
+        connect(r1, r2, rng, floor, map, snapshots);
 
         if rng.random_bool(0.5) { r1 } else { r2 }
     }
@@ -97,7 +96,7 @@ fn aspect_ratio(w: i32, h: i32) -> f32 {
     (min(w, h) as f32) / (max(w, h) as f32)
 }
 
-fn split(r: Rect, rng: &mut ThreadRng) -> (Rect, Rect) {
+fn split(r: Rect, rng: &mut impl Rng) -> (Rect, Rect) {
     loop {
         if rng.random_bool(0.5) {
             let split_point = rng.random_range(SPLIT_FROM..SPLIT_TO);
@@ -127,7 +126,8 @@ fn split(r: Rect, rng: &mut ThreadRng) -> (Rect, Rect) {
 
 fn position_and_carve(
     mut r: Rect,
-    rng: &mut ThreadRng,
+    rng: &mut impl Rng,
+    floor: usize,
     map: &mut Map,
     snapshots: &mut Snapshots,
 ) -> Rect {
@@ -140,22 +140,29 @@ fn position_and_carve(
     r.h -= r.y - p.y;
     r.h -= rng.random_range(1..max(2, r.h / 3));
 
-    map.carve_rect(r, FLOOR);
+    map.carve_rect(r, floor);
     snapshots.push(map);
 
     r
 }
 
-fn connect(r1: Rect, r2: Rect, rng: &mut RngHandle, map: &mut Map, snapshots: &mut Snapshots) {
+fn connect(
+    r1: Rect,
+    r2: Rect,
+    rng: &mut RngHandle,
+    floor: usize,
+    map: &mut Map,
+    snapshots: &mut Snapshots,
+) {
     let Pos { x: x1, y: y1 } = rng.random_point(r1, 1);
     let Pos { x: x2, y: y2 } = rng.random_point(r2, 1);
 
     if rng.random_bool(0.5) {
-        map.carve_h_tunnel(x1, x2, y1, FLOOR);
-        map.carve_v_tunnel(y1, y2, x2, FLOOR);
+        map.carve_h_tunnel(x1, x2, y1, floor);
+        map.carve_v_tunnel(y1, y2, x2, floor);
     } else {
-        map.carve_v_tunnel(y1, y2, x1, FLOOR);
-        map.carve_h_tunnel(x1, x2, y2, FLOOR);
+        map.carve_v_tunnel(y1, y2, x1, floor);
+        map.carve_h_tunnel(x1, x2, y2, floor);
     }
     snapshots.push(map);
 }
@@ -9,71 +9,25 @@
 //! https://conwaylife.com/wiki/Isotropic_non-totalistic_rule
 use crate::{
     Grid, Pos,
-    map::{
-        Map,
-        builders::{BuildMap, Snapshots, voronoi_regions},
-        map_tile::MapTile,
+    map::builders::{
+        BuildData, ConnectRegions, MapPass, Pipeline, RequireOpen, Snapshots, StartingPosition,
+        VoronoiRegions,
     },
-    rng::RngHandle,
     state::State,
     ui::palette,
 };
-use hecs::Entity;
-use sdl2::pixels::Color;
 
 const MIN_OPEN_PERC: f32 = 0.45;
+/// Caves smaller than this are filled in rather than being connected to the rest of the map
+const MIN_REGION_SIZE: usize = 12;
 const N_SEEDS: usize = 16;
 
-pub const FILLED: usize = 0;
-pub const OPEN: usize = 1;
-
-#[derive(Debug)]
-pub enum StartingPosition {
-    North,
-    South,
-    East,
-    West,
-    Center,
-}
-
-impl StartingPosition {
-    pub fn locate(&self, map: &Map) -> Option<Pos> {
-        let (w, h) = (map.w as i32, map.h as i32);
-
-        let (x, y, dx, dy) = match self {
-            Self::North => (w / 2, 0, 1, 0),
-            Self::South => (w / 2, h - 1, 1, 0),
-            Self::East => (w - 1, h / 2, 0, -1),
-            Self::West => (0, h / 2, 0, -1),
-            Self::Center => (w / 2, h / 2, -1, 0),
-        };
-
-        for i in [1, -1] {
-            let mut pos = Pos::new(x, y);
-            let delta = Pos::new(dx * i, dy * i);
-
-            while map.contains_pos(pos) {
-                if map[pos] == OPEN {
-                    return Some(pos);
-                }
-
-                pos += delta;
-            }
-        }
-
-        None
-    }
-}
-
-/// Helper struct that can construct a map from a given cellular automata rule.
-/// When used directly via [BuildMap] this will return maps where filled cells are walls and open
-/// cells are floor but no other features.
+/// Initial [MapPass] that randomly fills the map and then runs the given cellular automata rule
+/// over it. Filled cells use the wall terrain of the map and open cells use the floor.
 pub struct CellularAutomata {
-    pub start_pos: StartingPosition,
     pub p_initial_open: u16,
     pub iterations: usize,
     pub rule: CaRule,
-    pub regions: Vec<Vec<Pos>>,
 }
 
 impl Default for CellularAutomata {
@@ -82,77 +36,63 @@ impl Default for CellularAutomata {
     }
 }
 
-impl CellularAutomata {
-    fn has_sufficient_open(&self, map: &Map) -> bool {
-        let n_open = map.tiles.cells.iter().filter(|&&t| t == OPEN).count();
-        let p_open = n_open as f32 / map.tiles.cells.len() as f32;
-
-        p_open >= MIN_OPEN_PERC
-    }
-
-    fn assign_regions(&mut self, map: &Map, rng: &mut RngHandle) {
-        let points = map.cells.iter().enumerate().flat_map(|(i, idx)| {
-            if *idx > 0 {
-                let x = i % map.w;
-                let y = i / map.w;
-                Some(Pos::new(x as i32, y as i32))
-            } else {
-                None
-            }
-        });
-
-        self.regions = voronoi_regions(N_SEEDS, map.w, map.h, points, rng);
-    }
-}
-
-impl BuildMap for CellularAutomata {
-    fn bg_and_tiles(&self, state: &State<'_>) -> (Color, Vec<MapTile>) {
-        let bg = palette::BLACK;
-        let tiles = MapTile::dungeon_tiles(&state.ts);
-
-        (bg, tiles)
-    }
-
-    fn init_map(&mut self, map: &mut Map) {
-        let mut rng = RngHandle::new();
-
-        for i in 0..map.tiles.len() {
-            if rng.percentile() > self.p_initial_open {
-                map.tiles[i] = OPEN;
-            }
-        }
-    }
-
-    fn build(
+impl MapPass for CellularAutomata {
+    fn run(
         &mut self,
-        mut map: Map,
+        data: &mut BuildData,
         state: &mut State<'_>,
         snapshots: &mut Snapshots,
-    ) -> Option<(Pos, Map)> {
-        for i in 0..self.iterations {
-            map.tiles = self.rule.run(i, &map);
-            snapshots.push(&map);
+    ) -> Option<()> {
+        let mut filled = filled_cells(data);
+        for cell in filled.cells.iter_mut() {
+            if state.rng.percentile() > self.p_initial_open {
+                *cell = false;
+            }
         }
+        set_cells(&filled, data);
+        snapshots.push(&data.map);
 
-        let pos = self.start_pos.locate(&map)?;
-        self.fill_unreachable_from(&[(pos, 0)], FILLED, &mut map);
-
-        if !self.has_sufficient_open(&map) {
-            return None;
+        for i in 0..self.iterations {
+            filled = self.rule.run(i, &filled);
+            set_cells(&filled, data);
+            snapshots.push(&data.map);
         }
 
-        self.assign_regions(&map, &mut state.rng);
+        Some(())
+    }
+}
 
-        Some((pos, map))
+/// Which cells of the map are currently filled with its wall terrain
+pub(super) fn filled_cells(data: &BuildData) -> Grid<bool> {
+    Grid {
+        w: data.map.w,
+        h: data.map.h,
+        cells: data.map.cells.iter().map(|&idx| idx == data.wall).collect(),
     }
+}
 
-    fn populate(&mut self, _state: &mut State<'_>) -> Vec<Entity> {
-        Vec::new()
+/// Set the map to its wall terrain wherever `filled` is set and to its floor everywhere else
+pub(super) fn set_cells(filled: &Grid<bool>, data: &mut BuildData) {
+    for (idx, &filled) in data.map.cells.iter_mut().zip(filled.cells.iter()) {
+        *idx = if filled { data.wall } else { data.floor };
     }
 }
 
-/// for cell p, how many FILLED cells can be reached within a distance of n.
-fn n_filled(p: Pos, n: i32, current: &Grid<usize>) -> u8 {
+impl Pipeline {
+    /// Stone caves from the given automata: separate caves are joined by tunnels (other than any
+    /// that are too small to be worth keeping) and the open space is split into regions.
+    pub fn cave(ca: CellularAutomata) -> Self {
+        Self::new(palette::BLACK, "stone_wall", "stone_floor")
+            .then(ca)
+            .then(StartingPosition::Center)
+            .then(ConnectRegions(MIN_REGION_SIZE))
+            .then(RequireOpen(MIN_OPEN_PERC))
+            .then(VoronoiRegions(N_SEEDS))
+    }
+}
+
+/// for cell p, how many filled cells can be reached within a distance of n.
+fn n_filled(p: Pos, n: i32, current: &Grid<bool>) -> u8 {
     let mut count = 0;
 
     for dy in -n..=n {
@@ -161,7 +101,7 @@ fn n_filled(p: Pos, n: i32, current: &Grid<usize>) -> u8 {
             if ((dy == 0) && (dx == 0)) || !current.contains_pos(q) {
                 continue;
             }
-            if current[q] == FILLED {
+            if current[q] {
                 count += 1;
             }
         }
@@ -173,12 +113,13 @@ fn n_filled(p: Pos, n: i32, current: &Grid<usize>) -> u8 {
 // Rules
 
 pub enum CaRule {
-    Fn(fn(Pos, usize, &Grid<usize>) -> bool),
+    Fn(fn(Pos, usize, &Grid<bool>) -> bool),
     LifeLike { born: Vec<u8>, survive: Vec<u8> },
 }
 
 impl CaRule {
-    pub fn run(&self, i: usize, current: &Grid<usize>) -> Grid<usize> {
+    /// Run iteration `i` of the rule over a grid of which cells are filled
+    pub fn run(&self, i: usize, current: &Grid<bool>) -> Grid<bool> {
         let mut new = current.clone();
 
         for y in 1..current.h - 1 {
@@ -191,27 +132,26 @@ impl CaRule {
         new
     }
 
-    pub fn state_for(&self, p: Pos, i: usize, current: &Grid<usize>) -> usize {
-        let filled = match self {
+    /// Whether or not the cell at `p` is filled after iteration `i`
+    pub fn state_for(&self, p: Pos, i: usize, current: &Grid<bool>) -> bool {
+        match self {
             Self::Fn(f) => (f)(p, i, current),
             Self::LifeLike { born, survive } => life_like_rule(p, current, born, survive),
-        };
-
-        if filled { FILLED } else { OPEN }
+        }
     }
 }
 
 /// See https://en.wikipedia.org/wiki/Life-like_cellular_automaton
-fn life_like_rule(p: Pos, current: &Grid<usize>, born: &[u8], survive: &[u8]) -> bool {
+fn life_like_rule(p: Pos, current: &Grid<bool>, born: &[u8], survive: &[u8]) -> bool {
     let n = n_filled(p, 1, current);
-    let alive = current[p] == FILLED;
+    let alive = current[p];
 
     (alive && survive.contains(&n)) || (!alive && born.contains(&n))
 }
 
 macro_rules! rule {
     ($name:ident, $p_open:expr, $iterations:expr, $impl:expr) => {
-        pub fn $name(pos: Pos, i: usize, current: &Grid<usize>) -> bool {
+        pub fn $name(pos: Pos, i: usize, current: &Grid<bool>) -> bool {
             $impl(pos, i, current)
         }
 
@@ -224,18 +164,16 @@ macro_rules! rule {
         impl CellularAutomata {
             pub fn $name() -> Self {
                 Self {
-                    start_pos: StartingPosition::Center,
                     p_initial_open: $p_open,
                     iterations: $iterations,
                     rule: CaRule::$name(),
-                    regions: Default::default(),
                 }
             }
         }
     };
 
     (@life $name:ident, $p_open:expr, $iterations:expr, [$($born:expr),*], [$($survive:expr),*]) => {
-        pub fn $name(pos: Pos, _: usize, current: &Grid<usize>) -> bool {
+        pub fn $name(pos: Pos, _: usize, current: &Grid<bool>) -> bool {
             life_like_rule(pos, current, &[$($born),*], &[$($survive),*])
         }
 
@@ -248,24 +186,22 @@ macro_rules! rule {
         impl CellularAutomata {
             pub fn $name() -> Self {
                 Self {
-                    start_pos: StartingPosition::Center,
                     p_initial_open: $p_open,
                     iterations: $iterations,
                     rule: CaRule::Fn($name),
-                    regions: Default::default(),
                 }
             }
         }
     };
 }
 
-rule!(simple, 55, 15, |p: Pos, _i: usize, g: &Grid<usize>| {
+rule!(simple, 55, 15, |p: Pos, _i: usize, g: &Grid<bool>| {
     let n = n_filled(p, 1, g);
 
     [0, 5, 6, 7, 8].contains(&n)
 });
 
-rule!(rogue_basin, 60, 7, |p: Pos, i: usize, g: &Grid<usize>| {
+rule!(rogue_basin, 60, 7, |p: Pos, i: usize, g: &Grid<bool>| {
     let n1 = n_filled(p, 1, g);
     let n2 = n_filled(p, 2, g);
 
@@ -0,0 +1,272 @@
+//! https://www.roguebasin.com/index.php?title=Random_Walk_Cave_Generation
+use crate::{
+    Pos,
+    map::{
+        Map,
+        builders::{BuildData, CullUnreachable, MapPass, Pipeline, Snapshots, VoronoiRegions},
+    },
+    state::State,
+    ui::palette,
+};
+use rand::{Rng, seq::IndexedRandom};
+
+const N_REGIONS: usize = 16;
+
+/// Where each new walker starts from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnMode {
+    /// Always start from the center of the map
+    Start,
+    /// Start from a random cell that has already been dug out
+    Random,
+}
+
+/// Which axes each dug out cell is mirrored across
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symmetry {
+    None,
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+/// Initial [MapPass] that digs out the map by sending walkers stumbling randomly through solid
+/// rock until enough of it has been cleared. The map starts filled with its wall terrain and
+/// cleared cells use the floor.
+#[derive(Debug, Clone)]
+pub struct DrunkardsWalk {
+    /// The maximum number of walkers to send out before giving up on the map
+    pub walkers: usize,
+    /// How many steps each walker takes before stopping
+    pub lifetime: usize,
+    pub spawn: SpawnMode,
+    /// The width of the square dug out at each step
+    pub brush: i32,
+    pub symmetry: Symmetry,
+    /// The fraction of the map that needs to be cleared
+    pub target_open: f32,
+}
+
+impl Default for DrunkardsWalk {
+    fn default() -> Self {
+        Self::open_area()
+    }
+}
+
+impl DrunkardsWalk {
+    /// A single large open cave dug out from the center
+    pub fn open_area() -> Self {
+        Self {
+            walkers: 400,
+            lifetime: 400,
+            spawn: SpawnMode::Start,
+            brush: 1,
+            symmetry: Symmetry::None,
+            target_open: 0.5,
+        }
+    }
+
+    /// Many short walks branching out from anywhere already dug
+    pub fn open_halls() -> Self {
+        Self {
+            walkers: 800,
+            lifetime: 400,
+            spawn: SpawnMode::Random,
+            brush: 1,
+            symmetry: Symmetry::None,
+            target_open: 0.5,
+        }
+    }
+
+    /// Narrow tunnels made up of lots of very short walks
+    pub fn winding_passages() -> Self {
+        Self {
+            walkers: 2000,
+            lifetime: 100,
+            spawn: SpawnMode::Random,
+            brush: 1,
+            symmetry: Symmetry::None,
+            target_open: 0.4,
+        }
+    }
+
+    /// Winding passages dug with a wider brush
+    pub fn fat_passages() -> Self {
+        Self {
+            brush: 2,
+            ..Self::winding_passages()
+        }
+    }
+
+    /// Fat passages mirrored across both axes
+    pub fn fearful_symmetry() -> Self {
+        Self {
+            symmetry: Symmetry::Both,
+            ..Self::fat_passages()
+        }
+    }
+
+    fn dig(&self, p: Pos, floor: usize, map: &mut Map) {
+        let (w, h) = (map.w as i32, map.h as i32);
+
+        for dy in 0..self.brush {
+            for dx in 0..self.brush {
+                let q = p + Pos::new(dx, dy);
+                let mut targets = vec![q];
+                if matches!(self.symmetry, Symmetry::Horizontal | Symmetry::Both) {
+                    targets.push(Pos::new(w - 1 - q.x, q.y));
+                }
+                if matches!(self.symmetry, Symmetry::Vertical | Symmetry::Both) {
+                    targets.push(Pos::new(q.x, h - 1 - q.y));
+                }
+                if self.symmetry == Symmetry::Both {
+                    targets.push(Pos::new(w - 1 - q.x, h - 1 - q.y));
+                }
+
+                for t in targets {
+                    // keep a solid border around the edge of the map
+                    if t.x > 0 && t.y > 0 && t.x < w - 1 && t.y < h - 1 {
+                        map.tiles[t] = floor;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl MapPass for DrunkardsWalk {
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<()> {
+        let start = self.walk(&mut data.map, data.floor, &mut *state.rng, snapshots)?;
+        data.start = Some(start);
+
+        Some(())
+    }
+}
+
+impl DrunkardsWalk {
+    /// Send out walkers from the center of the map until enough of it has been cleared, returning
+    /// the center if it was
+    fn walk(
+        &self,
+        map: &mut Map,
+        floor: usize,
+        rng: &mut impl Rng,
+        snapshots: &mut Snapshots,
+    ) -> Option<Pos> {
+        let (w, h) = (map.w as i32, map.h as i32);
+        let start = Pos::new(w / 2, h / 2);
+        let target = (self.target_open * map.len() as f32) as usize;
+        let steps = [
+            Pos::new(0, -1),
+            Pos::new(0, 1),
+            Pos::new(-1, 0),
+            Pos::new(1, 0),
+        ];
+
+        self.dig(start, floor, map);
+        let mut n_open = map.cells.iter().filter(|&&idx| idx == floor).count();
+
+        for _ in 0..self.walkers {
+            if n_open >= target {
+                break;
+            }
+
+            let mut p = match self.spawn {
+                SpawnMode::Start => start,
+                SpawnMode::Random => {
+                    let open: Vec<usize> = (0..map.len()).filter(|&i| map[i] == floor).collect();
+                    let i = *open.choose(rng)?;
+                    Pos::new((i % map.w) as i32, (i / map.w) as i32)
+                }
+            };
+
+            for _ in 0..self.lifetime {
+                self.dig(p, floor, map);
+                let next = p + steps[rng.random_range(0..steps.len())];
+                if next.x > 0 && next.y > 0 && next.x < w - 1 && next.y < h - 1 {
+                    p = next;
+                }
+            }
+
+            n_open = map.cells.iter().filter(|&&idx| idx == floor).count();
+            snapshots.push(map);
+        }
+
+        (n_open >= target).then_some(start)
+    }
+}
+
+impl Pipeline {
+    /// Stone caves dug out by the given walkers
+    pub fn drunkards_walk(dw: DrunkardsWalk) -> Self {
+        Self::new(palette::BLACK, "stone_wall", "stone_floor")
+            .then(dw)
+            .then(CullUnreachable)
+            .then(VoronoiRegions(N_REGIONS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::map_tile::Terrain;
+    use rand::{SeedableRng, rngs::StdRng};
+    use sdl2::pixels::Color;
+
+    fn walk(dw: DrunkardsWalk, seed: u64) -> (Map, usize) {
+        let terrain = Terrain::for_tests([("stone_wall", None), ("stone_floor", Some(1))]);
+        let mut map = Map::new(40, 30, terrain, Color::BLACK, Color::BLACK);
+        let floor = map.terrain.idx("stone_floor");
+        let mut snapshots = Snapshots {
+            inner: Vec::new(),
+            active: false,
+        };
+
+        let start = dw.walk(
+            &mut map,
+            floor,
+            &mut StdRng::seed_from_u64(seed),
+            &mut snapshots,
+        );
+        assert_eq!(start, Some(Pos::new(20, 15)));
+
+        (map, floor)
+    }
+
+    #[test]
+    fn walkers_dig_out_the_target_fraction_of_the_map() {
+        for (i, dw) in [
+            DrunkardsWalk::open_area(),
+            DrunkardsWalk::open_halls(),
+            DrunkardsWalk::winding_passages(),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let target = dw.target_open;
+            let (map, floor) = walk(dw, i as u64);
+            let n_open = map.cells.iter().filter(|&&idx| idx == floor).count();
+
+            assert!(n_open as f32 >= target * map.len() as f32, "{i}");
+        }
+    }
+
+    #[test]
+    fn dug_cells_are_mirrored() {
+        let (map, floor) = walk(DrunkardsWalk::fearful_symmetry(), 7);
+        let (w, h) = (map.w as i32, map.h as i32);
+
+        for y in 0..h {
+            for x in 0..w {
+                let dug = map[Pos::new(x, y)] == floor;
+                assert_eq!(dug, map[Pos::new(w - 1 - x, y)] == floor, "({x}, {y})");
+                assert_eq!(dug, map[Pos::new(x, h - 1 - y)] == floor, "({x}, {y})");
+            }
+        }
+    }
+}
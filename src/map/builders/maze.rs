@@ -0,0 +1,425 @@
+//! Perfect and braided mazes
+//!   http://www.astrolog.org/labyrnth/algrithm.htm
+//!   https://weblog.jamisbuck.org/2011/2/7/maze-generation-algorithm-recap
+use crate::{
+    Pos,
+    map::builders::{BuildData, MapPass, Pipeline, PlaceExit, Snapshots},
+    state::State,
+    ui::palette,
+};
+use rand::{
+    Rng,
+    seq::{IndexedRandom, SliceRandom},
+};
+use sdl2::rect::Rect;
+use std::collections::HashMap;
+
+/// The algorithm used to generate the spanning tree of the maze
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MazeAlgorithm {
+    /// Long winding passages with few branches
+    RecursiveBacktracker,
+    /// Lots of short dead ends radiating out from the start
+    Prims,
+    /// Lots of short dead ends spread evenly over the maze
+    Kruskals,
+    /// Built a row at a time so works for arbitrarily tall mazes
+    Ellers,
+    /// An unbiased sample from all possible mazes
+    Wilsons,
+}
+
+/// [MapPass] that carves a maze out of the map (or just part of it). Passages are carved using
+/// the floor terrain of the map and the walls between them are left as they are.
+#[derive(Debug, Clone)]
+pub struct Maze {
+    pub algorithm: MazeAlgorithm,
+    /// The width of each passage in cells: walls are always a single cell thick
+    pub corridor_width: usize,
+    /// The fraction of dead ends to remove by joining them to a neighbour: 0.0 gives a perfect
+    /// maze and 1.0 a fully braided one with no dead ends at all
+    pub braid: f64,
+    /// How many rooms to punch into the maze after it is carved
+    pub rooms: usize,
+    /// The section of the map to fill with the maze, or the whole map if not set
+    pub area: Option<Rect>,
+}
+
+impl Maze {
+    pub fn new(algorithm: MazeAlgorithm) -> Self {
+        Self {
+            algorithm,
+            corridor_width: 1,
+            braid: 0.0,
+            rooms: 0,
+            area: None,
+        }
+    }
+}
+
+impl MapPass for Maze {
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<()> {
+        let floor = data.floor;
+        let map = &mut data.map;
+        let area = self
+            .area
+            .unwrap_or(Rect::new(0, 0, map.w as u32, map.h as u32));
+        let pitch = self.corridor_width as i32 + 1;
+        let (nx, ny) = ((area.w - 1) / pitch, (area.h - 1) / pitch);
+        if nx < 1 || ny < 1 {
+            return None;
+        }
+        let (nx, ny) = (nx as usize, ny as usize);
+
+        let rng = &mut *state.rng;
+        let mut edges = spanning_tree(self.algorithm, nx, ny, rng);
+        braid(&mut edges, self.braid, nx, ny, rng);
+
+        let cell_rect = |x: usize, y: usize, w: usize, h: usize| {
+            Rect::new(
+                area.x + 1 + x as i32 * pitch,
+                area.y + 1 + y as i32 * pitch,
+                (w as i32 * pitch - 1) as u32,
+                (h as i32 * pitch - 1) as u32,
+            )
+        };
+
+        for (i, &(a, b)) in edges.iter().enumerate() {
+            let (ax, ay, bx, by) = (a % nx, a / nx, b % nx, b / nx);
+            let (x, y) = (ax.min(bx), ay.min(by));
+            let (w, h) = (ax.abs_diff(bx) + 1, ay.abs_diff(by) + 1);
+            map.carve_rect(cell_rect(x, y, w, h), floor);
+            if i % nx == 0 {
+                snapshots.push(map);
+            }
+        }
+        // a single cell maze has no edges
+        map.carve_rect(cell_rect(0, 0, 1, 1), floor);
+
+        for _ in 0..self.rooms {
+            let (w, h) = (
+                rng.random_range(2..=4).min(nx),
+                rng.random_range(2..=4).min(ny),
+            );
+            let (x, y) = (rng.random_range(0..=nx - w), rng.random_range(0..=ny - h));
+            let r = cell_rect(x, y, w, h);
+            map.carve_rect(r, floor);
+            data.rooms.push(r);
+            snapshots.push(map);
+        }
+
+        if data.start.is_none() {
+            let c = cell_rect(0, 0, 1, 1).center();
+            data.start = Some(Pos::new(c.x, c.y));
+        }
+
+        Some(())
+    }
+}
+
+impl Pipeline {
+    /// A stone maze filling the whole map with an exit as far from the start as possible
+    pub fn maze(maze: Maze) -> Self {
+        Self::new(palette::BLACK, "stone_wall", "stone_floor")
+            .then(maze)
+            .then(PlaceExit)
+    }
+}
+
+pub(super) fn neighbours(c: usize, nx: usize, ny: usize) -> Vec<usize> {
+    let (x, y) = (c % nx, c / nx);
+    let mut ns = Vec::with_capacity(4);
+    if x > 0 {
+        ns.push(c - 1);
+    }
+    if x + 1 < nx {
+        ns.push(c + 1);
+    }
+    if y > 0 {
+        ns.push(c - nx);
+    }
+    if y + 1 < ny {
+        ns.push(c + nx);
+    }
+
+    ns
+}
+
+/// The passages of a perfect maze over a grid of nx by ny cells, as pairs of adjacent cell
+/// indices in the order they were carved.
+pub(super) fn spanning_tree(
+    algorithm: MazeAlgorithm,
+    nx: usize,
+    ny: usize,
+    rng: &mut impl Rng,
+) -> Vec<(usize, usize)> {
+    let n = nx * ny;
+    let mut edges = Vec::with_capacity(n - 1);
+    let mut visited = vec![false; n];
+    let start = rng.random_range(0..n);
+
+    match algorithm {
+        MazeAlgorithm::RecursiveBacktracker => {
+            let mut stack = vec![start];
+            visited[start] = true;
+
+            while let Some(&c) = stack.last() {
+                let unvisited: Vec<usize> = neighbours(c, nx, ny)
+                    .into_iter()
+                    .filter(|&nb| !visited[nb])
+                    .collect();
+                match unvisited.choose(rng) {
+                    Some(&nb) => {
+                        visited[nb] = true;
+                        edges.push((c, nb));
+                        stack.push(nb);
+                    }
+                    None => {
+                        stack.pop();
+                    }
+                }
+            }
+        }
+
+        MazeAlgorithm::Prims => {
+            visited[start] = true;
+            let mut frontier: Vec<(usize, usize)> = neighbours(start, nx, ny)
+                .into_iter()
+                .map(|nb| (start, nb))
+                .collect();
+
+            while !frontier.is_empty() {
+                let (a, b) = frontier.swap_remove(rng.random_range(0..frontier.len()));
+                if visited[b] {
+                    continue;
+                }
+                visited[b] = true;
+                edges.push((a, b));
+                frontier.extend(
+                    neighbours(b, nx, ny)
+                        .into_iter()
+                        .filter(|&nb| !visited[nb])
+                        .map(|nb| (b, nb)),
+                );
+            }
+        }
+
+        MazeAlgorithm::Kruskals => {
+            let mut all: Vec<(usize, usize)> = (0..n)
+                .flat_map(|c| {
+                    neighbours(c, nx, ny)
+                        .into_iter()
+                        .filter(move |&nb| nb > c)
+                        .map(move |nb| (c, nb))
+                })
+                .collect();
+            all.shuffle(rng);
+
+            let mut parent: Vec<usize> = (0..n).collect();
+            fn root(parent: &mut [usize], mut c: usize) -> usize {
+                while parent[c] != c {
+                    parent[c] = parent[parent[c]];
+                    c = parent[c];
+                }
+                c
+            }
+
+            for (a, b) in all {
+                let (ra, rb) = (root(&mut parent, a), root(&mut parent, b));
+                if ra != rb {
+                    parent[rb] = ra;
+                    edges.push((a, b));
+                }
+            }
+        }
+
+        MazeAlgorithm::Ellers => {
+            let mut sets: Vec<usize> = (0..nx).collect();
+            let mut next_set = nx;
+
+            for y in 0..ny {
+                let last_row = y + 1 == ny;
+
+                // randomly join neighbouring cells in different sets (all of them on the last row)
+                for x in 0..nx - 1 {
+                    if sets[x] != sets[x + 1] && (last_row || rng.random_bool(0.5)) {
+                        edges.push((y * nx + x, y * nx + x + 1));
+                        let (keep, old) = (sets[x], sets[x + 1]);
+                        sets.iter_mut()
+                            .filter(|s| **s == old)
+                            .for_each(|s| *s = keep);
+                    }
+                }
+                if last_row {
+                    break;
+                }
+
+                // every set needs at least one passage down to the next row
+                let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+                for (x, &s) in sets.iter().enumerate() {
+                    members.entry(s).or_default().push(x);
+                }
+                let mut below = vec![None; nx];
+                for (s, mut xs) in members {
+                    xs.shuffle(rng);
+                    let k = rng.random_range(1..=xs.len());
+                    for &x in xs.iter().take(k) {
+                        edges.push((y * nx + x, (y + 1) * nx + x));
+                        below[x] = Some(s);
+                    }
+                }
+                sets = below
+                    .into_iter()
+                    .map(|s| {
+                        s.unwrap_or_else(|| {
+                            next_set += 1;
+                            next_set
+                        })
+                    })
+                    .collect();
+            }
+        }
+
+        MazeAlgorithm::Wilsons => {
+            visited[start] = true;
+            let mut remaining: Vec<usize> = (0..n).filter(|&c| c != start).collect();
+            remaining.shuffle(rng);
+            let mut next = vec![0; n];
+
+            for c in remaining {
+                // random walk until we hit the maze: overwriting the exit from each cell as we go
+                // erases any loops in the walk
+                let mut p = c;
+                while !visited[p] {
+                    next[p] = *neighbours(p, nx, ny).choose(rng).unwrap();
+                    p = next[p];
+                }
+
+                let mut p = c;
+                while !visited[p] {
+                    visited[p] = true;
+                    edges.push((p, next[p]));
+                    p = next[p];
+                }
+            }
+        }
+    }
+
+    edges
+}
+
+/// Join the given fraction of dead ends to one of their neighbours, preferring neighbours that
+/// are also dead ends.
+fn braid(edges: &mut Vec<(usize, usize)>, p: f64, nx: usize, ny: usize, rng: &mut impl Rng) {
+    if p <= 0.0 {
+        return;
+    }
+
+    let mut links = vec![Vec::new(); nx * ny];
+    for &(a, b) in edges.iter() {
+        links[a].push(b);
+        links[b].push(a);
+    }
+
+    let mut dead_ends: Vec<usize> = (0..links.len()).filter(|&c| links[c].len() == 1).collect();
+    dead_ends.shuffle(rng);
+
+    for c in dead_ends {
+        if links[c].len() != 1 || !rng.random_bool(p.min(1.0)) {
+            continue;
+        }
+
+        let candidates: Vec<usize> = neighbours(c, nx, ny)
+            .into_iter()
+            .filter(|nb| !links[c].contains(nb))
+            .collect();
+        let other_dead_ends: Vec<usize> = candidates
+            .iter()
+            .copied()
+            .filter(|&nb| links[nb].len() == 1)
+            .collect();
+        let choices = if other_dead_ends.is_empty() {
+            &candidates
+        } else {
+            &other_dead_ends
+        };
+
+        if let Some(&nb) = choices.choose(rng) {
+            links[c].push(nb);
+            links[nb].push(c);
+            edges.push((c, nb));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n_reachable(edges: &[(usize, usize)], n: usize) -> usize {
+        let mut links = vec![Vec::new(); n];
+        for &(a, b) in edges {
+            links[a].push(b);
+            links[b].push(a);
+        }
+        let mut seen = vec![false; n];
+        let mut stack = vec![0];
+        seen[0] = true;
+        while let Some(c) = stack.pop() {
+            for &nb in links[c].iter() {
+                if !seen[nb] {
+                    seen[nb] = true;
+                    stack.push(nb);
+                }
+            }
+        }
+
+        seen.into_iter().filter(|&s| s).count()
+    }
+
+    #[test]
+    fn mazes_are_perfect() {
+        let (nx, ny) = (13, 7);
+        let algorithms = [
+            MazeAlgorithm::RecursiveBacktracker,
+            MazeAlgorithm::Prims,
+            MazeAlgorithm::Kruskals,
+            MazeAlgorithm::Ellers,
+            MazeAlgorithm::Wilsons,
+        ];
+
+        for algorithm in algorithms {
+            let edges = spanning_tree(algorithm, nx, ny, &mut rand::rng());
+
+            // a spanning tree connects every cell with exactly n - 1 edges so it has no loops
+            assert_eq!(edges.len(), nx * ny - 1, "{algorithm:?}");
+            assert_eq!(n_reachable(&edges, nx * ny), nx * ny, "{algorithm:?}");
+            for (a, b) in edges {
+                assert!(
+                    neighbours(a, nx, ny).contains(&b),
+                    "{algorithm:?}: {a} -> {b}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn full_braiding_removes_all_dead_ends() {
+        let (nx, ny) = (13, 7);
+        let mut rng = rand::rng();
+        let mut edges = spanning_tree(MazeAlgorithm::RecursiveBacktracker, nx, ny, &mut rng);
+        braid(&mut edges, 1.0, nx, ny, &mut rng);
+
+        let mut degree = vec![0; nx * ny];
+        for (a, b) in edges {
+            degree[a] += 1;
+            degree[b] += 1;
+        }
+        assert!(degree.iter().all(|&d| d > 1));
+    }
+}
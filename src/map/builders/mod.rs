@@ -1,8 +1,7 @@
 //! Map building algorithms
 use crate::{
     Pos,
-    grid::dijkstra_map,
-    map::{Map, MapTile},
+    map::{Map, map_tile::Terrain},
     state::State,
     ui::palette,
 };
@@ -11,16 +10,40 @@ use sdl2::pixels::Color;
 
 mod bsp;
 mod cellular_automata;
+mod drunkards_walk;
 mod forest;
+mod maze;
+mod noise_terrain;
+mod passes;
+mod pipeline;
+mod rogue;
+mod town;
+mod turmite;
 mod voronoi;
+mod water;
+mod wfc;
 
 pub use bsp::BspDungeon;
-pub use cellular_automata::{CaRule, CellularAutomata, StartingPosition};
-pub use forest::Forest;
+pub use cellular_automata::{CaRule, CellularAutomata};
+pub use drunkards_walk::{DrunkardsWalk, SpawnMode, Symmetry};
+pub use maze::{Maze, MazeAlgorithm};
+pub use noise_terrain::NoiseTerrain;
+pub use passes::{
+    CaSmoothing, ConnectRegions, CullUnreachable, EdgeExits, PlaceExit, Populate, RequireOpen,
+    RoomDoors, Scatter, StampPrefab, StartingPosition, VoronoiRegions,
+};
+pub use pipeline::{BuildData, MapPass, Pipeline};
+pub use rogue::RogueGrid;
+pub use town::{BuildingKind, Town};
+pub use turmite::{DEFAULT_TURMITE_RULE, Transition, TurmiteRule, Turmites, Turn};
 pub use voronoi::{voronoi_regions, voronoi_regions_from_seeds, voronoi_seeds};
+pub use water::{Crossings, Lakes, River};
+pub use wfc::{DEFAULT_WFC_SAMPLE, Wfc};
 
 pub trait BuildMap: Send + Sync {
-    fn bg_and_tiles(&self, state: &State<'_>) -> (Color, Vec<MapTile>);
+    /// The background colour and set of terrain used for maps made by this builder. The first
+    /// terrain in the set is used to fill the map before building.
+    fn bg_and_terrain(&self, state: &State<'_>) -> (Color, Terrain);
 
     #[allow(unused_variables)]
     fn init_map(&mut self, map: &mut Map) {}
@@ -46,11 +69,11 @@ pub trait BuildMap: Send + Sync {
             active: false,
         };
 
-        let (bg, tile_defs) = self.bg_and_tiles(state);
+        let (bg, terrain) = self.bg_and_terrain(state);
         let hidden = palette::HIDDEN;
 
         loop {
-            let mut map = Map::new(map_w, map_h, tile_defs.clone(), bg, hidden);
+            let mut map = Map::new(map_w, map_h, terrain.clone(), bg, hidden);
             self.init_map(&mut map);
             snapshots.push(&map);
 
@@ -73,11 +96,11 @@ pub trait BuildMap: Send + Sync {
             active: true,
         };
 
-        let (bg, tile_defs) = self.bg_and_tiles(state);
+        let (bg, terrain) = self.bg_and_terrain(state);
         let hidden = palette::HIDDEN;
 
         loop {
-            let mut map = Map::new(map_w, map_h, tile_defs.clone(), bg, hidden);
+            let mut map = Map::new(map_w, map_h, terrain.clone(), bg, hidden);
             self.init_map(&mut map);
             snapshots.push(&map);
 
@@ -90,15 +113,6 @@ pub trait BuildMap: Send + Sync {
             snapshots.inner.clear();
         }
     }
-
-    fn fill_unreachable_from(&self, from: &[(Pos, i32)], fill: usize, map: &mut Map) {
-        let dmap = dijkstra_map(&map.tiles, from, |p| map.tile_at(p).path_cost);
-        for (i, cost) in dmap.cells.into_iter().enumerate() {
-            if cost == i32::MAX {
-                map[i] = fill;
-            }
-        }
-    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -0,0 +1,120 @@
+use crate::{
+    Pos,
+    grid::{NoiseField, NoiseKind},
+    map::builders::{BuildData, MapPass, Pipeline, Scatter, Snapshots, StartingPosition},
+    state::State,
+    ui::palette,
+};
+use rand::Rng;
+
+const TREES: [&str; 5] = ["tree", "dark_tree", "pine", "dark_pine", "earth"];
+/// How far above sea level wet ground can be marsh
+const MARSH_HEIGHT: f32 = 0.03;
+
+/// Initial [MapPass] that lays out open terrain from elevation and moisture noise fields:
+/// low ground is water (with marsh around wet shorelines), high ground is rock and everything in
+/// between is forest or meadow depending on how wet it is.
+#[derive(Debug, Clone)]
+pub struct NoiseTerrain {
+    pub elevation: NoiseField,
+    pub moisture: NoiseField,
+    /// Use the seeds of the noise fields as given rather than picking new ones for each map
+    pub fixed_seed: bool,
+    /// Where the top left corner of the map is in the noise fields: maps built from fixed seeds
+    /// join up with each other if their origins are a map width or height apart
+    pub origin: Pos,
+    /// Elevation below which is water
+    pub sea_level: f32,
+    /// Elevation above which is rock
+    pub rock_level: f32,
+    /// Moisture above which low lying ground next to water is marsh
+    pub marsh_moisture: f32,
+    /// Moisture above which ground is forest rather than meadow
+    pub forest_moisture: f32,
+}
+
+impl Default for NoiseTerrain {
+    fn default() -> Self {
+        let mut elevation = NoiseField::new(NoiseKind::Simplex, 0);
+        elevation.scale = 48.0;
+        elevation.octaves = 5;
+        elevation.warp = 12.0;
+
+        let mut moisture = NoiseField::new(NoiseKind::Perlin, 1);
+        moisture.scale = 24.0;
+        moisture.octaves = 3;
+
+        Self {
+            elevation,
+            moisture,
+            fixed_seed: false,
+            origin: Pos::default(),
+            sea_level: 0.39,
+            rock_level: 0.65,
+            marsh_moisture: 0.5,
+            forest_moisture: 0.52,
+        }
+    }
+}
+
+impl NoiseTerrain {
+    /// The terrain name for a cell with the given elevation and moisture
+    pub fn terrain_for(&self, elevation: f32, moisture: f32) -> &'static str {
+        if elevation < self.sea_level {
+            "water"
+        } else if elevation < self.sea_level + MARSH_HEIGHT && moisture > self.marsh_moisture {
+            "marsh"
+        } else if elevation > self.rock_level {
+            "rock"
+        } else if moisture > self.forest_moisture {
+            "tree"
+        } else {
+            "meadow"
+        }
+    }
+}
+
+impl MapPass for NoiseTerrain {
+    fn terrain(&self, _: &State<'_>) -> Vec<String> {
+        ["water", "marsh", "rock", "tree", "meadow"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        _: &mut Snapshots,
+    ) -> Option<()> {
+        if !self.fixed_seed {
+            self.elevation.seed = state.rng.random();
+            self.moisture.seed = state.rng.random();
+        }
+
+        let map = &mut data.map;
+        let elevation = self.elevation.grid(self.origin, map.w, map.h);
+        let moisture = self.moisture.grid(self.origin, map.w, map.h);
+
+        for i in 0..map.len() {
+            let name = self.terrain_for(elevation.cells[i], moisture.cells[i]);
+            map.tiles[i] = map.terrain.idx(name);
+        }
+
+        Some(())
+    }
+}
+
+impl Pipeline {
+    /// Open country of meadows, forests, lakes and rocky hills
+    pub fn overworld(terrain: NoiseTerrain) -> Self {
+        Self::new(palette::FOREST_BG, "rock", "meadow")
+            .then(terrain)
+            .then(Scatter {
+                from: "tree",
+                to: TREES.to_vec(),
+                chance: 1.0,
+            })
+            .then(StartingPosition::Center)
+    }
+}
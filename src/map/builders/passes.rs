@@ -0,0 +1,546 @@
+//! Meta passes for use in a [Pipeline](super::Pipeline) after the map has been laid out.
+use crate::{
+    Pos,
+    grid::{a_star, connected_components, descend, dijkstra_map},
+    map::{
+        Map,
+        builders::{
+            BuildData, CaRule, MapPass, Snapshots,
+            cellular_automata::{filled_cells, set_cells},
+            voronoi_regions,
+        },
+        features::Feature,
+    },
+    state::State,
+};
+use rand::{Rng, seq::IndexedRandom};
+use sdl2::rect::Rect;
+
+/// The cost of digging through a blocked cell relative to walking over an open one when joining
+/// up parts of the map
+const DIG_COST: i32 = 4;
+
+/// Where on the map to look for a starting position
+#[derive(Debug)]
+pub enum StartingPosition {
+    North,
+    South,
+    East,
+    West,
+    Center,
+}
+
+impl StartingPosition {
+    /// Scan outwards from the chosen edge (or center) of the map for an open cell
+    pub fn locate(&self, map: &Map) -> Option<Pos> {
+        let (w, h) = (map.w as i32, map.h as i32);
+
+        let (x, y, dx, dy) = match self {
+            Self::North => (w / 2, 0, 1, 0),
+            Self::South => (w / 2, h - 1, 1, 0),
+            Self::East => (w - 1, h / 2, 0, -1),
+            Self::West => (0, h / 2, 0, -1),
+            Self::Center => (w / 2, h / 2, -1, 0),
+        };
+
+        for i in [1, -1] {
+            let mut pos = Pos::new(x, y);
+            let delta = Pos::new(dx * i, dy * i);
+
+            while map.contains_pos(pos) {
+                if !map.tile_at(pos).blocks_movement() {
+                    return Some(pos);
+                }
+
+                pos += delta;
+            }
+        }
+
+        None
+    }
+}
+
+/// Set the starting position for the map if an earlier pass hasn't already
+impl MapPass for StartingPosition {
+    fn run(&mut self, data: &mut BuildData, _: &mut State<'_>, _: &mut Snapshots) -> Option<()> {
+        if data.start.is_none() {
+            data.start = Some(self.locate(&data.map)?);
+        }
+
+        Some(())
+    }
+}
+
+/// Run further iterations of a cellular automata rule over the map to smooth it out
+pub struct CaSmoothing {
+    pub rule: CaRule,
+    pub iterations: usize,
+}
+
+impl MapPass for CaSmoothing {
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        _: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<()> {
+        let mut filled = filled_cells(data);
+        for i in 0..self.iterations {
+            filled = self.rule.run(i, &filled);
+            set_cells(&filled, data);
+            snapshots.push(&data.map);
+        }
+
+        Some(())
+    }
+}
+
+/// Fill in every cell that can't be reached from the starting position with the wall terrain
+pub struct CullUnreachable;
+
+impl MapPass for CullUnreachable {
+    fn run(&mut self, data: &mut BuildData, _: &mut State<'_>, _: &mut Snapshots) -> Option<()> {
+        let map = &mut data.map;
+        let dmap = dijkstra_map(&map.tiles, &[(data.start?, 0)], |p| {
+            map.tile_at(p).path_cost
+        });
+
+        for (i, cost) in dmap.cells.into_iter().enumerate() {
+            if cost == i32::MAX {
+                map[i] = data.wall;
+                map.features
+                    .remove(&Pos::new((i % map.w) as i32, (i / map.w) as i32));
+            }
+        }
+
+        Some(())
+    }
+}
+
+/// Join every region of open cells to the region containing the starting position by tunnelling
+/// through whatever is in the way, repeatedly connecting the nearest region along the route that
+/// needs the least digging. Regions smaller than the given number of cells are filled in with the
+/// wall terrain instead.
+pub struct ConnectRegions(pub usize);
+
+impl MapPass for ConnectRegions {
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        _: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<()> {
+        connect_regions(data, self.0, snapshots)
+    }
+}
+
+fn connect_regions(data: &mut BuildData, min_size: usize, snapshots: &mut Snapshots) -> Option<()> {
+    let (start, wall, floor) = (data.start?, data.wall, data.floor);
+    let map = &mut data.map;
+    let mut regions = connected_components(&map.tiles, |p| !map.tile_at(p).blocks_movement());
+
+    regions.retain(|r| {
+        let keep = r.len() >= min_size || r.contains(&start);
+        if !keep {
+            for p in r {
+                map.tiles[*p] = wall;
+                map.features.remove(p);
+            }
+        }
+        keep
+    });
+    snapshots.push(map);
+
+    let i = regions.iter().position(|r| r.contains(&start))?;
+    let mut connected = regions.swap_remove(i);
+
+    while !regions.is_empty() {
+        let targets: Vec<(Pos, i32)> = connected.iter().map(|&p| (p, 0)).collect();
+        let dmap = dijkstra_map(&map.tiles, &targets, |p| {
+            if map.features.contains_key(&p) {
+                None
+            } else if map.tile_at(p).blocks_movement() {
+                Some(DIG_COST)
+            } else {
+                Some(1)
+            }
+        });
+
+        let (i, p) = regions
+            .iter()
+            .enumerate()
+            .flat_map(|(i, r)| r.iter().map(move |&p| (i, p)))
+            .min_by_key(|&(_, p)| dmap[p])?;
+        let region = regions.swap_remove(i);
+        if dmap[p] == i32::MAX {
+            // walled off by map features so there is no way to reach it
+            for p in region {
+                map.tiles[p] = wall;
+                map.features.remove(&p);
+            }
+            continue;
+        }
+
+        for q in descend(&dmap, p) {
+            if map.tile_at(q).blocks_movement() {
+                map.tiles[q] = floor;
+            }
+            connected.push(q);
+        }
+        connected.extend(region);
+        snapshots.push(map);
+    }
+
+    Some(())
+}
+
+/// Reject maps where less than the given fraction of cells are open
+pub struct RequireOpen(pub f32);
+
+impl MapPass for RequireOpen {
+    fn run(&mut self, data: &mut BuildData, _: &mut State<'_>, _: &mut Snapshots) -> Option<()> {
+        let map = &data.map;
+        let n_open = map
+            .cells
+            .iter()
+            .filter(|&&idx| !map.terrain[idx].blocks_movement())
+            .count();
+        let p_open = n_open as f32 / map.cells.len() as f32;
+
+        (p_open >= self.0).then_some(())
+    }
+}
+
+/// Split the open cells of the map into the given number of Voronoi regions
+pub struct VoronoiRegions(pub usize);
+
+impl MapPass for VoronoiRegions {
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        _: &mut Snapshots,
+    ) -> Option<()> {
+        let map = &data.map;
+        let points = map.cells.iter().enumerate().flat_map(|(i, &idx)| {
+            if map.terrain[idx].blocks_movement() {
+                None
+            } else {
+                Some(Pos::new((i % map.w) as i32, (i / map.w) as i32))
+            }
+        });
+
+        data.regions = voronoi_regions(self.0, map.w, map.h, points, &mut state.rng);
+
+        Some(())
+    }
+}
+
+/// Replace a fraction of the cells of one terrain with a random choice of others
+pub struct Scatter {
+    pub from: &'static str,
+    pub to: Vec<&'static str>,
+    pub chance: f64,
+}
+
+impl MapPass for Scatter {
+    fn terrain(&self, _: &State<'_>) -> Vec<String> {
+        self.to.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        _: &mut Snapshots,
+    ) -> Option<()> {
+        let map = &mut data.map;
+        let from = map.terrain.idx(self.from);
+        let to: Vec<usize> = self.to.iter().map(|t| map.terrain.idx(t)).collect();
+
+        for tile in map.tiles.cells.iter_mut() {
+            if *tile == from && state.rng.random_bool(self.chance) {
+                *tile = *to.choose(&mut state.rng).unwrap();
+            }
+        }
+
+        Some(())
+    }
+}
+
+/// Stamp the named prefab from data/prefabs somewhere it connects to the rest of the map, if there
+/// is room for it.
+pub struct StampPrefab(pub &'static str);
+
+impl MapPass for StampPrefab {
+    fn terrain(&self, state: &State<'_>) -> Vec<String> {
+        match state.prefabs.get(self.0) {
+            Some(prefab) => prefab
+                .terrain_names()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        _: &mut Snapshots,
+    ) -> Option<()> {
+        let prefab = match state.prefabs.get(self.0) {
+            Some(prefab) => prefab.randomly_transformed(&mut *state.rng),
+            None => return Some(()),
+        };
+
+        if let Some(at) = prefab.find_placement(&data.map, data.start?, &mut *state.rng) {
+            let stamped = prefab.stamp(at, &mut data.map);
+            data.spawns.extend(stamped.mobs);
+        }
+
+        Some(())
+    }
+}
+
+/// Place doors wherever a single width corridor enters one of the rooms
+pub struct RoomDoors;
+
+impl MapPass for RoomDoors {
+    fn terrain(&self, _: &State<'_>) -> Vec<String> {
+        Feature::terrain_names().map(String::from).to_vec()
+    }
+
+    fn run(&mut self, data: &mut BuildData, _: &mut State<'_>, _: &mut Snapshots) -> Option<()> {
+        for r in data.rooms.iter() {
+            place_doors(*r, &mut data.map);
+        }
+
+        Some(())
+    }
+}
+
+fn place_doors(r: Rect, map: &mut Map) {
+    let (x1, y1, x2, y2) = (r.x, r.y, r.x + r.w - 1, r.y + r.h - 1);
+
+    // (position just outside of the room, direction along the room edge, direction away from it)
+    let edges = (x1..=x2)
+        .flat_map(|x| {
+            [
+                (Pos::new(x, y1 - 1), Pos::new(1, 0), Pos::new(0, -1)),
+                (Pos::new(x, y2 + 1), Pos::new(1, 0), Pos::new(0, 1)),
+            ]
+        })
+        .chain((y1..=y2).flat_map(|y| {
+            [
+                (Pos::new(x1 - 1, y), Pos::new(0, 1), Pos::new(-1, 0)),
+                (Pos::new(x2 + 1, y), Pos::new(0, 1), Pos::new(1, 0)),
+            ]
+        }));
+
+    let open = |p: Pos| map.contains_pos(p) && !map.tile_at(p).blocks_movement();
+    let wall = |p: Pos| map.tile_at(p).blocks_movement() && !map.features.contains_key(&p);
+    let doors: Vec<Pos> = edges
+        .filter(|&(p, along, out)| {
+            let back = Pos::new(-along.x, -along.y);
+            open(p) && open(p + out) && wall(p + along) && wall(p + back)
+        })
+        .map(|(p, _, _)| p)
+        .collect();
+
+    for p in doors {
+        map.add_feature(p, Feature::door());
+    }
+}
+
+/// Place an exit at the reachable cell furthest from the starting position
+pub struct PlaceExit;
+
+impl MapPass for PlaceExit {
+    fn terrain(&self, _: &State<'_>) -> Vec<String> {
+        vec!["stairs_down".to_string()]
+    }
+
+    fn run(&mut self, data: &mut BuildData, _: &mut State<'_>, _: &mut Snapshots) -> Option<()> {
+        let map = &mut data.map;
+        let dmap = dijkstra_map(&map.tiles, &[(data.start?, 0)], |p| {
+            map.tile_at(p).path_cost
+        });
+        let (i, _) = dmap
+            .cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cost)| **cost != i32::MAX)
+            .max_by_key(|(_, cost)| **cost)?;
+
+        let exit = Pos::new((i % map.w) as i32, (i / map.w) as i32);
+        map.tiles[exit] = map.terrain.idx("stairs_down");
+        data.exit = Some(exit);
+
+        Some(())
+    }
+}
+
+/// Carve a path from the middle of each edge of the map to the starting position so that the
+/// player is able to walk off the map in any direction. Paths prefer to follow open ground and
+/// never cut through map features.
+pub struct EdgeExits;
+
+impl MapPass for EdgeExits {
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        _: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<()> {
+        let map = &mut data.map;
+        let start = data.start?;
+        let (w, h) = (map.w as i32, map.h as i32);
+
+        for edge in [
+            Pos::new(w / 2, 0),
+            Pos::new(w / 2, h - 1),
+            Pos::new(0, h / 2),
+            Pos::new(w - 1, h / 2),
+        ] {
+            let path = a_star(edge, start, &map.tiles, |p| {
+                if map.features.contains_key(&p) {
+                    None
+                } else if map.tile_at(p).blocks_movement() {
+                    Some(DIG_COST)
+                } else {
+                    Some(1)
+                }
+            });
+            if path.is_empty() {
+                return None;
+            }
+
+            for p in std::iter::once(edge).chain(path) {
+                if map.tile_at(p).blocks_movement() {
+                    map.tiles[p] = data.floor;
+                }
+            }
+            snapshots.push(map);
+        }
+
+        Some(())
+    }
+}
+
+/// Choose where mobs should spawn once the map is complete
+pub enum Populate {
+    /// The given mob in the center of each room
+    EachRoom(&'static str),
+    /// The given mob somewhere in each region
+    EachRegion(&'static str),
+    /// The given mob somewhere in the region furthest from the starting position
+    FurthestRegion(&'static str),
+}
+
+impl MapPass for Populate {
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        _: &mut Snapshots,
+    ) -> Option<()> {
+        let map = &data.map;
+        let open = |p: &&Pos| !map.tile_at(**p).blocks_movement();
+        let mut pick = |r: &[Pos]| {
+            r.iter()
+                .filter(open)
+                .collect::<Vec<_>>()
+                .choose(&mut state.rng)
+                .map(|p| **p)
+        };
+
+        match *self {
+            Self::EachRoom(mob) => {
+                for r in data.rooms.iter() {
+                    let c = r.center();
+                    data.spawns
+                        .push((Pos::new(c.x, c.y), Some(mob.to_string())));
+                }
+            }
+
+            Self::EachRegion(mob) => {
+                for r in data.regions.iter() {
+                    if let Some(p) = pick(r) {
+                        data.spawns.push((p, Some(mob.to_string())));
+                    }
+                }
+            }
+
+            Self::FurthestRegion(mob) => {
+                let start = data.start?;
+                let p = data
+                    .regions
+                    .iter()
+                    .flat_map(|r| pick(r))
+                    .max_by(|a, b| a.fdist(start).total_cmp(&b.fdist(start)));
+                if let Some(p) = p {
+                    data.spawns.push((p, Some(mob.to_string())));
+                }
+            }
+        }
+
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::map_tile::Terrain;
+    use sdl2::pixels::Color;
+
+    #[test]
+    fn doors_are_placed_where_corridors_enter_rooms() {
+        let terrain = Terrain::for_tests(
+            [("stone_wall", None), ("stone_floor", Some(1))]
+                .into_iter()
+                .chain(Feature::terrain_names().map(|name| (name, None))),
+        );
+        let mut map = Map::new(20, 10, terrain, Color::BLACK, Color::BLACK);
+        let floor = map.terrain.idx("stone_floor");
+        let room = Rect::new(2, 2, 5, 5);
+        map.carve_rect(room, floor);
+        map.carve_h_tunnel(7, 15, 4, floor); // enters the east wall
+        map.carve_v_tunnel(1, 8, 8, floor); // runs alongside the east wall
+
+        place_doors(room, &mut map);
+
+        let doors: Vec<Pos> = map.features.keys().copied().collect();
+        assert_eq!(doors, vec![Pos::new(7, 4)]);
+        assert_eq!(map[Pos::new(7, 4)], map.terrain.idx("door"));
+    }
+
+    #[test]
+    fn regions_are_connected_or_filled() {
+        let terrain = Terrain::for_tests([("stone_wall", None), ("stone_floor", Some(1))]);
+        let map = Map::new(30, 10, terrain, Color::BLACK, Color::BLACK);
+        let mut data = BuildData::new(map, "stone_wall", "stone_floor");
+        let (wall, floor) = (data.wall, data.floor);
+        data.map.carve_rect(Rect::new(1, 1, 6, 6), floor);
+        data.map.carve_rect(Rect::new(12, 2, 5, 5), floor);
+        data.map.carve_rect(Rect::new(22, 3, 6, 6), floor);
+        data.map.carve_rect(Rect::new(10, 8, 2, 1), floor); // too small to keep
+        let mut snapshots = Snapshots {
+            inner: Vec::new(),
+            active: false,
+        };
+
+        let start = Pos::new(3, 3);
+        data.start = Some(start);
+        connect_regions(&mut data, 4, &mut snapshots).unwrap();
+
+        let map = &data.map;
+        let dmap = dijkstra_map(&map.tiles, &[(start, 0)], |p| map.tile_at(p).path_cost);
+        assert!(dmap[Pos::new(24, 5)] < i32::MAX);
+        assert_eq!(map[Pos::new(10, 8)], wall);
+        let n_open = map.cells.iter().filter(|&&idx| idx == floor).count();
+        assert!(n_open < 36 + 25 + 36 + 5 + 6 + 4, "tunnels should be short");
+    }
+}
@@ -0,0 +1,166 @@
+//! Composable map building: an initial pass lays out the map and is followed by a chain of "meta"
+//! passes that refine it, place features and decide where things should spawn.
+use crate::{
+    Pos,
+    map::{
+        Map,
+        builders::{BuildMap, Snapshots},
+        map_tile::Terrain,
+    },
+    mob::{Mob, Resident},
+    state::State,
+};
+use hecs::Entity;
+use rand::seq::IndexedRandom;
+use sdl2::{pixels::Color, rect::Rect};
+
+/// The map being built along with the metadata shared between passes
+#[derive(Debug, Clone)]
+pub struct BuildData {
+    pub map: Map,
+    /// The terrain the map is filled with before building
+    pub wall: usize,
+    /// The terrain used for open ground carved out of the walls
+    pub floor: usize,
+    pub start: Option<Pos>,
+    pub exit: Option<Pos>,
+    pub rooms: Vec<Rect>,
+    pub regions: Vec<Vec<Pos>>,
+    /// Mobs to spawn once the map is complete: a specific mob from data/mobs or any of them
+    pub spawns: Vec<(Pos, Option<String>)>,
+    /// Homes and workplaces for the mobs spawned at the given positions
+    pub residents: Vec<(Pos, Resident)>,
+}
+
+impl BuildData {
+    /// # Panics
+    /// Panics if either of the named terrains is not available on the map
+    pub fn new(map: Map, wall: &str, floor: &str) -> Self {
+        Self {
+            wall: map.terrain.idx(wall),
+            floor: map.terrain.idx(floor),
+            map,
+            start: None,
+            exit: None,
+            rooms: Vec::new(),
+            regions: Vec::new(),
+            spawns: Vec::new(),
+            residents: Vec::new(),
+        }
+    }
+}
+
+/// A single step in a [Pipeline]
+pub trait MapPass: Send + Sync {
+    /// The terrain (see data/tiles) that this pass places, which needs to be available on the map
+    fn terrain(&self, _state: &State<'_>) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Run this pass over the map, returning None if the map is unusable and should be rebuilt
+    /// from scratch.
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<()>;
+}
+
+/// A sequence of [MapPass]es run in order to build a map.
+///
+/// The map starts out filled with the pipeline's wall terrain and a snapshot is taken after each
+/// pass. The pipeline fails (and is retried) if no pass sets a starting position.
+pub struct Pipeline {
+    bg: Color,
+    wall: &'static str,
+    floor: &'static str,
+    passes: Vec<Box<dyn MapPass>>,
+    spawns: Vec<(Pos, Option<String>)>,
+    residents: Vec<(Pos, Resident)>,
+}
+
+impl Pipeline {
+    /// A pipeline for maps that are carved out of the `wall` terrain, leaving `floor` behind
+    pub fn new(bg: Color, wall: &'static str, floor: &'static str) -> Self {
+        Self {
+            bg,
+            wall,
+            floor,
+            passes: Vec::new(),
+            spawns: Vec::new(),
+            residents: Vec::new(),
+        }
+    }
+
+    /// Add a pass to the end of the pipeline
+    pub fn then(mut self, pass: impl MapPass + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+}
+
+impl BuildMap for Pipeline {
+    fn bg_and_terrain(&self, state: &State<'_>) -> (Color, Terrain) {
+        let mut names = vec![self.wall.to_string(), self.floor.to_string()];
+        for name in self.passes.iter().flat_map(|p| p.terrain(state)) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        let names: Vec<&str> = names.iter().map(|s| s.as_str()).collect();
+
+        (self.bg, state.terrain(&names))
+    }
+
+    fn build(
+        &mut self,
+        map: Map,
+        state: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<(Pos, Map)> {
+        let mut data = BuildData::new(map, self.wall, self.floor);
+        for pass in self.passes.iter_mut() {
+            pass.run(&mut data, state, snapshots)?;
+            snapshots.push(&data.map);
+        }
+
+        self.spawns = data.spawns;
+        self.residents = data.residents;
+
+        Some((data.start?, data.map))
+    }
+
+    fn populate(&mut self, state: &mut State<'_>) -> Vec<Entity> {
+        let mut entities = Vec::new();
+
+        for (p, name) in std::mem::take(&mut self.spawns) {
+            let name = match name {
+                Some(name) => name,
+                None => {
+                    let names: Vec<&String> = state
+                        .mob_specs
+                        .values()
+                        .filter(|spec| spec.spawn_anywhere)
+                        .map(|spec| &spec.name)
+                        .collect();
+                    match names.choose(&mut state.rng) {
+                        Some(name) => name.to_string(),
+                        None => continue,
+                    }
+                }
+            };
+            let entity = match Mob::spawn_named(&name, p.x, p.y, state) {
+                Some(entity) => entity,
+                None => continue,
+            };
+            if let Some(&(_, resident)) = self.residents.iter().find(|(rp, _)| *rp == p) {
+                state.world.insert_one(entity, resident).unwrap();
+            }
+            entities.push(entity);
+        }
+        self.residents.clear();
+
+        entities
+    }
+}
@@ -0,0 +1,164 @@
+//! The room layout used by the original Rogue
+//!   https://web.archive.org/web/20131025132021/http://kuoi.org/~kamikaze/GameDesign/art07_rogue_dungeon.php
+use crate::{
+    Pos,
+    map::{
+        Map,
+        builders::{
+            BuildData, MapPass, MazeAlgorithm, Pipeline, Populate, RoomDoors, Snapshots,
+            maze::{neighbours, spanning_tree},
+        },
+    },
+    state::State,
+    ui::palette,
+};
+use rand::{Rng, seq::IndexedRandom};
+use sdl2::rect::Rect;
+
+/// Cells need to be big enough for a 3x3 room with a gap for corridors on either side
+const MIN_CELL_SIZE: usize = 5;
+
+/// Initial [MapPass] that splits the map into a grid of cells, each holding either a room or a
+/// "gone" room that is just a junction between corridors. Neighbouring cells are joined so that
+/// every room can be reached with a few extra corridors to make loops, and the start and stairs
+/// are placed in different rooms. The grid is shrunk if the map is too small to fit it.
+#[derive(Debug, Clone)]
+pub struct RogueGrid {
+    pub cols: usize,
+    pub rows: usize,
+    /// The chance of each cell being a corridor junction rather than a room
+    pub p_gone: f64,
+    /// How many extra corridors to add once every room is connected
+    pub extra_loops: usize,
+}
+
+impl Default for RogueGrid {
+    fn default() -> Self {
+        Self {
+            cols: 3,
+            rows: 3,
+            p_gone: 0.2,
+            extra_loops: 2,
+        }
+    }
+}
+
+impl MapPass for RogueGrid {
+    fn terrain(&self, _: &State<'_>) -> Vec<String> {
+        vec!["stairs_down".to_string()]
+    }
+
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<()> {
+        let rng = &mut *state.rng;
+        let floor = data.floor;
+        let map = &mut data.map;
+        let cols = self.cols.clamp(1, (map.w / MIN_CELL_SIZE).max(1));
+        let rows = self.rows.clamp(1, (map.h / MIN_CELL_SIZE).max(1));
+        let (cw, ch) = (map.w / cols, map.h / rows);
+        assert!(
+            cw >= MIN_CELL_SIZE && ch >= MIN_CELL_SIZE && cols * rows > 1,
+            "a {}x{} map is too small for a rogue grid",
+            map.w,
+            map.h
+        );
+
+        // each cell is either a room or a single cell junction but the first is always a room
+        let mut cells = Vec::with_capacity(cols * rows);
+        let mut rooms = Vec::new();
+        for i in 0..cols * rows {
+            let (x, y) = (((i % cols) * cw) as i32, ((i / cols) * ch) as i32);
+            let r = if i > 0 && rng.random_bool(self.p_gone) {
+                let jx = rng.random_range(x + 1..x + cw as i32 - 1);
+                let jy = rng.random_range(y + 1..y + ch as i32 - 1);
+                Rect::new(jx, jy, 1, 1)
+            } else {
+                let w = rng.random_range(3..=cw as i32 - 2);
+                let h = rng.random_range(3..=ch as i32 - 2);
+                let rx = rng.random_range(x + 1..=x + cw as i32 - 1 - w);
+                let ry = rng.random_range(y + 1..=y + ch as i32 - 1 - h);
+                let r = Rect::new(rx, ry, w as u32, h as u32);
+                rooms.push(r);
+                r
+            };
+            map.carve_rect(r, floor);
+            cells.push(r);
+        }
+        if rooms.len() < 2 {
+            return None;
+        }
+        snapshots.push(map);
+
+        let mut edges = spanning_tree(MazeAlgorithm::Prims, cols, rows, rng);
+        for _ in 0..self.extra_loops {
+            let a = rng.random_range(0..cells.len());
+            let unjoined: Vec<usize> = neighbours(a, cols, rows)
+                .into_iter()
+                .filter(|&b| !edges.contains(&(a, b)) && !edges.contains(&(b, a)))
+                .collect();
+            if let Some(&b) = unjoined.choose(rng) {
+                edges.push((a, b));
+            }
+        }
+
+        for (a, b) in edges {
+            let (a, b) = (a.min(b), a.max(b));
+            let horizontal = a / cols == b / cols;
+            connect(cells[a], cells[b], horizontal, floor, rng, map);
+            snapshots.push(map);
+        }
+
+        let mut chosen = rooms.choose_multiple(rng, 2);
+        let (start, exit) = (chosen.next()?, chosen.next()?);
+        let c = start.center();
+        let exit = Pos::new(
+            rng.random_range(exit.x..exit.x + exit.w),
+            rng.random_range(exit.y..exit.y + exit.h),
+        );
+        map.tiles[exit] = map.terrain.idx("stairs_down");
+
+        data.start = Some(Pos::new(c.x, c.y));
+        data.exit = Some(exit);
+        data.rooms = rooms;
+
+        Some(())
+    }
+}
+
+/// Join two neighbouring cells with a corridor that runs out from one, turns once in the gap
+/// between them and then runs into the other.
+fn connect(a: Rect, b: Rect, horizontal: bool, floor: usize, rng: &mut impl Rng, map: &mut Map) {
+    let mut point = |r: Rect| {
+        Pos::new(
+            rng.random_range(r.x..r.x + r.w),
+            rng.random_range(r.y..r.y + r.h),
+        )
+    };
+    let (pa, pb) = (point(a), point(b));
+
+    if horizontal {
+        let mid = rng.random_range(a.x + a.w..b.x);
+        map.carve_h_tunnel(pa.x, mid, pa.y, floor);
+        map.carve_v_tunnel(pa.y, pb.y, mid, floor);
+        map.carve_h_tunnel(mid, pb.x, pb.y, floor);
+    } else {
+        let mid = rng.random_range(a.y + a.h..b.y);
+        map.carve_v_tunnel(pa.y, mid, pa.x, floor);
+        map.carve_h_tunnel(pa.x, pb.x, mid, floor);
+        map.carve_v_tunnel(mid, pb.y, pb.x, floor);
+    }
+}
+
+impl Pipeline {
+    /// A Rogue style grid of rooms with doors and a pixie in every room
+    pub fn rogue(grid: RogueGrid) -> Self {
+        Self::new(palette::BLACK, "stone_wall", "stone_floor")
+            .then(grid)
+            .then(RoomDoors)
+            .then(Populate::EachRoom("pixie"))
+    }
+}
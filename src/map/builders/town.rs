@@ -0,0 +1,532 @@
+//! Towns laid out along a grid of streets with a building on each plot of land
+use crate::{
+    Pos,
+    grid::dijkstra_map,
+    map::{
+        Map,
+        builders::{BuildData, MapPass, Pipeline, Scatter, Snapshots},
+        features::Feature,
+        prefab::{Prefab, Stamped},
+    },
+    mob::Resident,
+    state::State,
+    ui::palette,
+};
+use rand::{Rng, seq::IndexedRandom};
+use sdl2::rect::Rect;
+
+const TREES: [&str; 4] = ["tree", "dark_tree", "pine", "dark_pine"];
+const FURNITURE: [&str; 7] = [
+    "table", "bed", "counter", "anvil", "shelf", "barrel", "hearth",
+];
+/// The mob from data/mobs spawned in each house
+const TOWNSFOLK: &str = "townsfolk";
+/// Rooms larger than this are split in two by an internal wall
+const MAX_ROOM_AREA: i32 = 24;
+const MIN_ROOM: i32 = 3;
+/// How many spots to try for each piece of furniture before leaving it out
+const FURNITURE_ATTEMPTS: usize = 20;
+
+/// The purpose of a building in a [Town]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildingKind {
+    GuildHall,
+    Tavern,
+    Smithy,
+    Apothecary,
+    House,
+}
+
+impl BuildingKind {
+    /// The buildings that every town has one of, in the order that they are given plots
+    pub const NAMED: [Self; 4] = [
+        Self::GuildHall,
+        Self::Tavern,
+        Self::Smithy,
+        Self::Apothecary,
+    ];
+
+    /// The prefab in data/prefabs stamped for this building if it exists and fits on the plot:
+    /// without one the building is generated instead.
+    pub fn prefab(&self) -> Option<&'static str> {
+        match self {
+            Self::GuildHall => Some("guild_hall"),
+            Self::Tavern => Some("tavern"),
+            Self::Smithy => Some("smithy"),
+            Self::Apothecary => Some("apothecary"),
+            Self::House => None,
+        }
+    }
+
+    /// The wall and floor terrain for generated buildings
+    fn materials(&self) -> (&'static str, &'static str) {
+        match self {
+            Self::GuildHall | Self::Smithy => ("stone_wall", "stone_floor"),
+            _ => ("plank_wall", "wood_floor"),
+        }
+    }
+
+    fn furniture(&self) -> &'static [&'static str] {
+        match self {
+            Self::GuildHall => &["hearth", "table", "table", "table", "shelf", "shelf"],
+            Self::Tavern => &["counter", "barrel", "barrel", "table", "table", "hearth"],
+            Self::Smithy => &["anvil", "hearth", "barrel", "shelf"],
+            Self::Apothecary => &["counter", "shelf", "shelf", "shelf", "table"],
+            Self::House => &["bed", "table", "hearth", "shelf"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Building {
+    kind: BuildingKind,
+    rect: Rect,
+}
+
+/// Initial [MapPass] for a town in a forest clearing. A main street runs across the map with
+/// cross streets running north to south, and the blocks between them are divided into plots. The
+/// named buildings take the largest plots and the rest are houses (or left as gardens), each of
+/// which is home to townsfolk who work in one of the named buildings.
+#[derive(Debug, Clone)]
+pub struct Town {
+    /// How much of the forest to leave around the edge of the map
+    pub margin: i32,
+    pub street_width: i32,
+    /// The smallest and largest gap between cross streets
+    pub block_width: (i32, i32),
+    /// The smallest plot (w, h) to build on
+    pub min_plot: (i32, i32),
+    /// Plots larger than this (w, h) are split in two
+    pub max_plot: (i32, i32),
+    /// The chance of a plot without a named building being left as a garden
+    pub p_garden: f64,
+    pub max_residents: usize,
+}
+
+impl Default for Town {
+    fn default() -> Self {
+        Self {
+            margin: 2,
+            street_width: 3,
+            block_width: (16, 24),
+            min_plot: (8, 7),
+            max_plot: (16, 16),
+            p_garden: 0.2,
+            max_residents: 2,
+        }
+    }
+}
+
+impl MapPass for Town {
+    fn terrain(&self, state: &State<'_>) -> Vec<String> {
+        let mut names: Vec<String> = ["road", "plank_wall", "wood_floor", "stone_wall"]
+            .into_iter()
+            .chain(["stone_floor"])
+            .chain(FURNITURE)
+            .chain(Feature::terrain_names())
+            .map(String::from)
+            .collect();
+
+        for kind in BuildingKind::NAMED {
+            if let Some(prefab) = kind.prefab().and_then(|name| state.prefabs.get(name)) {
+                names.extend(prefab.terrain_names().into_iter().map(String::from));
+            }
+        }
+
+        names
+    }
+
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<()> {
+        let rng = &mut *state.rng;
+        let map = &mut data.map;
+        let (w, h) = (map.w as i32, map.h as i32);
+        let (m, sw) = (self.margin, self.street_width);
+        let road = map.terrain.idx("road");
+
+        map.carve_rect(
+            Rect::new(m, m, (w - 2 * m) as u32, (h - 2 * m) as u32),
+            data.floor,
+        );
+
+        // the streets run right across the map so that the town can be left in any direction
+        let sy = (h - sw) / 2;
+        map.carve_rect(Rect::new(0, sy, w as u32, sw as u32), road);
+        let mut xs = vec![m];
+        let mut x = m + rng.random_range(self.block_width.0..=self.block_width.1);
+        while x + sw + self.block_width.0 <= w - m {
+            map.carve_rect(Rect::new(x, 0, sw as u32, h as u32), road);
+            xs.extend([x, x + sw]);
+            x += sw + rng.random_range(self.block_width.0..=self.block_width.1);
+        }
+        xs.push(w - m);
+        snapshots.push(map);
+
+        let mut plots = Vec::new();
+        for block in xs.chunks(2) {
+            for (y1, y2) in [(m, sy), (sy + sw, h - m)] {
+                let r = Rect::new(block[0], y1, (block[1] - block[0]) as u32, (y2 - y1) as u32);
+                self.split_plot(r, rng, &mut plots);
+            }
+        }
+        if plots.len() <= BuildingKind::NAMED.len() {
+            return None;
+        }
+        plots.sort_by_key(|r| -(r.w * r.h));
+
+        let mut buildings = Vec::with_capacity(plots.len());
+        for (i, plot) in plots.into_iter().enumerate() {
+            let kind = match BuildingKind::NAMED.get(i) {
+                Some(&kind) => kind,
+                None if rng.random_bool(self.p_garden) => continue,
+                None => BuildingKind::House,
+            };
+
+            // leave a yard around each building so that they can all be reached
+            let r = Rect::new(plot.x + 1, plot.y + 1, plot.w as u32 - 2, plot.h as u32 - 2);
+            let stamped = kind
+                .prefab()
+                .and_then(|name| state.prefabs.get(name))
+                .and_then(|prefab| stamp_prefab(prefab, r, map, rng));
+            let rect = match stamped {
+                Some((rect, stamped)) => {
+                    data.spawns.extend(stamped.mobs);
+                    rect
+                }
+                None => build(kind, r, road, map, rng),
+            };
+            buildings.push(Building { kind, rect });
+            snapshots.push(map);
+        }
+
+        // each named building gets a worker before any of them get a second one
+        let workplaces: Vec<Rect> = buildings
+            .iter()
+            .filter(|b| b.kind != BuildingKind::House)
+            .map(|b| b.rect)
+            .collect();
+        let mut jobs = workplaces.iter().cycle();
+        for home in buildings.iter().filter(|b| b.kind == BuildingKind::House) {
+            let mut beds: Vec<Pos> = interior(home.rect)
+                .filter(|&p| !map.tile_at(p).blocks_movement())
+                .collect();
+            for _ in 0..rng.random_range(1..=self.max_residents) {
+                if beds.is_empty() {
+                    break;
+                }
+                let p = beds.swap_remove(rng.random_range(0..beds.len()));
+                let work = jobs.next().copied().unwrap_or(home.rect);
+                data.spawns.push((p, Some(TOWNSFOLK.to_string())));
+                data.residents.push((
+                    p,
+                    Resident {
+                        home: home.rect,
+                        work,
+                    },
+                ));
+            }
+        }
+
+        data.start = Some(Pos::new(w / 2, sy + sw / 2));
+        data.rooms = buildings.iter().map(|b| b.rect).collect();
+
+        Some(())
+    }
+}
+
+impl Town {
+    /// Recursively split a plot of land until no side is larger than the max plot size, dropping
+    /// any that end up too small to build on.
+    fn split_plot(&self, r: Rect, rng: &mut impl Rng, plots: &mut Vec<Rect>) {
+        let (w, h) = (r.w, r.h);
+        let ((min_w, min_h), (max_w, max_h)) = (self.min_plot, self.max_plot);
+
+        if w > max_w && w >= 2 * min_w {
+            let split = rng.random_range(min_w..=w - min_w);
+            self.split_plot(Rect::new(r.x, r.y, split as u32, h as u32), rng, plots);
+            let rest = Rect::new(r.x + split, r.y, (w - split) as u32, h as u32);
+            self.split_plot(rest, rng, plots);
+        } else if h > max_h && h >= 2 * min_h {
+            let split = rng.random_range(min_h..=h - min_h);
+            self.split_plot(Rect::new(r.x, r.y, w as u32, split as u32), rng, plots);
+            let rest = Rect::new(r.x, r.y + split, w as u32, (h - split) as u32);
+            self.split_plot(rest, rng, plots);
+        } else if w >= min_w && h >= min_h {
+            plots.push(r);
+        }
+    }
+}
+
+/// The cells inside of the outer walls of a building
+fn interior(r: Rect) -> impl Iterator<Item = Pos> {
+    (r.y + 1..r.y + r.h - 1)
+        .flat_map(move |y| (r.x + 1..r.x + r.w - 1).map(move |x| Pos::new(x, y)))
+}
+
+/// Stamp the prefab in the middle of `r` using any rotation of it that fits, returning the area
+/// it covers along with the markers that were stamped.
+fn stamp_prefab(
+    prefab: &Prefab,
+    r: Rect,
+    map: &mut Map,
+    rng: &mut impl Rng,
+) -> Option<(Rect, Stamped)> {
+    let turns: u8 = rng.random_range(0..4);
+
+    for i in 0..4 {
+        let prefab = prefab.transformed(turns + i, rng.random_bool(0.5));
+        let (pw, ph) = (prefab.cells.w as i32, prefab.cells.h as i32);
+        if pw <= r.w && ph <= r.h {
+            let at = Pos::new(r.x + (r.w - pw) / 2, r.y + (r.h - ph) / 2);
+            let stamped = prefab.stamp(at, map);
+
+            return Some((Rect::new(at.x, at.y, pw as u32, ph as u32), stamped));
+        }
+    }
+
+    None
+}
+
+/// Generate a building filling `r`: the inside is split into rooms joined by doors, the front
+/// door faces the nearest street and the rooms are then furnished.
+fn build(kind: BuildingKind, r: Rect, road: usize, map: &mut Map, rng: &mut impl Rng) -> Rect {
+    let (wall, floor) = kind.materials();
+    let (wall, floor) = (map.terrain.idx(wall), map.terrain.idx(floor));
+    map.carve_rect(r, wall);
+
+    let inside = Rect::new(r.x + 1, r.y + 1, r.w as u32 - 2, r.h as u32 - 2);
+    map.carve_rect(inside, floor);
+    let mut doors = Vec::new();
+    let mut rooms = Vec::new();
+    partition(inside, wall, map, rng, &mut doors, &mut rooms);
+
+    let front = front_door(r, road, floor, map, rng);
+    doors.push(front);
+    for &p in doors.iter() {
+        map.add_feature(p, Feature::door());
+    }
+
+    for name in kind.furniture() {
+        let idx = map.terrain.idx(name);
+        for _ in 0..FURNITURE_ATTEMPTS {
+            let room = rooms.choose(rng).unwrap();
+            let p = Pos::new(
+                rng.random_range(room.x..room.x + room.w),
+                rng.random_range(room.y..room.y + room.h),
+            );
+            // furniture goes against the walls and out of the way of doors
+            let against_wall = [(0, 1), (0, -1), (1, 0), (-1, 0)]
+                .iter()
+                .any(|&(dx, dy)| map.tiles[p + Pos::new(dx, dy)] == wall);
+            let near_door = doors.iter().any(|d| d.fdist(p) < 2.0);
+            if map.tiles[p] != floor || !against_wall || near_door {
+                continue;
+            }
+
+            map.tiles[p] = idx;
+            if all_reachable(r, front, map) {
+                break;
+            }
+            map.tiles[p] = floor;
+        }
+    }
+
+    r
+}
+
+/// Split a room in two with a wall (and a door through it) along its longer side until each room
+/// is small enough. Walls are never placed where they would block an existing door.
+fn partition(
+    r: Rect,
+    wall: usize,
+    map: &mut Map,
+    rng: &mut impl Rng,
+    doors: &mut Vec<Pos>,
+    rooms: &mut Vec<Rect>,
+) {
+    let (x, y, w, h) = (r.x, r.y, r.w, r.h);
+    if w * h <= MAX_ROOM_AREA {
+        rooms.push(r);
+        return;
+    }
+
+    let vertical: Vec<i32> = (x + MIN_ROOM..x + w - MIN_ROOM)
+        .filter(|&sx| {
+            !doors.contains(&Pos::new(sx, y - 1)) && !doors.contains(&Pos::new(sx, y + h))
+        })
+        .collect();
+    let horizontal: Vec<i32> = (y + MIN_ROOM..y + h - MIN_ROOM)
+        .filter(|&sy| {
+            !doors.contains(&Pos::new(x - 1, sy)) && !doors.contains(&Pos::new(x + w, sy))
+        })
+        .collect();
+
+    if (w >= h || horizontal.is_empty())
+        && let Some(&sx) = vertical.choose(rng)
+    {
+        map.carve_v_tunnel(y, y + h - 1, sx, wall);
+        doors.push(Pos::new(sx, rng.random_range(y..y + h)));
+        partition(
+            Rect::new(x, y, (sx - x) as u32, h as u32),
+            wall,
+            map,
+            rng,
+            doors,
+            rooms,
+        );
+        let rest = Rect::new(sx + 1, y, (x + w - sx - 1) as u32, h as u32);
+        partition(rest, wall, map, rng, doors, rooms);
+    } else if let Some(&sy) = horizontal.choose(rng) {
+        map.carve_h_tunnel(x, x + w - 1, sy, wall);
+        doors.push(Pos::new(rng.random_range(x..x + w), sy));
+        partition(
+            Rect::new(x, y, w as u32, (sy - y) as u32),
+            wall,
+            map,
+            rng,
+            doors,
+            rooms,
+        );
+        let rest = Rect::new(x, sy + 1, w as u32, (y + h - sy - 1) as u32);
+        partition(rest, wall, map, rng, doors, rooms);
+    } else {
+        rooms.push(r);
+    }
+}
+
+/// Pick a spot for the front door on whichever side of the building is closest to a street,
+/// looking outwards from the middle of each side.
+fn front_door(r: Rect, road: usize, floor: usize, map: &Map, rng: &mut impl Rng) -> Pos {
+    let (x1, y1, x2, y2) = (r.x, r.y, r.x + r.w - 1, r.y + r.h - 1);
+    let c = r.center();
+
+    let sides = [
+        (Pos::new(c.x, y1), Pos::new(0, -1)),
+        (Pos::new(c.x, y2), Pos::new(0, 1)),
+        (Pos::new(x1, c.y), Pos::new(-1, 0)),
+        (Pos::new(x2, c.y), Pos::new(1, 0)),
+    ];
+    let steps_to_road = |(mut p, dir): (Pos, Pos)| {
+        let mut steps = 0;
+        while map.contains_pos(p) {
+            if map.tiles[p] == road {
+                return steps;
+            }
+            p += dir;
+            steps += 1;
+        }
+        i32::MAX
+    };
+    let (mid, dir) = sides.into_iter().min_by_key(|&s| steps_to_road(s)).unwrap();
+
+    // any cell along the chosen side that opens onto a room rather than an internal wall
+    let side: Vec<Pos> = if dir.x == 0 {
+        (x1 + 1..x2).map(|x| Pos::new(x, mid.y)).collect()
+    } else {
+        (y1 + 1..y2).map(|y| Pos::new(mid.x, y)).collect()
+    };
+    let candidates: Vec<Pos> = side
+        .into_iter()
+        .filter(|&p| map.tiles[p + Pos::new(-dir.x, -dir.y)] == floor)
+        .collect();
+
+    candidates.choose(rng).copied().unwrap_or(mid)
+}
+
+/// Whether every open cell of the building at `r` can be reached from its front door
+fn all_reachable(r: Rect, front: Pos, map: &Map) -> bool {
+    let inside = |p: Pos| r.contains_point((p.x, p.y));
+    let dmap = dijkstra_map(&map.tiles, &[(front, 0)], |p| {
+        if !inside(p) {
+            None
+        } else if map.features.contains_key(&p) {
+            Some(1)
+        } else {
+            map.tile_at(p).path_cost
+        }
+    });
+
+    interior(r).all(|p| map.tile_at(p).blocks_movement() || dmap[p] != i32::MAX)
+}
+
+impl Pipeline {
+    /// A town in a forest clearing with a guild hall, tavern, smithy and apothecary along with
+    /// houses for the townsfolk who work in them
+    pub fn town(town: Town) -> Self {
+        Self::new(palette::FOREST_BG, "tree", "meadow")
+            .then(town)
+            .then(Scatter {
+                from: "tree",
+                to: TREES.to_vec(),
+                chance: 1.0,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::map_tile::Terrain;
+    use sdl2::pixels::Color;
+
+    #[test]
+    fn generated_buildings_can_be_fully_explored() {
+        let open = ["meadow", "road", "wood_floor", "stone_floor"].map(|name| (name, Some(1)));
+        let blocked = ["plank_wall", "stone_wall"]
+            .into_iter()
+            .chain(FURNITURE)
+            .chain(Feature::terrain_names())
+            .map(|name| (name, None));
+        let terrain = Terrain::for_tests(open.into_iter().chain(blocked));
+        let mut rng = rand::rng();
+
+        for kind in BuildingKind::NAMED.into_iter().chain([BuildingKind::House]) {
+            for _ in 0..20 {
+                let mut map = Map::new(20, 16, terrain.clone(), Color::BLACK, Color::BLACK);
+                let road = map.terrain.idx("road");
+                map.carve_h_tunnel(0, 19, 15, road);
+                let r = Rect::new(2, 2, 14, 11);
+
+                build(kind, r, road, &mut map, &mut rng);
+
+                let front: Vec<Pos> = map
+                    .features
+                    .keys()
+                    .copied()
+                    .filter(|p| {
+                        p.x == r.x || p.y == r.y || p.x == r.right() - 1 || p.y == r.bottom() - 1
+                    })
+                    .collect();
+                assert_eq!(front.len(), 1, "{kind:?}");
+                assert_eq!(
+                    front[0].y,
+                    r.bottom() - 1,
+                    "front door should face the road"
+                );
+                assert!(all_reachable(r, front[0], &map), "{kind:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn plots_are_within_the_size_limits() {
+        let town = Town::default();
+        let mut rng = rand::rng();
+
+        for _ in 0..20 {
+            let mut plots = Vec::new();
+            town.split_plot(Rect::new(2, 2, 71, 18), &mut rng, &mut plots);
+
+            assert!(!plots.is_empty());
+            for r in plots {
+                let (w, h) = (r.w, r.h);
+                assert!(w >= town.min_plot.0 && h >= town.min_plot.1, "{r:?}");
+                assert!(w <= town.max_plot.0 && h <= town.max_plot.1, "{r:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! https://en.wikipedia.org/wiki/Turmite
+//! https://en.wikipedia.org/wiki/Langton%27s_ant
+use crate::{
+    Pos,
+    map::builders::{
+        BuildData, CullUnreachable, MapPass, Pipeline, Snapshots, StartingPosition, VoronoiRegions,
+    },
+    state::State,
+    ui::palette,
+};
+use rand::Rng;
+use sdl2::rect::Rect;
+use std::collections::HashMap;
+
+/// The rule used by [Turmites::default], which [State::init] checks is defined in data/turmites
+pub const DEFAULT_TURMITE_RULE: &str = "langtons_ant";
+const N_REGIONS: usize = 16;
+/// How many steps to run between snapshots
+const SNAPSHOT_EVERY: usize = 500;
+const HEADINGS: [Pos; 4] = [
+    Pos::new(0, -1),
+    Pos::new(1, 0),
+    Pos::new(0, 1),
+    Pos::new(-1, 0),
+];
+
+/// How a turmite turns after writing to its current cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Turn {
+    Left,
+    Right,
+    None,
+    UTurn,
+}
+
+impl Turn {
+    fn apply(&self, heading: usize) -> usize {
+        match self {
+            Self::Left => (heading + 3) % 4,
+            Self::Right => (heading + 1) % 4,
+            Self::None => heading,
+            Self::UTurn => (heading + 2) % 4,
+        }
+    }
+}
+
+/// What a turmite does on a cell given its current state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    /// The new cell state: 0 for solid and 1 for open
+    pub write: usize,
+    pub turn: Turn,
+    pub next: u8,
+}
+
+/// A turmite state transition table keyed by (turmite state, cell state).
+///
+/// See data/turmites for the available rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TurmiteRule {
+    pub name: String,
+    pub transitions: HashMap<(u8, usize), Transition>,
+}
+
+impl TurmiteRule {
+    pub fn get(&self, state: u8, cell: usize) -> Option<&Transition> {
+        self.transitions.get(&(state, cell))
+    }
+}
+
+/// Initial [MapPass] that runs one or more turmites using the named rule from data/turmites over
+/// the map, opening and filling in cells as they go. The map starts filled with its wall terrain
+/// and open cells use the floor.
+#[derive(Debug, Clone)]
+pub struct Turmites {
+    pub rule: String,
+    pub n_turmites: usize,
+    /// The maximum number of steps each turmite takes
+    pub max_steps: usize,
+    /// Stop early once this fraction of the map is open
+    pub target_open: f32,
+}
+
+impl Turmites {
+    pub fn new(rule: impl Into<String>) -> Self {
+        Self {
+            rule: rule.into(),
+            n_turmites: 3,
+            max_steps: 20_000,
+            target_open: 0.45,
+        }
+    }
+}
+
+impl Default for Turmites {
+    fn default() -> Self {
+        Self::new(DEFAULT_TURMITE_RULE)
+    }
+}
+
+impl MapPass for Turmites {
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<()> {
+        // the default rule is checked when the state is initialised so only a custom rule that
+        // hasn't been added to data/turmites can be missing
+        let rule = match state.turmite_rules.get(&self.rule) {
+            Some(rule) => rule,
+            None => panic!("unknown turmite rule: {:?}", self.rule),
+        };
+        let rng = &mut state.rng;
+        // indexed by cell state
+        let terrain = [data.wall, data.floor];
+        let map = &mut data.map;
+        let (w, h) = (map.w as i32, map.h as i32);
+        let target = (self.target_open * map.len() as f32) as usize;
+        let inner = Rect::new(1, 1, (w - 2) as u32, (h - 2) as u32);
+
+        // (position, heading, state) with the first turmite always starting in the center
+        let mut turmites: Vec<(Pos, usize, u8)> = (0..self.n_turmites)
+            .map(|i| {
+                let p = if i == 0 {
+                    Pos::new(w / 2, h / 2)
+                } else {
+                    rng.random_point(inner, 0)
+                };
+                (p, rng.random_range(0..HEADINGS.len()), 0)
+            })
+            .collect();
+
+        let mut n_open = map.cells.iter().filter(|&&idx| idx != terrain[0]).count();
+
+        for step in 0..self.max_steps {
+            if n_open >= target {
+                break;
+            }
+
+            for (p, heading, s) in turmites.iter_mut() {
+                let cell = usize::from(map[*p] != terrain[0]);
+                // rules are checked for missing transitions when they are parsed
+                let t = *rule.get(*s, cell)?;
+
+                match (cell, t.write) {
+                    (0, 1) => n_open += 1,
+                    (1, 0) => n_open -= 1,
+                    _ => (),
+                }
+                map.tiles[*p] = terrain[t.write];
+                *s = t.next;
+                *heading = t.turn.apply(*heading);
+
+                // turn back at the edge of the map to keep a solid border
+                let mut next = *p + HEADINGS[*heading];
+                if next.x < 1 || next.y < 1 || next.x > w - 2 || next.y > h - 2 {
+                    *heading = Turn::UTurn.apply(*heading);
+                    next = *p + HEADINGS[*heading];
+                }
+                *p = next;
+            }
+
+            if step % SNAPSHOT_EVERY == 0 {
+                snapshots.push(map);
+            }
+        }
+
+        Some(())
+    }
+}
+
+impl Pipeline {
+    /// Organic stone caves dug out by turmites following the given rule
+    pub fn turmites(turmites: Turmites) -> Self {
+        Self::new(palette::BLACK, "stone_wall", "stone_floor")
+            .then(turmites)
+            .then(StartingPosition::Center)
+            .then(CullUnreachable)
+            .then(VoronoiRegions(N_REGIONS))
+    }
+}
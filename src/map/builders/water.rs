@@ -0,0 +1,423 @@
+//! Rivers and lakes for outdoor maps, along with the crossings needed to keep them connected.
+//!
+//! Water is placed using three pieces of terrain from data/tiles: "water" is shallow enough to
+//! wade through, "deep_water" is impassable and "bridge" carries a path over deep water.
+use crate::{
+    Pos,
+    grid::{Grid, NoiseField, NoiseKind, a_star, descend, dijkstra_map},
+    map::{
+        Map,
+        builders::{BuildData, MapPass, Snapshots},
+    },
+    state::State,
+};
+use rand::Rng;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
+};
+
+const SHALLOW: &str = "water";
+const DEEP: &str = "deep_water";
+const BRIDGE: &str = "bridge";
+
+/// The cost of crossing a deep water cell relative to walking over an open one
+const BRIDGE_COST: i32 = 2;
+/// The cost of clearing a blocked cell relative to walking over an open one
+const CLEAR_COST: i32 = 4;
+
+/// A river running between opposite edges of the map. Its course is an A* path that prefers open
+/// ground over blocked terrain, with the values of a noise field added to the cost of each cell
+/// so that it meanders rather than running straight across.
+#[derive(Debug, Clone)]
+pub struct River {
+    pub noise: NoiseField,
+    /// The most that the noise field can add to the cost of a cell
+    pub meander: f32,
+    /// The extra cost of a cell that the river has to cut through rather than flow over
+    pub blocked_cost: i32,
+    /// Cells within this distance of the course are deep water
+    pub deep_radius: f32,
+    /// Cells within this distance of the course are at least shallow water
+    pub bank_radius: f32,
+}
+
+impl Default for River {
+    fn default() -> Self {
+        let mut noise = NoiseField::new(NoiseKind::Perlin, 0);
+        noise.scale = 12.0;
+        noise.octaves = 3;
+
+        Self {
+            noise,
+            meander: 24.0,
+            blocked_cost: 3,
+            deep_radius: 1.0,
+            bank_radius: 2.5,
+        }
+    }
+}
+
+impl MapPass for River {
+    fn terrain(&self, _: &State<'_>) -> Vec<String> {
+        [SHALLOW, DEEP].map(String::from).to_vec()
+    }
+
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<()> {
+        self.noise.seed = state.rng.random();
+        let map = &mut data.map;
+        let (w, h) = (map.w as i32, map.h as i32);
+        let noise = self.noise.grid(Pos::default(), map.w, map.h);
+
+        let rng = &mut *state.rng;
+        let (from, to) = if rng.random_bool(0.5) {
+            let mut x = || rng.random_range(w / 4..=3 * w / 4);
+            (Pos::new(x(), 0), Pos::new(x(), h - 1))
+        } else {
+            let mut y = || rng.random_range(h / 4..=3 * h / 4);
+            (Pos::new(0, y()), Pos::new(w - 1, y()))
+        };
+
+        let course = a_star(from, to, &map.tiles, |p| {
+            if map.features.contains_key(&p) {
+                return None;
+            }
+            let blocked = if map.tile_at(p).blocks_movement() {
+                self.blocked_cost
+            } else {
+                0
+            };
+
+            Some(1 + blocked + (noise[p] * self.meander) as i32)
+        });
+        if course.is_empty() {
+            return None;
+        }
+
+        let course: Vec<Pos> = std::iter::once(from).chain(course).collect();
+        carve_river(map, &course, self.deep_radius, self.bank_radius, data.start);
+        snapshots.push(map);
+
+        Some(())
+    }
+}
+
+/// Lakes formed by flooding basins in an elevation noise field: starting from a low point, water
+/// fills the lowest cell bordering the lake until it reaches its size. The first cells to flood
+/// are the deepest so each lake has a core of deep water surrounded by shallows.
+#[derive(Debug, Clone)]
+pub struct Lakes {
+    pub elevation: NoiseField,
+    /// The most lakes to place: there may be fewer if the field has fewer basins
+    pub count: usize,
+    /// The range of sizes in cells for each lake
+    pub size: (usize, usize),
+    /// The fraction of each lake that is deep water
+    pub deep_fraction: f32,
+}
+
+impl Default for Lakes {
+    fn default() -> Self {
+        let mut elevation = NoiseField::new(NoiseKind::Simplex, 0);
+        elevation.scale = 16.0;
+        elevation.octaves = 3;
+
+        Self {
+            elevation,
+            count: 2,
+            size: (20, 60),
+            deep_fraction: 0.5,
+        }
+    }
+}
+
+impl MapPass for Lakes {
+    fn terrain(&self, _: &State<'_>) -> Vec<String> {
+        [SHALLOW, DEEP].map(String::from).to_vec()
+    }
+
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<()> {
+        self.elevation.seed = state.rng.random();
+        let map = &mut data.map;
+        let elevation = self.elevation.grid(Pos::default(), map.w, map.h);
+
+        let mut basins = basins(&elevation);
+        for _ in 0..self.count {
+            if basins.is_empty() {
+                break;
+            }
+            let low = basins.swap_remove(state.rng.random_range(0..basins.len()));
+            if map.tile_at(low).water {
+                continue; // already flooded by an earlier lake or river
+            }
+
+            let size = state.rng.random_range(self.size.0..=self.size.1);
+            let lake = flood_basin(low, size, &elevation, map, data.start);
+            let n_deep = (lake.len() as f32 * self.deep_fraction) as usize;
+            let (shallow, deep) = (map.terrain.idx(SHALLOW), map.terrain.idx(DEEP));
+            for (i, p) in lake.into_iter().enumerate() {
+                if i < n_deep {
+                    map.tiles[p] = deep;
+                } else if !map.tile_at(p).water {
+                    map.tiles[p] = shallow;
+                }
+            }
+            snapshots.push(map);
+        }
+
+        Some(())
+    }
+}
+
+/// Ensure that deep water doesn't cut off any open part of the map from the starting position by
+/// adding crossings where it is narrowest. Short crossings are shallow fords and longer ones are
+/// bridges. Any other blocked terrain in the way of a crossing is cleared.
+#[derive(Debug, Clone)]
+pub struct Crossings {
+    /// The most deep water cells that can be crossed by a ford rather than a bridge
+    pub max_ford: usize,
+}
+
+impl Default for Crossings {
+    fn default() -> Self {
+        Self { max_ford: 2 }
+    }
+}
+
+impl MapPass for Crossings {
+    fn terrain(&self, _: &State<'_>) -> Vec<String> {
+        [SHALLOW, BRIDGE].map(String::from).to_vec()
+    }
+
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        _: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<()> {
+        let start = data.start?;
+        while let Some(crossing) = next_crossing(&data.map, start)? {
+            place_crossing(&mut data.map, &crossing, self.max_ford, data.floor);
+            snapshots.push(&data.map);
+        }
+
+        Some(())
+    }
+}
+
+fn is_deep_water(map: &Map, p: Pos) -> bool {
+    let tile = map.tile_at(p);
+    tile.water && tile.blocks_movement()
+}
+
+/// Fill in cells around the course of a river, keeping the starting position out of deep water
+fn carve_river(
+    map: &mut Map,
+    course: &[Pos],
+    deep_radius: f32,
+    bank_radius: f32,
+    start: Option<Pos>,
+) {
+    let (shallow, deep) = (map.terrain.idx(SHALLOW), map.terrain.idx(DEEP));
+    let r = bank_radius.ceil() as i32;
+
+    for &p in course {
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let q = p + Pos::new(dx, dy);
+                if !map.contains_pos(q) || map.features.contains_key(&q) {
+                    continue;
+                }
+
+                let d = p.fdist(q);
+                if d <= deep_radius && Some(q) != start {
+                    map.tiles[q] = deep;
+                } else if d <= bank_radius && !map.tile_at(q).water {
+                    map.tiles[q] = shallow;
+                }
+            }
+        }
+    }
+}
+
+/// Cells lower than all of their neighbours
+fn basins(elevation: &Grid<f32>) -> Vec<Pos> {
+    (0..elevation.len())
+        .map(|i| Pos::new((i % elevation.w) as i32, (i / elevation.w) as i32))
+        .filter(|&p| {
+            elevation
+                .neighbouring_tiles(p)
+                .all(|q| elevation[q] > elevation[p])
+        })
+        .collect()
+}
+
+/// Flood the basin containing `low` until the lake covers `size` cells, returning the cells of
+/// the lake in the order that they flooded. Map features and the starting position stay dry.
+fn flood_basin(
+    low: Pos,
+    size: usize,
+    elevation: &Grid<f32>,
+    map: &Map,
+    start: Option<Pos>,
+) -> Vec<Pos> {
+    // elevation is normalised to 0.0..=1.0 so this keeps plenty of precision for ordering
+    let key = |p: Pos| Reverse(((elevation[p] * 1e6) as i32, p.x, p.y));
+    let mut lake = Vec::with_capacity(size);
+    let mut seen = HashSet::from([low]);
+    let mut frontier = BinaryHeap::from([key(low)]);
+
+    while let Some(Reverse((_, x, y))) = frontier.pop() {
+        let p = Pos::new(x, y);
+        if map.features.contains_key(&p) || Some(p) == start {
+            continue;
+        }
+
+        lake.push(p);
+        if lake.len() == size {
+            break;
+        }
+
+        for q in map.neighbouring_tiles(p) {
+            if seen.insert(q) {
+                frontier.push(key(q));
+            }
+        }
+    }
+
+    lake
+}
+
+/// The cells of the cheapest crossing from the part of the map reachable from `start` to an open
+/// cell that isn't, if there are any such cells. `None` if a crossing is needed but impossible.
+fn next_crossing(map: &Map, start: Pos) -> Option<Option<Vec<Pos>>> {
+    let reach = dijkstra_map(&map.tiles, &[(start, 0)], |p| map.tile_at(p).path_cost);
+    let pos = |i: usize| Pos::new((i % map.w) as i32, (i / map.w) as i32);
+    let reachable: Vec<(Pos, i32)> = (0..map.len())
+        .filter(|&i| reach.cells[i] != i32::MAX)
+        .map(|i| (pos(i), 0))
+        .collect();
+
+    let cost = dijkstra_map(&map.tiles, &reachable, |p| {
+        if map.features.contains_key(&p) {
+            None
+        } else if is_deep_water(map, p) {
+            Some(BRIDGE_COST)
+        } else if map.tile_at(p).blocks_movement() {
+            Some(CLEAR_COST)
+        } else {
+            Some(1)
+        }
+    });
+
+    let target = (0..map.len())
+        .filter(|&i| reach.cells[i] == i32::MAX && !map.tile_at(pos(i)).blocks_movement())
+        .min_by_key(|&i| cost.cells[i]);
+    match target {
+        Some(i) if cost.cells[i] == i32::MAX => None,
+        Some(i) => Some(Some(descend(&cost, pos(i)))),
+        None => Some(None),
+    }
+}
+
+fn place_crossing(map: &mut Map, crossing: &[Pos], max_ford: usize, floor: usize) {
+    let n_deep = crossing.iter().filter(|&&p| is_deep_water(map, p)).count();
+    let over_water = if n_deep <= max_ford {
+        map.terrain.idx(SHALLOW)
+    } else {
+        map.terrain.idx(BRIDGE)
+    };
+
+    for &p in crossing {
+        if is_deep_water(map, p) {
+            map.tiles[p] = over_water;
+        } else if map.tile_at(p).blocks_movement() {
+            map.tiles[p] = floor;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{MapTile, map_tile::Terrain};
+    use sdl2::pixels::Color;
+
+    fn test_map() -> Map {
+        let water = |path_cost| MapTile {
+            water: true,
+            ..MapTile::for_tests(path_cost)
+        };
+        let terrain = Terrain::new([
+            ("tree", MapTile::for_tests(None)),
+            ("earth", MapTile::for_tests(Some(1))),
+            (SHALLOW, water(Some(4))),
+            (DEEP, water(None)),
+            (BRIDGE, MapTile::for_tests(Some(1))),
+        ]);
+        let mut map = Map::new(30, 20, terrain, Color::BLACK, Color::BLACK);
+        let earth = map.terrain.idx("earth");
+        map.carve_rect(sdl2::rect::Rect::new(1, 1, 28, 18), earth);
+
+        map
+    }
+
+    #[test]
+    fn crossings_reconnect_the_map() {
+        for (river_width, expected) in [(2, SHALLOW), (5, BRIDGE)] {
+            let mut map = test_map();
+            let deep = map.terrain.idx(DEEP);
+            for y in 0..20 {
+                for x in 12..12 + river_width {
+                    map.tiles[Pos::new(x, y)] = deep;
+                }
+            }
+
+            let (start, earth) = (Pos::new(3, 10), map.terrain.idx("earth"));
+            while let Some(crossing) = next_crossing(&map, start).unwrap() {
+                place_crossing(&mut map, &crossing, 2, earth);
+            }
+
+            let reach = dijkstra_map(&map.tiles, &[(start, 0)], |p| map.tile_at(p).path_cost);
+            assert!(reach[Pos::new(28, 10)] < i32::MAX, "width {river_width}");
+            let n_crossed = (0..20)
+                .filter(|&y| map.tiles[Pos::new(12, y)] == map.terrain.idx(expected))
+                .count();
+            assert_eq!(n_crossed, 1, "width {river_width}");
+        }
+    }
+
+    #[test]
+    fn lakes_flood_from_the_lowest_point() {
+        let map = test_map();
+        let centre = Pos::new(15, 10);
+        let elevation = Grid {
+            cells: (0..map.len())
+                .map(|i| Pos::new((i % map.w) as i32, (i / map.w) as i32).fdist(centre))
+                .collect(),
+            w: map.w,
+            h: map.h,
+        };
+
+        assert_eq!(basins(&elevation), vec![centre]);
+
+        let lake = flood_basin(centre, 25, &elevation, &map, Some(Pos::new(15, 11)));
+        assert_eq!(lake.len(), 25);
+        assert_eq!(lake[0], centre);
+        assert!(
+            !lake.contains(&Pos::new(15, 11)),
+            "the start should stay dry"
+        );
+        assert!(lake.iter().all(|p| p.fdist(centre) <= 3.0));
+    }
+}
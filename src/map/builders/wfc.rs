@@ -0,0 +1,507 @@
+//! Wave function collapse using the overlapping model
+//!   https://github.com/mxgmn/WaveFunctionCollapse
+//!   https://www.boristhebrave.com/2020/04/13/wave-function-collapse-explained/
+use crate::{
+    Grid, Pos,
+    map::{
+        builders::{
+            BuildData, CullUnreachable, MapPass, Pipeline, RequireOpen, Snapshots, StartingPosition,
+        },
+        prefab::PrefabCell,
+    },
+    state::State,
+    ui::palette,
+};
+use indexmap::IndexMap;
+use rand::Rng;
+
+/// The sample used by [Wfc::default], which [State::init] checks is in data/prefabs
+pub const DEFAULT_WFC_SAMPLE: &str = "room";
+const MIN_OPEN_PERC: f32 = 0.3;
+// neighbour offsets and the index of the opposite direction for each of them
+const DX: [i32; 4] = [-1, 0, 1, 0];
+const DY: [i32; 4] = [0, 1, 0, -1];
+const OPPOSITE: [usize; 4] = [2, 3, 0, 1];
+
+/// Initial [MapPass] that learns the NxN patterns found in the named prefab from data/prefabs and
+/// generates a map made up entirely of them.
+///
+/// Prefab cells marked as "keep" become the wall terrain of the map and any markers or features
+/// become the prefab's floor (or the floor terrain of the map if it doesn't have one).
+#[derive(Debug, Clone)]
+pub struct Wfc {
+    pub sample: &'static str,
+    /// The size of the patterns to learn
+    pub n: usize,
+    /// Also learn every rotation and reflection of each pattern
+    pub symmetry: bool,
+    /// Keep the edges of the map filled with the wall terrain
+    pub border: bool,
+    /// A position that must be open and is used as the start of the map
+    pub start: Option<Pos>,
+    /// How many times to restart after a contradiction before giving up
+    pub max_attempts: usize,
+    model: Option<Model>,
+}
+
+impl Wfc {
+    pub fn new(sample: &'static str) -> Self {
+        Self {
+            sample,
+            n: 3,
+            symmetry: true,
+            border: true,
+            start: None,
+            max_attempts: 10,
+            model: None,
+        }
+    }
+}
+
+impl Default for Wfc {
+    fn default() -> Self {
+        Self::new(DEFAULT_WFC_SAMPLE)
+    }
+}
+
+impl MapPass for Wfc {
+    fn terrain(&self, state: &State<'_>) -> Vec<String> {
+        match state.prefabs.get(self.sample) {
+            Some(prefab) => prefab
+                .terrain_names()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn run(
+        &mut self,
+        data: &mut BuildData,
+        state: &mut State<'_>,
+        snapshots: &mut Snapshots,
+    ) -> Option<()> {
+        let (wall, floor) = (data.wall, data.floor);
+        let map = &mut data.map;
+        if self.model.is_none() {
+            // the default sample is checked when the state is initialised so only a custom sample
+            // that hasn't been added to data/prefabs can be missing
+            let prefab = match state.prefabs.get(self.sample) {
+                Some(prefab) => prefab,
+                None => panic!("unknown prefab: {:?}", self.sample),
+            };
+            let floor = prefab
+                .floor
+                .as_ref()
+                .map_or(floor, |name| map.terrain.idx(name));
+            let cells = prefab
+                .cells
+                .cells
+                .iter()
+                .map(|cell| match cell {
+                    PrefabCell::Keep => wall,
+                    PrefabCell::Terrain(name) => map.terrain.idx(name),
+                    _ => floor,
+                })
+                .collect();
+            let sample = Grid {
+                cells,
+                w: prefab.cells.w,
+                h: prefab.cells.h,
+            };
+            self.model = Some(Model::train(&sample, self.n, self.symmetry));
+        }
+        let model = self.model.as_ref().unwrap();
+
+        let (w, h) = (map.w as i32, map.h as i32);
+        let allowed = |p: Pos, idx: usize| {
+            let on_border = p.x == 0 || p.y == 0 || p.x == w - 1 || p.y == h - 1;
+            let border_ok = !self.border || !on_border || idx == wall;
+            let start_ok = self.start != Some(p) || !map.terrain[idx].blocks_movement();
+
+            border_ok && start_ok
+        };
+
+        let mut generated = None;
+        for _ in 0..self.max_attempts {
+            let mut steps = Vec::new();
+            let mut on_step = |g: Grid<usize>| steps.push(g);
+            let on_step: Option<&mut dyn FnMut(Grid<usize>)> = if snapshots.active {
+                Some(&mut on_step)
+            } else {
+                None
+            };
+
+            let res = model.generate(map.w, map.h, allowed, &mut *state.rng, on_step);
+            for tiles in steps {
+                let mut snapshot = map.clone();
+                snapshot.tiles = tiles;
+                snapshots.push(&snapshot);
+            }
+            if res.is_some() {
+                generated = res;
+                break;
+            }
+        }
+
+        map.tiles = generated?;
+        if self.start.is_some() {
+            data.start = self.start;
+        }
+
+        Some(())
+    }
+}
+
+impl Pipeline {
+    /// Stone maps in the style of the given prefab sample
+    pub fn wfc(wfc: Wfc) -> Self {
+        Self::new(palette::BLACK, "stone_wall", "stone_floor")
+            .then(wfc)
+            .then(StartingPosition::Center)
+            .then(CullUnreachable)
+            .then(RequireOpen(MIN_OPEN_PERC))
+    }
+}
+
+/// The patterns learned from a sample along with how often they occur and which of them can be
+/// placed next to each other.
+#[derive(Debug, Clone)]
+struct Model {
+    n: usize,
+    patterns: Vec<Vec<usize>>,
+    weights: Vec<f64>,
+    /// for each direction and pattern, the patterns that can be placed at that offset from it
+    propagator: [Vec<Vec<usize>>; 4],
+}
+
+impl Model {
+    fn train(sample: &Grid<usize>, n: usize, symmetry: bool) -> Self {
+        let mut counts: IndexMap<Vec<usize>, usize> = IndexMap::new();
+
+        for y in 0..=sample.h.saturating_sub(n) {
+            for x in 0..=sample.w.saturating_sub(n) {
+                let cells = (0..n * n)
+                    .map(|i| sample[Pos::new((x + i % n) as i32, (y + i / n) as i32)])
+                    .collect();
+                let mut window = Grid { cells, w: n, h: n };
+                let n_variants = if symmetry { 4 } else { 1 };
+
+                for _ in 0..n_variants {
+                    *counts.entry(window.cells.clone()).or_default() += 1;
+                    if symmetry {
+                        *counts.entry(window.mirrored().cells).or_default() += 1;
+                    }
+                    window = window.rotated_cw();
+                }
+            }
+        }
+
+        let (patterns, weights): (Vec<_>, Vec<_>) =
+            counts.into_iter().map(|(p, c)| (p, c as f64)).unzip();
+        let propagator = [0, 1, 2, 3].map(|d| {
+            patterns
+                .iter()
+                .map(|p1| {
+                    (0..patterns.len())
+                        .filter(|&t2| agrees(p1, &patterns[t2], DX[d], DY[d], n))
+                        .collect()
+                })
+                .collect()
+        });
+
+        Self {
+            n,
+            patterns,
+            weights,
+            propagator,
+        }
+    }
+
+    /// Generate a w x h grid where every NxN section is one of the learned patterns and each cell
+    /// holds a value that `allowed` accepts for its position. Returns None on a contradiction.
+    fn generate(
+        &self,
+        w: usize,
+        h: usize,
+        allowed: impl Fn(Pos, usize) -> bool,
+        rng: &mut impl Rng,
+        mut on_step: Option<&mut dyn FnMut(Grid<usize>)>,
+    ) -> Option<Grid<usize>> {
+        let mut wave = Wave::new(self, w, h)?;
+
+        // patterns found at the edge of the sample can have nothing that fits next to them so
+        // they can only be used at the matching edge of the output
+        for i in 0..wave.ww * wave.wh {
+            let (x, y) = ((i % wave.ww) as i32, (i / wave.ww) as i32);
+            for t in 0..self.patterns.len() {
+                let unsupported = (0..4).any(|d| {
+                    let (x2, y2) = (x - DX[d], y - DY[d]);
+                    let has_neighbour =
+                        x2 >= 0 && y2 >= 0 && x2 < wave.ww as i32 && y2 < wave.wh as i32;
+                    has_neighbour && wave.compatible[i * wave.n_patterns + t][d] == 0
+                });
+                if unsupported {
+                    wave.ban(i, t, self);
+                }
+            }
+        }
+
+        // apply the fixed constraints before collapsing anything
+        for i in 0..wave.ww * wave.wh {
+            let (wx, wy) = (i % wave.ww, i / wave.ww);
+            for (t, pattern) in self.patterns.iter().enumerate() {
+                let ok = pattern.iter().enumerate().all(|(j, &v)| {
+                    let p = Pos::new((wx + j % self.n) as i32, (wy + j / self.n) as i32);
+                    allowed(p, v)
+                });
+                if !ok {
+                    wave.ban(i, t, self);
+                }
+            }
+        }
+        if !wave.propagate(self) {
+            return None;
+        }
+
+        loop {
+            match wave.observe(self, rng) {
+                Observed::Contradiction => return None,
+                Observed::Done => return Some(wave.render(self, w, h)),
+                Observed::Collapsed => {
+                    if !wave.propagate(self) {
+                        return None;
+                    }
+                    if let Some(f) = on_step.as_mut() {
+                        f(wave.render(self, w, h));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Whether p2 can be placed at (dx, dy) from p1 with their overlapping cells matching
+fn agrees(p1: &[usize], p2: &[usize], dx: i32, dy: i32, n: usize) -> bool {
+    let n = n as i32;
+    let (xmin, xmax) = if dx < 0 { (0, dx + n) } else { (dx, n) };
+    let (ymin, ymax) = if dy < 0 { (0, dy + n) } else { (dy, n) };
+
+    (ymin..ymax).all(|y| {
+        (xmin..xmax).all(|x| p1[(x + n * y) as usize] == p2[(x - dx + n * (y - dy)) as usize])
+    })
+}
+
+enum Observed {
+    Collapsed,
+    Done,
+    Contradiction,
+}
+
+/// The patterns still possible at each position of the output: positions are the top left
+/// corner of the pattern placed there so the wave is n - 1 smaller than the output in each
+/// direction.
+struct Wave {
+    ww: usize,
+    wh: usize,
+    n_patterns: usize,
+    possible: Vec<bool>,
+    /// for each position and pattern, how many patterns in each direction still support it
+    compatible: Vec<[usize; 4]>,
+    counts: Vec<usize>,
+    sum_w: Vec<f64>,
+    sum_wlogw: Vec<f64>,
+    stack: Vec<(usize, usize)>,
+}
+
+impl Wave {
+    fn new(model: &Model, w: usize, h: usize) -> Option<Self> {
+        if w < model.n || h < model.n || model.patterns.is_empty() {
+            return None;
+        }
+        let (ww, wh) = (w - model.n + 1, h - model.n + 1);
+        let n_patterns = model.patterns.len();
+        let sum_w: f64 = model.weights.iter().sum();
+        let sum_wlogw: f64 = model.weights.iter().map(|w| w * w.ln()).sum();
+        let initial: Vec<[usize; 4]> = (0..n_patterns)
+            .map(|t| [0, 1, 2, 3].map(|d| model.propagator[OPPOSITE[d]][t].len()))
+            .collect();
+
+        Some(Self {
+            ww,
+            wh,
+            n_patterns,
+            possible: vec![true; ww * wh * n_patterns],
+            compatible: (0..ww * wh).flat_map(|_| initial.iter().copied()).collect(),
+            counts: vec![n_patterns; ww * wh],
+            sum_w: vec![sum_w; ww * wh],
+            sum_wlogw: vec![sum_wlogw; ww * wh],
+            stack: Vec::new(),
+        })
+    }
+
+    fn ban(&mut self, i: usize, t: usize, model: &Model) {
+        let j = i * self.n_patterns + t;
+        if !self.possible[j] {
+            return;
+        }
+
+        self.possible[j] = false;
+        self.compatible[j] = [0; 4];
+        self.stack.push((i, t));
+        self.counts[i] -= 1;
+        let w = model.weights[t];
+        self.sum_w[i] -= w;
+        self.sum_wlogw[i] -= w * w.ln();
+    }
+
+    /// Returns false if any position is left without any possible patterns
+    fn propagate(&mut self, model: &Model) -> bool {
+        while let Some((i1, t1)) = self.stack.pop() {
+            let (x1, y1) = ((i1 % self.ww) as i32, (i1 / self.ww) as i32);
+
+            for d in 0..4 {
+                let (x2, y2) = (x1 + DX[d], y1 + DY[d]);
+                if x2 < 0 || y2 < 0 || x2 >= self.ww as i32 || y2 >= self.wh as i32 {
+                    continue;
+                }
+                let i2 = x2 as usize + y2 as usize * self.ww;
+
+                for &t2 in model.propagator[d][t1].iter() {
+                    let c = &mut self.compatible[i2 * self.n_patterns + t2][d];
+                    if *c > 0 {
+                        *c -= 1;
+                        if *c == 0 {
+                            self.ban(i2, t2, model);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.counts.iter().all(|&c| c > 0)
+    }
+
+    /// Collapse the position with the lowest entropy to a single pattern
+    fn observe(&mut self, model: &Model, rng: &mut impl Rng) -> Observed {
+        let mut min = None;
+        for i in 0..self.counts.len() {
+            match self.counts[i] {
+                0 => return Observed::Contradiction,
+                1 => continue,
+                _ => {
+                    let entropy = self.sum_w[i].ln() - self.sum_wlogw[i] / self.sum_w[i];
+                    let noisy = entropy + rng.random::<f64>() * 1e-6;
+                    if min.is_none_or(|(_, e)| noisy < e) {
+                        min = Some((i, noisy));
+                    }
+                }
+            }
+        }
+
+        let i = match min {
+            Some((i, _)) => i,
+            None => return Observed::Done,
+        };
+
+        let mut r = rng.random::<f64>() * self.sum_w[i];
+        let mut chosen = 0;
+        for t in 0..self.n_patterns {
+            if self.possible[i * self.n_patterns + t] {
+                chosen = t;
+                r -= model.weights[t];
+                if r <= 0.0 {
+                    break;
+                }
+            }
+        }
+        for t in 0..self.n_patterns {
+            if t != chosen {
+                self.ban(i, t, model);
+            }
+        }
+
+        Observed::Collapsed
+    }
+
+    /// The current state of the output: cells without a single pattern are left as 0
+    fn render(&self, model: &Model, w: usize, h: usize) -> Grid<usize> {
+        let mut cells = vec![0; w * h];
+
+        for (k, cell) in cells.iter_mut().enumerate() {
+            let (x, y) = (k % w, k / w);
+            let (wx, wy) = (x.min(self.ww - 1), y.min(self.wh - 1));
+            let i = wx + wy * self.ww;
+            if self.counts[i] != 1 {
+                continue;
+            }
+            let start = i * self.n_patterns;
+            let t = (0..self.n_patterns)
+                .find(|&t| self.possible[start + t])
+                .unwrap();
+            *cell = model.patterns[t][(x - wx) + (y - wy) * model.n];
+        }
+
+        Grid { cells, w, h }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn sample() -> Grid<usize> {
+        let rows = [
+            "0000000000",
+            "0111101110",
+            "0100101010",
+            "0111101110",
+            "0000100000",
+            "0111111110",
+            "0000000000",
+        ];
+        let cells = rows
+            .iter()
+            .flat_map(|r| r.chars().map(|c| c.to_digit(10).unwrap() as usize))
+            .collect();
+
+        Grid { cells, w: 10, h: 7 }
+    }
+
+    #[test]
+    fn output_only_contains_learned_patterns() {
+        let model = Model::train(&sample(), 3, true);
+        let known: HashSet<&Vec<usize>> = model.patterns.iter().collect();
+        let (w, h) = (20, 15);
+
+        let mut rng = rand::rng();
+        let out = (0..20)
+            .find_map(|_| model.generate(w, h, |_, _| true, &mut rng, None))
+            .expect("no successful generation in 20 attempts");
+
+        for y in 0..=h - 3 {
+            for x in 0..=w - 3 {
+                let window: Vec<usize> = (0..9)
+                    .map(|i| out[Pos::new((x + i % 3) as i32, (y + i / 3) as i32)])
+                    .collect();
+                assert!(known.contains(&window), "unknown pattern at ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn constraints_are_respected() {
+        let model = Model::train(&sample(), 3, true);
+        let (w, h) = (16, 12);
+        let allowed = |p: Pos, v: usize| p.x != 0 || v == 0;
+
+        let mut rng = rand::rng();
+        let out = (0..20)
+            .find_map(|_| model.generate(w, h, allowed, &mut rng, None))
+            .expect("no successful generation in 20 attempts");
+
+        assert!((0..h).all(|y| out[Pos::new(0, y as i32)] == 0));
+    }
+}
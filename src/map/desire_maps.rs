@@ -0,0 +1,258 @@
+//! Brogue style "desire maps" built from Dijkstra maps that AIs can combine in order to decide
+//! where to move.
+//!   https://www.roguebasin.com/index.php/The_Incredible_Power_of_Dijkstra_Maps
+//!   https://www.roguebasin.com/index.php/Dijkstra_Maps_Visualized
+use crate::{Grid, Pos, grid::dijkstra_map, map::Map};
+use std::collections::HashMap;
+
+/// Coefficient applied to an approach map before rescanning it to produce a flee map.
+///
+/// Values below -1.0 make it worth running past a threat towards a large open area rather than
+/// backing into the nearest corner.
+const FLEE_COEFF: f32 = -1.2;
+
+/// The individual maps held in [DesireMaps]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DesireMap<'a> {
+    /// Distance to the player
+    Player,
+    /// Safety from the player
+    FleePlayer,
+    /// Distance to the nearest mob of the named faction
+    Faction(&'a str),
+    /// Safety from all mobs of the named faction
+    FleeFaction(&'a str),
+}
+
+/// A single weighted term in the combined desire for a given cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Desire<'a> {
+    /// Move down the gradient of the given map
+    Towards(DesireMap<'a>, f32),
+    /// Hold position at a fixed distance according to the given map
+    KeepAt(DesireMap<'a>, i32, f32),
+}
+
+/// The approach and flee maps for a single faction
+#[derive(Debug, Clone)]
+struct FactionMaps {
+    approach: Grid<i32>,
+    flee: Grid<i32>,
+}
+
+/// A per-map cache of the Dijkstra maps used by AIs.
+///
+/// These are rebuilt whenever the targets they were computed from move.
+#[derive(Debug, Clone)]
+pub struct DesireMaps {
+    player_pos: Pos,
+    faction_positions: HashMap<String, Vec<Pos>>,
+    player: Grid<i32>,
+    flee_player: Grid<i32>,
+    factions: HashMap<String, FactionMaps>,
+}
+
+impl DesireMaps {
+    /// Build the player maps along with a pair of maps for each faction present on the map.
+    pub fn new(
+        map: &Map,
+        player_pos: Pos,
+        mut faction_positions: HashMap<String, Vec<Pos>>,
+    ) -> Self {
+        let player = approach_map(map, &[(player_pos, 0)]);
+        let flee_player = flee_map(map, &player);
+
+        let mut factions = HashMap::with_capacity(faction_positions.len());
+        for (faction, positions) in faction_positions.iter_mut() {
+            positions.sort_by_key(|p| (p.x, p.y));
+            let targets: Vec<_> = positions.iter().map(|&p| (p, 0)).collect();
+            let approach = approach_map(map, &targets);
+            let flee = flee_map(map, &approach);
+            factions.insert(faction.clone(), FactionMaps { approach, flee });
+        }
+
+        Self {
+            player_pos,
+            faction_positions,
+            player,
+            flee_player,
+            factions,
+        }
+    }
+
+    /// Whether or not these maps were computed for different target positions
+    pub fn is_stale(&self, player_pos: Pos, faction_positions: &HashMap<String, Vec<Pos>>) -> bool {
+        if self.player_pos != player_pos || self.faction_positions.len() != faction_positions.len()
+        {
+            return true;
+        }
+
+        faction_positions.iter().any(|(faction, positions)| {
+            let mut positions = positions.clone();
+            positions.sort_by_key(|p| (p.x, p.y));
+
+            self.faction_positions.get(faction) != Some(&positions)
+        })
+    }
+
+    /// Factions without any mobs on the map have no maps
+    pub fn get(&self, m: DesireMap<'_>) -> Option<&Grid<i32>> {
+        match m {
+            DesireMap::Player => Some(&self.player),
+            DesireMap::FleePlayer => Some(&self.flee_player),
+            DesireMap::Faction(f) => self.factions.get(f).map(|m| &m.approach),
+            DesireMap::FleeFaction(f) => self.factions.get(f).map(|m| &m.flee),
+        }
+    }
+
+    /// The value of a single map at the given position.
+    ///
+    /// Returns `None` if the position is unreachable or the map does not exist.
+    pub fn value_at(&self, m: DesireMap<'_>, p: Pos) -> Option<i32> {
+        self.get(m)?
+            .try_cell_at(p)
+            .copied()
+            .filter(|&v| v != i32::MAX)
+    }
+
+    /// The combined score for moving to `p` when currently at `from`: lower is better.
+    ///
+    /// Terms for maps that are unreachable from `from` are ignored.
+    fn score(&self, from: Pos, p: Pos, desires: &[Desire<'_>]) -> Option<f32> {
+        let mut score = 0.0;
+
+        for d in desires.iter() {
+            let (m, w) = match *d {
+                Desire::Towards(m, w) | Desire::KeepAt(m, _, w) => (m, w),
+            };
+            if self.value_at(m, from).is_none() {
+                continue;
+            }
+            let v = self.value_at(m, p)?;
+
+            score += match *d {
+                Desire::Towards(..) => w * v as f32,
+                Desire::KeepAt(_, dist, _) => w * (v - dist).abs() as f32,
+            };
+        }
+
+        Some(score)
+    }
+
+    /// Select the neighbouring cell that best satisfies the given desires.
+    ///
+    /// Returns `None` if staying in place is at least as good as any available move.
+    pub fn best_move(&self, from: Pos, desires: &[Desire<'_>], map: &Map) -> Option<Pos> {
+        let mut best = (from, self.score(from, from, desires)?);
+
+        for p in map.neighbouring_tiles(from) {
+            if map.path_cost_through_doors(p).is_none() {
+                continue;
+            }
+            if let Some(score) = self.score(from, p, desires)
+                && score < best.1
+            {
+                best = (p, score);
+            }
+        }
+
+        if best.0 == from { None } else { Some(best.0) }
+    }
+}
+
+/// Mobs can open doors so closed doors don't block these maps
+fn approach_map(map: &Map, targets: &[(Pos, i32)]) -> Grid<i32> {
+    dijkstra_map(&map.tiles, targets, |p| map.path_cost_through_doors(p))
+}
+
+/// Invert an approach map and rescan it so that the resulting gradient leads away from the
+/// original targets, preferring routes that open out rather than dead ends.
+fn flee_map(map: &Map, approach: &Grid<i32>) -> Grid<i32> {
+    let targets: Vec<_> = approach
+        .cells
+        .iter()
+        .enumerate()
+        .filter(|&(_, &d)| d != i32::MAX)
+        .map(|(i, &d)| {
+            let p = Pos::new((i % approach.w) as i32, (i / approach.w) as i32);
+            (p, (d as f32 * FLEE_COEFF) as i32)
+        })
+        .collect();
+
+    approach_map(map, &targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{features::Feature, map_tile::Terrain};
+    use sdl2::{pixels::Color, rect::Rect};
+
+    fn corridor_map() -> Map {
+        let terrain = Terrain::for_tests(
+            [("wall", None), ("floor", Some(1))]
+                .into_iter()
+                .chain(Feature::terrain_names().map(|name| (name, None))),
+        );
+        let mut map = Map::new(40, 5, terrain, Color::BLACK, Color::BLACK);
+        map.carve_rect(Rect::new(1, 1, 38, 3), 1);
+
+        map
+    }
+
+    #[test]
+    fn flee_does_not_get_stuck_in_dead_ends() {
+        let map = corridor_map();
+        let dmaps = DesireMaps::new(&map, Pos::new(3, 2), HashMap::new());
+        let from = Pos::new(1, 2);
+
+        let p = dmaps.best_move(from, &[Desire::Towards(DesireMap::FleePlayer, 1.0)], &map);
+
+        assert!(matches!(p, Some(p) if p.x > from.x), "{p:?}");
+    }
+
+    #[test]
+    fn each_faction_present_gets_its_own_maps() {
+        let map = corridor_map();
+        let positions = HashMap::from([("goblins".to_string(), vec![Pos::new(10, 2)])]);
+        let dmaps = DesireMaps::new(&map, Pos::new(3, 2), positions);
+        let from = Pos::new(20, 2);
+
+        let p = dmaps.best_move(
+            from,
+            &[Desire::Towards(DesireMap::FleeFaction("goblins"), 1.0)],
+            &map,
+        );
+
+        assert!(matches!(p, Some(p) if p.x > from.x), "{p:?}");
+        assert_eq!(
+            dmaps.value_at(DesireMap::FleeFaction("kobolds"), from),
+            None
+        );
+    }
+
+    #[test]
+    fn keep_at_holds_position() {
+        let map = corridor_map();
+        let dmaps = DesireMaps::new(&map, Pos::new(10, 2), HashMap::new());
+        let desires = [Desire::KeepAt(DesireMap::Player, 2, 1.0)];
+
+        assert_eq!(dmaps.best_move(Pos::new(12, 2), &desires, &map), None);
+        assert!(dmaps.best_move(Pos::new(20, 2), &desires, &map).is_some());
+    }
+
+    #[test]
+    fn mobs_can_path_through_closed_doors() {
+        let mut map = corridor_map();
+        map.carve_rect(Rect::new(20, 0, 1, 5), 0);
+        map.add_feature(Pos::new(20, 2), Feature::door());
+        let dmaps = DesireMaps::new(&map, Pos::new(30, 2), HashMap::new());
+        let desires = [Desire::Towards(DesireMap::Player, 1.0)];
+
+        assert!(dmaps.value_at(DesireMap::Player, Pos::new(5, 2)).is_some());
+        assert_eq!(
+            dmaps.best_move(Pos::new(19, 2), &desires, &map),
+            Some(Pos::new(20, 2))
+        );
+    }
+}
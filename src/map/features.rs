@@ -0,0 +1,152 @@
+//! Map features that can change state over the course of the game such as doors and levers.
+//!
+//! Features live in a layer on top of the map tiles: each feature swaps the terrain beneath it
+//! between an open and a closed variant so that pathing, movement and FOV all pick up the change
+//! without needing to know about features.
+use crate::{Pos, actor::capitalise, map::fov::Fov, state::State};
+use hecs::Entity;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeatureKind {
+    /// Opened by bumping into it and closed again by hand
+    Door,
+    /// Can only be raised or lowered by a linked lever
+    Portcullis,
+    /// Toggles itself along with each of the linked features when pulled
+    Lever { linked: Vec<Pos> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feature {
+    pub kind: FeatureKind,
+    pub open: bool,
+}
+
+impl Feature {
+    pub fn door() -> Self {
+        Self {
+            kind: FeatureKind::Door,
+            open: false,
+        }
+    }
+
+    pub fn portcullis() -> Self {
+        Self {
+            kind: FeatureKind::Portcullis,
+            open: false,
+        }
+    }
+
+    pub fn lever(linked: Vec<Pos>) -> Self {
+        Self {
+            kind: FeatureKind::Lever { linked },
+            open: false,
+        }
+    }
+
+    /// The name of the terrain (see data/tiles) to use for this feature in its current state
+    pub fn terrain(&self) -> &'static str {
+        match (&self.kind, self.open) {
+            (FeatureKind::Door, false) => "door",
+            (FeatureKind::Door, true) => "open_door",
+            (FeatureKind::Portcullis, false) => "portcullis",
+            (FeatureKind::Portcullis, true) => "open_portcullis",
+            (FeatureKind::Lever { .. }, false) => "lever",
+            (FeatureKind::Lever { .. }, true) => "pulled_lever",
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self.kind {
+            FeatureKind::Door => "door",
+            FeatureKind::Portcullis => "portcullis",
+            FeatureKind::Lever { .. } => "lever",
+        }
+    }
+
+    /// The terrain names needed by any map that includes features
+    pub fn terrain_names() -> [&'static str; 6] {
+        [
+            "door",
+            "open_door",
+            "portcullis",
+            "open_portcullis",
+            "lever",
+            "pulled_lever",
+        ]
+    }
+
+    /// Interact with the feature at `pos` on the current map on behalf of the player: opening
+    /// doors and pulling levers.
+    ///
+    /// Returns false if there is no feature at `pos`.
+    pub fn interact(pos: Pos, state: &mut State<'_>) -> anyhow::Result<bool> {
+        let feature = match state.mapset.current().features.get(&pos) {
+            Some(f) => f.clone(),
+            None => return Ok(false),
+        };
+
+        match feature.kind {
+            FeatureKind::Door if !feature.open => Self::open_door(pos, state.e_player, state),
+            FeatureKind::Portcullis => {
+                let msg = if feature.open {
+                    "The portcullis is raised high above you."
+                } else {
+                    "The portcullis won't budge: perhaps there is a mechanism somewhere?"
+                };
+                state.log(msg);
+            }
+            FeatureKind::Lever { linked } => {
+                Self::set_open(pos, !feature.open, state);
+                for p in linked {
+                    let open = state.mapset.current().features.get(&p).map(|f| !f.open);
+                    if let Some(open) = open {
+                        Self::set_open(p, open, state);
+                    }
+                }
+                state.log("You pull the lever. Something clanks in the distance.");
+            }
+            FeatureKind::Door => (),
+        }
+
+        Ok(true)
+    }
+
+    /// Open the door at `pos` on the current map on behalf of `by`, letting the player know if
+    /// they are the one opening it or can see it happen.
+    pub fn open_door(pos: Pos, by: Entity, state: &mut State<'_>) {
+        Self::set_open(pos, true, state);
+
+        if by == state.e_player {
+            state.log("You open the door.");
+        } else if state
+            .world
+            .get::<&Fov>(state.e_player)
+            .is_ok_and(|fov| fov.points.contains(&pos))
+        {
+            let msg = format!("{} opens a door.", capitalise(&state.describe(by)));
+            state.log(msg);
+        }
+    }
+
+    /// Open or close the feature at `pos` on the current map, marking the FOV of anything that can
+    /// currently see it (along with the light map and desire maps) as needing to be recomputed.
+    pub fn set_open(pos: Pos, open: bool, state: &mut State<'_>) {
+        let map = state.mapset.current_mut();
+        if !map.set_feature_open(pos, open) {
+            return;
+        }
+        map.light_map = None;
+        map.desire_maps = None;
+
+        for (_, fov) in state.world.query_mut::<&mut Fov>() {
+            if fov.points.contains(&pos) {
+                fov.dirty = true;
+            }
+        }
+    }
+}
+
+/// The features present on a map keyed by position
+pub type Features = HashMap<Pos, Feature>;
@@ -32,7 +32,7 @@ fn get_opacity(map: &Map, objects: &HashMap<Pos, Opacity>) -> impl Fn(Pos) -> Op
     |pos| {
         let obj_opacity = objects.get(&pos).copied().unwrap_or_default().0;
         map.try_cell_at(pos).map(|idx| {
-            let opacity = map.tile_defs[*idx].opacity;
+            let opacity = map.terrain[*idx].opacity;
             if opacity > obj_opacity {
                 opacity
             } else {
@@ -48,12 +48,16 @@ impl FovRange {
             return false;
         }
 
+        self.fast_has_los_in(state.mapset.current(), from, to)
+    }
+
+    /// As [FovRange::fast_has_los] but for a specific map rather than the current one
+    pub fn fast_has_los_in(&self, map: &Map, from: Pos, to: Pos) -> bool {
         let r_cutoff = self.0 as f32 + R_SMOOTHING;
         if from.fdist(to) > r_cutoff {
             return false;
         }
 
-        let map = state.mapset.current();
         let mut opacity = 0.0;
         for p in map.line_between(from, to) {
             opacity += map.tile_at(p).opacity;
@@ -113,6 +117,57 @@ pub struct LightSource {
     pub color: Color,
 }
 
+impl LightSource {
+    /// How brightly this source at `from` lights `p` from 0.0 (unlit) to 1.0. Unlike a [LightMap]
+    /// this doesn't depend on what the player can see.
+    pub fn brightness_at(&self, map: &Map, from: Pos, p: Pos) -> f32 {
+        if from.fdist(p) > self.range as f32 + R_SMOOTHING {
+            return 0.0;
+        }
+
+        let opacity: f32 = map
+            .line_between(from, p)
+            .into_iter()
+            .filter(|&q| q != from)
+            .map(|q| map.tile_at(q).opacity)
+            .sum();
+        if opacity >= 1.0 {
+            return 0.0;
+        }
+
+        let c = light_color(from, p, self.color, opacity);
+
+        c.r.max(c.g).max(c.b) as f32 / 255.0
+    }
+}
+
+/// The colour of light from a source at `from` reaching `pos` through cells with the given
+/// combined opacity
+fn light_color(from: Pos, pos: Pos, color: Color, opacity: f32) -> Color {
+    // The origin ends up blowing out if due to having zero displacement so we treat it as being
+    // the cell to the right instead.
+    let p = if pos != from {
+        pos
+    } else {
+        pos + Pos::new(1, 0)
+    };
+
+    let d = from.fdist(p);
+    let mut falloff = DIST_SCALE * d.powi(2);
+    if falloff < 1.0 {
+        // need to force this to be clamped for origin
+        falloff = falloff.powf(EXP_FALLOFF);
+    }
+
+    let transparency = 1.0 - opacity;
+
+    Color::RGB(
+        (color.r as f32 * transparency / falloff) as u8,
+        (color.g as f32 * transparency / falloff) as u8,
+        (color.b as f32 * transparency / falloff) as u8,
+    )
+}
+
 #[derive(Debug, Clone)]
 pub struct LightMap {
     pub points: HashMap<Pos, Color>,
@@ -157,34 +212,10 @@ impl LightMap {
         let r_cutoff = range as f32 + R_SMOOTHING;
         let points: HashMap<Pos, Color> =
             RPACaster::new(from, range as i32, r_cutoff, Vis::CenterPlus, |pos| {
-                map.try_cell_at(pos).map(|idx| map.tile_defs[*idx].opacity)
+                map.try_cell_at(pos).map(|idx| map.terrain[*idx].opacity)
             })
             .filter(|(p, opacity)| fov.points.contains(p) && *opacity < 1.0)
-            .map(|(pos, opacity)| {
-                // The origin ends up blowing out if due to having zero displacement so we treat it
-                // as being the cell to the right instead.
-                let p = if pos != from {
-                    pos
-                } else {
-                    pos + Pos::new(1, 0)
-                };
-
-                let d = from.fdist(p);
-                let mut falloff = DIST_SCALE * d.powi(2);
-                if falloff < 1.0 {
-                    // need to force this to be clamped for origin
-                    falloff = falloff.powf(EXP_FALLOFF);
-                }
-
-                let transparency = 1.0 - opacity;
-                let cell_color = Color::RGB(
-                    (color.r as f32 * transparency / falloff) as u8,
-                    (color.g as f32 * transparency / falloff) as u8,
-                    (color.b as f32 * transparency / falloff) as u8,
-                );
-
-                (pos, cell_color)
-            })
+            .map(|(pos, opacity)| (pos, light_color(from, pos, color, opacity)))
             .collect();
 
         LightMap { points, c_hidden }
@@ -1,12 +1,7 @@
-use crate::{
-    tileset::{Tile, TileSet},
-    ui::palette,
-};
+use crate::tileset::{Tile, TileSet};
+use anyhow::anyhow;
 use sdl2::pixels::Color;
-
-pub const WALL: usize = 0;
-pub const FLOOR: usize = 1;
-// pub const DOOR: usize = 2;
+use std::{collections::HashMap, ops::Index};
 
 #[derive(Debug, Copy, Clone)]
 pub struct MapTile {
@@ -20,69 +15,149 @@ pub struct MapTile {
     pub move_weight: u8,
     /// Opacity 0.0..=1.0
     pub opacity: f32,
+    pub water: bool,
+    pub flammable: bool,
 }
 
 impl MapTile {
-    pub fn new(
-        ident: &str,
-        color: Color,
-        path_cost: Option<i32>,
-        move_weight: u8,
-        opacity: f32,
-        ts: &TileSet<'_>,
-    ) -> Self {
-        let idx = ts.tile_index(ident).unwrap();
+    pub fn blocks_movement(&self) -> bool {
+        self.path_cost.is_none()
+    }
+}
 
+#[cfg(test)]
+impl MapTile {
+    /// A blank tile with the given path cost for building maps in tests without a tileset
+    pub fn for_tests(path_cost: Option<i32>) -> Self {
         Self {
-            t: Tile::new_with_color(idx, color),
+            t: Tile::default(),
             bg: None,
             path_cost,
-            move_weight,
-            opacity,
+            move_weight: 1,
+            opacity: 0.0,
+            water: false,
+            flammable: false,
         }
     }
+}
 
-    pub fn with_bg(mut self, color: Color) -> Self {
-        self.bg = Some(color);
-        self
+/// A named terrain type loaded from data/tiles that has not yet been resolved against a
+/// particular tileset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileDef {
+    pub name: String,
+    /// The tile ident to use when there is no override for the current tileset family
+    pub ident: String,
+    /// Tileset family -> tile ident overrides
+    pub idents: HashMap<String, String>,
+    pub color: Color,
+    pub bg: Option<Color>,
+    pub path_cost: Option<i32>,
+    pub move_weight: u8,
+    pub opacity: f32,
+    pub water: bool,
+    pub flammable: bool,
+}
+
+impl TileDef {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ident: String::new(),
+            idents: HashMap::new(),
+            color: Color::WHITE,
+            bg: None,
+            path_cost: Some(1),
+            move_weight: 1,
+            opacity: 0.0,
+            water: false,
+            flammable: false,
+        }
     }
 
-    pub fn forest_tiles(ts: &TileSet<'_>) -> Vec<Self> {
-        vec![
-            // default "wall"
-            Self::new("club", palette::TREE_1, None, u8::MAX, 0.6, ts),
-            // floor
-            Self::new("dot", palette::EARTH, Some(1), 1, 0.0, ts),
-            // other trees
-            Self::new("club", palette::TREE_2, None, u8::MAX, 0.6, ts),
-            Self::new("spade", palette::TREE_1, None, u8::MAX, 0.7, ts),
-            Self::new("spade", palette::TREE_2, None, u8::MAX, 0.7, ts),
-        ]
-        .into_iter()
-        .map(|c| c.with_bg(palette::FOREST_BG))
-        .collect()
+    /// Look up the sprite for this terrain in the given tileset
+    pub fn resolve(&self, ts: &TileSet<'_>) -> anyhow::Result<MapTile> {
+        let ident = self.idents.get(ts.family()).unwrap_or(&self.ident);
+        let idx = ts.tile_index(ident).ok_or_else(|| {
+            anyhow!(
+                "terrain {:?}: unknown glyph {ident:?} for {} tilesets",
+                self.name,
+                ts.family()
+            )
+        })?;
+
+        Ok(MapTile {
+            t: Tile::new_with_color(idx, self.color),
+            bg: self.bg,
+            path_cost: self.path_cost,
+            move_weight: self.move_weight,
+            opacity: self.opacity,
+            water: self.water,
+            flammable: self.flammable,
+        })
     }
+}
+
+/// The set of terrain types in use on a given map, resolved against the current tileset.
+///
+/// Maps store indices into this set rather than the tiles themselves: builders look up the index
+/// for a given terrain by name using [Terrain::idx]. The first terrain in the set is the default
+/// that new maps are filled with and that is assumed to lie beyond the edges of the map.
+#[derive(Debug, Clone, Default)]
+pub struct Terrain {
+    tiles: Vec<MapTile>,
+    names: Vec<String>,
+}
+
+impl Terrain {
+    pub fn new<S: Into<String>>(tiles: impl IntoIterator<Item = (S, MapTile)>) -> Self {
+        let (names, tiles) = tiles.into_iter().map(|(s, t)| (s.into(), t)).unzip();
 
-    pub fn dungeon_tiles(ts: &TileSet<'_>) -> Vec<Self> {
-        vec![
-            Self::new("shade-dark", palette::GREY_13, None, u8::MAX, 1.0, ts),
-            Self::new("dot", palette::GREY_15, Some(1), 1, 0.0, ts),
-        ]
+        Self { tiles, names }
     }
 
-    pub fn wall(ts: &TileSet<'_>) -> Self {
-        Self::new("shade-dark", palette::GREY_13, None, u8::MAX, 1.0, ts)
+    /// The index of the named terrain within this set.
+    ///
+    /// # Panics
+    /// Panics if the terrain was not included when the set was created
+    pub fn idx(&self, name: &str) -> usize {
+        self.try_idx(name)
+            .unwrap_or_else(|| panic!("terrain {name:?} is not available for this map"))
     }
 
-    pub fn floor(ts: &TileSet<'_>) -> Self {
-        Self::new("dot", palette::EARTH, Some(1), 1, 0.0, ts)
+    pub fn try_idx(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
     }
 
-    //     pub fn door(ts: &TileSet<'_>, palette: &HashMap<String, Color>) -> Self {
-    //         Self::new("=", "faded_orange", Some(2), 2, 0.1, ts, palette)
-    //     }
+    pub fn name(&self, idx: usize) -> &str {
+        &self.names[idx]
+    }
 
-    pub fn blocks_movement(&self) -> bool {
-        self.path_cost.is_none()
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+}
+
+#[cfg(test)]
+impl Terrain {
+    /// A set of blank named tiles with the given path costs for building maps in tests
+    pub fn for_tests<'a>(tiles: impl IntoIterator<Item = (&'a str, Option<i32>)>) -> Self {
+        Self::new(
+            tiles
+                .into_iter()
+                .map(|(name, path_cost)| (name, MapTile::for_tests(path_cost))),
+        )
+    }
+}
+
+impl Index<usize> for Terrain {
+    type Output = MapTile;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.tiles[index]
     }
 }
@@ -1,4 +1,4 @@
-use crate::map::Map;
+use crate::map::{Map, MapId};
 use std::cmp::min;
 
 #[derive(Debug, Default, Clone)]
@@ -31,6 +31,17 @@ impl MapSet {
         self.maps.push(map);
     }
 
+    /// Make the map with the given id the current map, returning false if there is no such map
+    pub fn switch_to(&mut self, id: MapId) -> bool {
+        match self.maps.iter().position(|m| m.id == id) {
+            Some(idx) => {
+                self.current = idx;
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn next(&mut self) {
         self.current = min(self.current + 1, self.maps.len() - 1);
     }
@@ -1,4 +1,12 @@
-use crate::{Grid, Pos, grid::a_star, map::map_tile::MapTile};
+use crate::{
+    Grid, Pos,
+    grid::a_star,
+    map::{
+        features::{Feature, FeatureKind, Features},
+        map_tile::{MapTile, Terrain},
+    },
+};
+use desire_maps::DesireMaps;
 use fov::LightMap;
 use sdl2::{pixels::Color, rect::Rect};
 use std::{
@@ -9,13 +17,18 @@ use std::{
 };
 
 pub mod builders;
+pub mod desire_maps;
+pub mod features;
 pub mod fov;
 pub mod map_tile;
 mod mapset;
+pub mod prefab;
 
-use map_tile::WALL;
 pub use mapset::MapSet;
 
+/// The cost of pathing through a closed door for something that needs to open it first
+const DOOR_COST: i32 = 2;
+
 /// monatonically increasing map ID
 static NEXT_MAP_ID: AtomicU64 = AtomicU64::new(0);
 
@@ -28,20 +41,24 @@ pub struct Map {
     pub id: MapId,
     pub tiles: Grid<usize>,
     pub explored: HashSet<usize>,
-    pub tile_defs: Vec<MapTile>,
+    pub terrain: Terrain,
+    pub features: Features,
     pub light_map: Option<LightMap>,
+    pub desire_maps: Option<DesireMaps>,
     pub bg: Color,
     pub hidden: Color,
 }
 
 impl Map {
-    pub fn new(w: usize, h: usize, tile_defs: Vec<MapTile>, bg: Color, hidden: Color) -> Self {
+    pub fn new(w: usize, h: usize, terrain: Terrain, bg: Color, hidden: Color) -> Self {
         Self {
             id: MapId(NEXT_MAP_ID.fetch_add(1, Ordering::Relaxed)),
             tiles: Grid::new(w, h, 0),
             explored: HashSet::new(),
-            tile_defs,
+            terrain,
+            features: Features::new(),
             light_map: None,
+            desire_maps: None,
             bg,
             hidden,
         }
@@ -55,14 +72,36 @@ impl Map {
         self.explored = Default::default();
     }
 
+    /// The tile at the given position, treating anything outside of the map as being the default
+    /// (first) terrain for the map
     pub fn tile_at(&self, pos: Pos) -> &MapTile {
         if self.contains_pos(pos) {
-            &self.tile_defs[self.tiles[pos]]
+            &self.terrain[self.tiles[pos]]
         } else {
-            &self.tile_defs[WALL]
+            &self.terrain[0]
         }
     }
 
+    /// Place a feature on the map, replacing the terrain at `pos` with the feature's terrain
+    pub fn add_feature(&mut self, pos: Pos, feature: Feature) {
+        self.tiles[pos] = self.terrain.idx(feature.terrain());
+        self.features.insert(pos, feature);
+    }
+
+    /// Open or close the feature at `pos`, returning true if its state changed
+    pub fn set_feature_open(&mut self, pos: Pos, open: bool) -> bool {
+        let idx = match self.features.get_mut(&pos) {
+            Some(f) if f.open != open => {
+                f.open = open;
+                self.terrain.idx(f.terrain())
+            }
+            _ => return false,
+        };
+        self.tiles[pos] = idx;
+
+        true
+    }
+
     pub fn carve_rect(&mut self, r: Rect, tile_idx: usize) {
         for y in r.y..r.y + r.h {
             for x in r.x..r.x + r.w {
@@ -86,17 +125,33 @@ impl Map {
     pub fn a_star_in_player_explored(&self, a: Pos, b: Pos) -> Vec<Pos> {
         a_star(a, b, &self.tiles, |p| {
             if self.explored.contains(&self.pos_idx(p)) {
-                self.tile_defs[self.tiles[p]].path_cost
+                self.terrain[self.tiles[p]].path_cost
             } else {
                 None
             }
         })
     }
 
+    /// A path from `a` to `b` for a mob, which is able to open any closed doors along the way
     pub fn a_star(&self, a: Pos, b: Pos) -> Vec<Pos> {
-        a_star(a, b, &self.tiles, |p| {
-            self.tile_defs[self.tiles[p]].path_cost
-        })
+        a_star(a, b, &self.tiles, |p| self.path_cost_through_doors(p))
+    }
+
+    /// Whether there is a door at `p` that is currently closed
+    pub fn closed_door_at(&self, p: Pos) -> bool {
+        self.features
+            .get(&p)
+            .is_some_and(|f| f.kind == FeatureKind::Door && !f.open)
+    }
+
+    /// The path cost of `p` for something that is able to open doors: closed doors cost a little
+    /// extra rather than blocking movement.
+    pub fn path_cost_through_doors(&self, p: Pos) -> Option<i32> {
+        if self.closed_door_at(p) {
+            Some(DOOR_COST)
+        } else {
+            self.tile_at(p).path_cost
+        }
     }
 }
 
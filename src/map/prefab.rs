@@ -0,0 +1,292 @@
+//! Hand made map sections ("vaults") that can be stamped into generated maps.
+//!
+//! See data/prefabs/README for the file format.
+use crate::{
+    Grid, Pos,
+    grid::dijkstra_map,
+    map::{
+        Map,
+        features::{Feature, FeatureKind},
+    },
+};
+use rand::Rng;
+use std::collections::HashSet;
+
+/// How many random positions to try before giving up on placing a prefab
+const PLACEMENT_ATTEMPTS: usize = 100;
+
+/// What a single character of a prefab becomes when stamped into a map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrefabCell {
+    /// Leave the existing map terrain as it is
+    Keep,
+    /// Replace the map terrain with the named terrain from data/tiles
+    Terrain(String),
+    /// A door, portcullis or lever: levers are linked to every portcullis in the prefab
+    Feature(FeatureCell),
+    /// A possible starting position for the player
+    Start,
+    /// Somewhere to spawn a mob: either a specific mob from data/mobs or any of them
+    Mob(Option<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureCell {
+    Door,
+    Portcullis,
+    Lever,
+}
+
+impl PrefabCell {
+    pub fn from_name(s: &str) -> Self {
+        let mut words = s.split_whitespace();
+        match (words.next().unwrap_or(""), words.next()) {
+            ("keep", None) => Self::Keep,
+            ("start", None) => Self::Start,
+            ("mob", name) => Self::Mob(name.map(String::from)),
+            ("door", None) => Self::Feature(FeatureCell::Door),
+            ("portcullis", None) => Self::Feature(FeatureCell::Portcullis),
+            ("lever", None) => Self::Feature(FeatureCell::Lever),
+            _ => Self::Terrain(s.to_string()),
+        }
+    }
+}
+
+/// The positions of any markers found when stamping a prefab into a map
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stamped {
+    pub starts: Vec<Pos>,
+    pub mobs: Vec<(Pos, Option<String>)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Prefab {
+    pub name: String,
+    pub cells: Grid<PrefabCell>,
+    /// The terrain placed beneath markers and features
+    pub floor: Option<String>,
+}
+
+impl Prefab {
+    /// A copy of this prefab rotated by the given number of quarter turns clockwise and then
+    /// optionally mirrored left to right.
+    pub fn transformed(&self, quarter_turns: u8, mirror: bool) -> Self {
+        let mut cells = self.cells.clone();
+        for _ in 0..quarter_turns % 4 {
+            cells = cells.rotated_cw();
+        }
+        if mirror {
+            cells = cells.mirrored();
+        }
+
+        Self {
+            name: self.name.clone(),
+            cells,
+            floor: self.floor.clone(),
+        }
+    }
+
+    /// A copy of this prefab with a random rotation and mirroring
+    pub fn randomly_transformed(&self, rng: &mut impl Rng) -> Self {
+        self.transformed(rng.random_range(0..4), rng.random_bool(0.5))
+    }
+
+    /// The names of all terrain this prefab can place: maps it is stamped into need to include
+    /// each of these in their terrain.
+    pub fn terrain_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.floor.iter().map(|s| s.as_str()).collect();
+        for cell in self.cells.cells.iter() {
+            match cell {
+                PrefabCell::Terrain(name) => names.push(name),
+                PrefabCell::Feature(_) => names.extend(Feature::terrain_names()),
+                _ => (),
+            }
+        }
+        names.sort();
+        names.dedup();
+
+        names
+    }
+
+    /// Replace the section of `map` with its top left corner at `at` with this prefab.
+    pub fn stamp(&self, at: Pos, map: &mut Map) -> Stamped {
+        let mut stamped = Stamped::default();
+        let mut portcullises = Vec::new();
+        let mut levers = Vec::new();
+        let floor = self.floor.as_ref().map(|name| map.terrain.idx(name));
+
+        for (i, cell) in self.cells.cells.iter().enumerate() {
+            let p = at + Pos::new((i % self.cells.w) as i32, (i / self.cells.w) as i32);
+            if !map.contains_pos(p) {
+                continue;
+            }
+            map.features.remove(&p);
+
+            match cell {
+                PrefabCell::Keep => (),
+                PrefabCell::Terrain(name) => map.tiles[p] = map.terrain.idx(name),
+                PrefabCell::Feature(f) => {
+                    match f {
+                        FeatureCell::Door => map.add_feature(p, Feature::door()),
+                        FeatureCell::Portcullis => {
+                            map.add_feature(p, Feature::portcullis());
+                            portcullises.push(p);
+                        }
+                        FeatureCell::Lever => levers.push(p),
+                    };
+                }
+                PrefabCell::Start => {
+                    if let Some(idx) = floor {
+                        map.tiles[p] = idx;
+                    }
+                    stamped.starts.push(p);
+                }
+                PrefabCell::Mob(name) => {
+                    if let Some(idx) = floor {
+                        map.tiles[p] = idx;
+                    }
+                    stamped.mobs.push((p, name.clone()));
+                }
+            }
+        }
+
+        for p in levers {
+            map.add_feature(p, Feature::lever(portcullises.clone()));
+        }
+
+        stamped
+    }
+
+    /// Look for somewhere to stamp this prefab into `map` where it fits entirely within the
+    /// map, can be reached from `from` and doesn't cut off any part of the map that could
+    /// previously be reached.
+    pub fn find_placement(&self, map: &Map, from: Pos, rng: &mut impl Rng) -> Option<Pos> {
+        let (w, h) = (self.cells.w, self.cells.h);
+        if w + 2 > map.w || h + 2 > map.h {
+            return None;
+        }
+        let reachable_before = reachable(map, from);
+
+        for _ in 0..PLACEMENT_ATTEMPTS {
+            let at = Pos::new(
+                rng.random_range(1..=(map.w - w - 1)) as i32,
+                rng.random_range(1..=(map.h - h - 1)) as i32,
+            );
+            let inside = |p: Pos| {
+                p.x >= at.x && p.y >= at.y && p.x < at.x + w as i32 && p.y < at.y + h as i32
+            };
+            if inside(from) {
+                continue;
+            }
+
+            let mut candidate = map.clone();
+            self.stamp(at, &mut candidate);
+            let reachable_after = reachable(&candidate, from);
+
+            let keeps_map_connected = reachable_before
+                .iter()
+                .all(|p| inside(*p) || reachable_after.contains(p));
+            let connects = (0..candidate.len()).all(|i| {
+                let p = Pos::new((i % map.w) as i32, (i / map.w) as i32);
+                !inside(p) || candidate.tile_at(p).blocks_movement() || reachable_after.contains(&p)
+            });
+
+            if keeps_map_connected && connects {
+                return Some(at);
+            }
+        }
+
+        None
+    }
+}
+
+/// Every position that can be walked to from `from` (treating doors and portcullises as open)
+fn reachable(map: &Map, from: Pos) -> HashSet<Pos> {
+    let dmap = dijkstra_map(&map.tiles, &[(from, 0)], |p| match map.features.get(&p) {
+        Some(f) if !matches!(f.kind, FeatureKind::Lever { .. }) => Some(1),
+        _ => map.tile_at(p).path_cost,
+    });
+
+    dmap.cells
+        .iter()
+        .enumerate()
+        .filter(|(_, cost)| **cost != i32::MAX)
+        .map(|(i, _)| Pos::new((i % map.w) as i32, (i / map.w) as i32))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::map_tile::Terrain;
+    use sdl2::pixels::Color;
+
+    fn test_map() -> Map {
+        let terrain = Terrain::for_tests(
+            [("wall", None), ("floor", Some(1))]
+                .into_iter()
+                .chain(Feature::terrain_names().map(|name| (name, None))),
+        );
+        let mut map = Map::new(12, 12, terrain, Color::BLACK, Color::BLACK);
+        map.carve_rect(sdl2::rect::Rect::new(1, 1, 10, 10), 1);
+
+        map
+    }
+
+    // ####
+    // #D@|
+    // #P##
+    fn test_prefab() -> Prefab {
+        let wall = PrefabCell::Terrain("wall".to_string());
+        let cells = vec![
+            wall.clone(),
+            wall.clone(),
+            wall.clone(),
+            wall.clone(),
+            wall.clone(),
+            PrefabCell::Mob(None),
+            PrefabCell::Start,
+            PrefabCell::Feature(FeatureCell::Door),
+            wall.clone(),
+            PrefabCell::Feature(FeatureCell::Lever),
+            wall.clone(),
+            wall,
+        ];
+
+        Prefab {
+            name: "test".to_string(),
+            cells: Grid { cells, w: 4, h: 3 },
+            floor: Some("floor".to_string()),
+        }
+    }
+
+    #[test]
+    fn stamping_places_terrain_features_and_markers() {
+        let mut map = test_map();
+        let prefab = test_prefab().transformed(1, false);
+        // rotated a quarter turn clockwise:
+        // ###
+        // LD#
+        // #@#
+        // #|#
+        let stamped = prefab.stamp(Pos::new(2, 2), &mut map);
+
+        assert_eq!(stamped.starts, vec![Pos::new(3, 4)]);
+        assert_eq!(stamped.mobs, vec![(Pos::new(3, 3), None)]);
+        assert_eq!(map[Pos::new(3, 4)], map.terrain.idx("floor"));
+        assert_eq!(map.features[&Pos::new(3, 5)], Feature::door());
+        assert_eq!(map.features[&Pos::new(2, 3)], Feature::lever(vec![]));
+    }
+
+    #[test]
+    fn placements_connect_to_the_map() {
+        let map = test_map();
+        let prefab = test_prefab();
+        let from = Pos::new(1, 1);
+        let at = prefab.find_placement(&map, from, &mut rand::rng()).unwrap();
+
+        let mut map = map.clone();
+        let stamped = prefab.stamp(at, &mut map);
+        assert!(reachable(&map, from).contains(&stamped.starts[0]));
+    }
+}
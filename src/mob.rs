@@ -1,53 +1,204 @@
 use crate::{
     Pos,
     action::{Action, ActionProvider, AvailableActions},
-    actor::Actor,
+    actor::{Actor, Name, Stats},
+    ai::{BehaviourTree, Memory, Node},
+    dialogue::HasDialogue,
+    faction::Faction,
     map::fov::{FovRange, Opacity},
+    sound::{BARK_LOUDNESS, Sound, SoundKind},
     state::State,
     ui::palette,
 };
 use hecs::{Entity, EntityBuilder};
-use sdl2::pixels::Color;
-use std::cmp::{max, min};
+use rand::seq::IndexedRandom;
+use sdl2::{pixels::Color, rect::Rect};
+use std::{
+    cmp::{max, min},
+    collections::HashMap,
+};
 
+/// The definition of a kind of mob as loaded from data/mobs
+#[derive(Debug, Clone)]
 pub struct MobSpec {
-    pub name: &'static str,
-    pub ident: &'static str,
+    pub name: String,
+    pub ident: String,
     pub color: Color,
     pub fov_range: u32,
+    pub opacity: f32,
+    pub faction: Option<String>,
     pub ai: AiType,
+    pub stats: Stats,
+    pub barks: HashMap<BarkEvent, Vec<String>>,
+    /// How close this mob stays to the player once recruited (mobs without one can't be)
+    pub leash: Option<f32>,
+    /// The name of the dialogue tree (from data/dialogue) used when talking to this mob
+    pub dialogue: Option<String>,
+    /// Whether this mob can be chosen for spawns that don't name a particular mob
+    pub spawn_anywhere: bool,
 }
 
-pub const PIXIE: MobSpec = MobSpec {
-    name: "pixie",
-    ident: "pi",
-    color: palette::FADED_PURPLE,
-    fov_range: 4,
-    ai: AiType::Curious,
-};
-
-pub const SNOOT: MobSpec = MobSpec {
-    name: "snoot",
-    ident: "s",
-    color: palette::IBM_WHITE,
-    fov_range: 8,
-    ai: AiType::Snoot,
-};
+impl MobSpec {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ident: String::new(),
+            color: palette::IBM_WHITE,
+            fov_range: 4,
+            opacity: 0.5,
+            faction: None,
+            ai: AiType::Random,
+            stats: Stats::default(),
+            barks: HashMap::new(),
+            leash: None,
+            dialogue: None,
+            spawn_anywhere: true,
+        }
+    }
+}
 
 /// Mobs cover all sentient creatures other than the player.
 #[derive(Debug)]
 pub struct Mob;
 
+/// Mobs that can be recruited by the player to follow them around
+#[derive(Debug, Clone, Copy)]
+pub struct Recruitable {
+    pub leash: f32,
+}
+
+/// Townsfolk who live in one building of their town and work in another (which may be the same)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resident {
+    pub home: Rect,
+    pub work: Rect,
+}
+
+/// Minimum number of ticks between barks triggered by events
+const BARK_COOLDOWN: usize = 8;
+
+/// Events that can cause a mob to call out one of its [Barks]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BarkEvent {
+    /// Nothing in particular: used by the behaviour tree "bork" leaf
+    Idle,
+    /// The mob has just caught sight of the player
+    SpotPlayer,
+    /// The mob is running away
+    Flee,
+    /// The mob has been hit but survived
+    Hurt,
+}
+
+impl BarkEvent {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "idle" => Some(Self::Idle),
+            "spot_player" => Some(Self::SpotPlayer),
+            "flee" => Some(Self::Flee),
+            "hurt" => Some(Self::Hurt),
+            _ => None,
+        }
+    }
+}
+
+/// Lines that a mob can call out in response to different events
+#[derive(Debug, Default, Clone)]
+pub struct Barks {
+    pub lines: HashMap<BarkEvent, Vec<String>>,
+    last_tick: Option<usize>,
+}
+
+impl Barks {
+    pub fn new(lines: HashMap<BarkEvent, Vec<String>>) -> Self {
+        Self {
+            lines,
+            last_tick: None,
+        }
+    }
+
+    /// Have `entity` call out a random line for the given event if it has one and hasn't barked
+    /// too recently.
+    pub fn trigger(entity: Entity, event: BarkEvent, state: &mut State<'_>) -> anyhow::Result<()> {
+        let pos = match state.world.get::<&Pos>(entity) {
+            Ok(pos) => *pos,
+            Err(_) => return Ok(()),
+        };
+
+        let line = match state.world.get::<&mut Barks>(entity) {
+            Ok(mut barks) => {
+                if barks
+                    .last_tick
+                    .is_some_and(|t| state.tick.saturating_sub(t) < BARK_COOLDOWN)
+                {
+                    return Ok(());
+                }
+                let line = barks
+                    .lines
+                    .get(&event)
+                    .and_then(|lines| lines.choose(&mut rand::rng()))
+                    .cloned();
+                if line.is_some() {
+                    barks.last_tick = Some(state.tick);
+                }
+                line
+            }
+            Err(_) => return Ok(()),
+        };
+
+        match line {
+            Some(line) => Self::call_out(entity, pos, line, state),
+            None => Ok(()),
+        }
+    }
+
+    /// Show a line from `entity` and let anyone nearby hear it
+    pub fn call_out(
+        entity: Entity,
+        pos: Pos,
+        line: String,
+        state: &mut State<'_>,
+    ) -> anyhow::Result<()> {
+        state.bork(pos, line);
+
+        Sound::new(pos, BARK_LOUDNESS, SoundKind::Bark, Some(entity)).emit(state)
+    }
+}
+
 impl Mob {
-    pub fn spawn_spec(spec: MobSpec, x: i32, y: i32, state: &mut State<'_>) -> Entity {
+    /// Spawn a mob using the spec registered under `name`.
+    ///
+    /// Returns `None` if there is no such mob defined.
+    pub fn spawn_named(name: &str, x: i32, y: i32, state: &mut State<'_>) -> Option<Entity> {
+        let spec = state.mob_specs.get(name)?.clone();
+
+        Some(Self::spawn_spec(&spec, x, y, state))
+    }
+
+    pub fn spawn_spec(spec: &MobSpec, x: i32, y: i32, state: &mut State<'_>) -> Entity {
+        let mut builder = EntityBuilder::new();
+        if let Some(faction) = spec.faction.as_ref() {
+            builder.add(Faction(faction.clone()));
+        }
+        if let Some(leash) = spec.leash {
+            builder.add(Recruitable { leash });
+        }
+        if let Some(dialogue) = spec.dialogue.as_ref() {
+            builder.add(HasDialogue(dialogue.clone()));
+        }
+
         state.world.spawn(
-            EntityBuilder::new()
+            builder
                 .add(Mob)
+                .add(Name(spec.name.clone()))
                 .add(FovRange(spec.fov_range))
+                .add(spec.stats)
+                .add(Barks::new(spec.barks.clone()))
+                .add(Memory::default())
                 .add_bundle(Actor {
                     pos: Pos::new(x, y),
-                    tile: state.tile_with_color(spec.ident, spec.color),
-                    opacity: Opacity(0.5),
+                    tile: state.tile_with_color(&spec.ident, spec.color),
+                    opacity: Opacity(spec.opacity),
                     actions: spec.ai.as_available_actions(),
                 })
                 .build(),
@@ -55,19 +206,17 @@ impl Mob {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum AiType {
     Random,
-    Curious,
-    Snoot,
+    Tree(Node),
 }
 
 impl AiType {
     fn as_available_actions(&self) -> AvailableActions {
         match self {
             Self::Random => AvailableActions::from(RandomMoveAI),
-            Self::Curious => AvailableActions::from(CuriousAI::default()),
-            Self::Snoot => AvailableActions::from(SnootAI),
+            Self::Tree(root) => AvailableActions::from(BehaviourTree::new(root.clone())),
         }
     }
 }
@@ -92,113 +241,6 @@ impl ActionProvider for RandomMoveAI {
         pos.y = max(0, min(ymax as i32, pos.y));
         map.tile_at(pos).path_cost?;
 
-        Some(vec![Action::from(move |state: &mut State<'_>| {
-            *state.world.query_one_mut::<&mut Pos>(entity)? = pos;
-
-            Ok(())
-        })])
-    }
-}
-
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
-pub struct CuriousAI {
-    last_player_pos: Pos,
-}
-
-impl ActionProvider for CuriousAI {
-    fn retain(&self) -> bool {
-        true
-    }
-
-    fn available_actions(&mut self, entity: Entity, state: &State<'_>) -> Option<Vec<Action>> {
-        if state.mapset.is_empty() {
-            return None;
-        }
-
-        // required components
-        let pos = *state.world.get::<&Pos>(entity).ok()?;
-        let fov = state.world.get::<&FovRange>(entity).ok()?;
-        let player_pos = *state.world.get::<&Pos>(state.e_player).ok()?;
-
-        // potter around if we can't see the player
-        if !fov.fast_has_los(pos, player_pos, state) {
-            return RandomMoveAI.available_actions(entity, state);
-        }
-
-        let map = state.mapset.current();
-        let prev = self.last_player_pos.fdist(pos);
-        let current = pos.fdist(player_pos);
-        self.last_player_pos = player_pos;
-
-        // If the player is moving towards us: back off
-        if current < prev && current < 2.5 {
-            for p in map.neighbouring_tiles(pos) {
-                let dist = p.fdist(player_pos);
-                if map.tile_at(p).path_cost.is_some() && dist > current {
-                    return Some(vec![Action::from(move |state: &mut State<'_>| {
-                        *state.world.query_one_mut::<&mut Pos>(entity)? = p;
-
-                        Ok(())
-                    })]);
-                }
-            }
-        }
-
-        // Otherwise approach to a fixed distance
-        for p in map.neighbouring_tiles(pos) {
-            let dist = p.fdist(player_pos);
-            if map.tile_at(p).path_cost.is_some() && dist <= current && dist > 1.5 {
-                return Some(vec![Action::from(move |state: &mut State<'_>| {
-                    *state.world.query_one_mut::<&mut Pos>(entity)? = p;
-
-                    Ok(())
-                })]);
-            }
-        }
-
-        None
-    }
-}
-
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
-pub struct SnootAI;
-
-impl ActionProvider for SnootAI {
-    fn retain(&self) -> bool {
-        true
-    }
-
-    fn available_actions(&mut self, entity: Entity, state: &State<'_>) -> Option<Vec<Action>> {
-        if state.mapset.is_empty() {
-            return None;
-        }
-
-        // required components
-        let pos = *state.world.get::<&Pos>(entity).ok()?;
-        let fov = state.world.get::<&FovRange>(entity).ok()?;
-        let player_pos = *state.world.get::<&Pos>(state.e_player).ok()?;
-        let map = state.mapset.current();
-
-        // if there is a nearby pixie then run away
-        for (e, p) in state.world.query::<&Pos>().with::<&Mob>().iter() {
-            if e != entity && fov.fast_has_los(pos, *p, state) {
-                let current = pos.fdist(*p);
-                for p in map.neighbouring_tiles(pos) {
-                    let dist = p.fdist(player_pos);
-
-                    if map.tile_at(p).path_cost.is_some() && dist < current {
-                        return Some(vec![Action::from(move |state: &mut State<'_>| {
-                            state.bork(p, "woof!");
-                            *state.world.query_one_mut::<&mut Pos>(entity)? = p;
-
-                            Ok(())
-                        })]);
-                    }
-                }
-            }
-        }
-
-        // otherwise potter around
-        RandomMoveAI.available_actions(entity, state)
+        Some(vec![Actor::step_to(entity, pos)])
     }
 }
@@ -0,0 +1,326 @@
+//! The overworld: a coarse map of regions, each of which is explored as its own local map
+use crate::{
+    Grid, Pos,
+    action::Action,
+    ai::follow::Follower,
+    grid::dijkstra_map,
+    map::{
+        MapId,
+        builders::{
+            BuildMap, ConnectRegions, EdgeExits, NoiseTerrain, Pipeline, StampPrefab, Town,
+        },
+    },
+    player::Player,
+    rng::RngHandle,
+    state::State,
+    ui::{MAP_H, MAP_W},
+};
+use anyhow::bail;
+use rand::{Rng, seq::IndexedRandom};
+use std::collections::{HashMap, HashSet};
+
+pub const OVERWORLD_W: usize = 15;
+pub const OVERWORLD_H: usize = 8;
+
+/// Open pockets of the faerie ring smaller than this are filled in rather than joined up
+const MIN_POCKET_SIZE: usize = 12;
+
+/// How likely each kind of region is when laying out a new overworld
+const REGION_WEIGHTS: [(Region, u32); 4] = [
+    (Region::Forest, 80),
+    (Region::Village, 8),
+    (Region::Ruin, 7),
+    (Region::FaerieRing, 5),
+];
+
+/// The kind of local area represented by a single cell of the overworld
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Forest,
+    Village,
+    Ruin,
+    FaerieRing,
+}
+
+impl Region {
+    pub const ALL: [Self; 4] = [Self::Forest, Self::Village, Self::Ruin, Self::FaerieRing];
+
+    /// The terrain from data/tiles used to draw this region on the overworld map
+    pub fn terrain(&self) -> &'static str {
+        match self {
+            Self::Forest => "region_forest",
+            Self::Village => "region_village",
+            Self::Ruin => "region_ruin",
+            Self::FaerieRing => "region_faerie_ring",
+        }
+    }
+
+    /// How the region is referred to in log messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Forest => "the woods",
+            Self::Village => "a village",
+            Self::Ruin => "some ruins",
+            Self::FaerieRing => "a faerie ring",
+        }
+    }
+
+    /// The builder for the local map of this region. Every local map can be left from the middle
+    /// of each of its edges.
+    pub fn pipeline(&self) -> Pipeline {
+        match self {
+            Self::Forest => Pipeline::forest(),
+            Self::Village => Pipeline::town(Town::default()),
+            Self::Ruin => Pipeline::dungeon(),
+            Self::FaerieRing => Pipeline::overworld(NoiseTerrain::default())
+                .then(ConnectRegions(MIN_POCKET_SIZE))
+                .then(StampPrefab("faerie_ring")),
+        }
+        .then(EdgeExits)
+    }
+}
+
+/// The regions making up the world along with which of them the player has visited. Local maps
+/// are generated the first time a region is entered from a seed derived from its position so the
+/// same world seed always gives the same world.
+#[derive(Debug, Clone)]
+pub struct Overworld {
+    pub regions: Grid<Region>,
+    pub explored: HashSet<Pos>,
+    pub current: Pos,
+    pub seed: u64,
+    /// Set while the overworld map is being shown
+    pub viewing: bool,
+    /// The local maps generated so far and where the player started on each of them
+    visited: HashMap<Pos, (MapId, Pos)>,
+}
+
+impl Overworld {
+    /// Lay out a new overworld with the player starting in the forest at the middle of its
+    /// southern edge.
+    pub fn new(w: usize, h: usize, rng: &mut impl Rng) -> Self {
+        let mut regions = Grid::new(w, h, Region::Forest);
+        for region in regions.cells.iter_mut() {
+            *region = REGION_WEIGHTS.choose_weighted(rng, |(_, w)| *w).unwrap().0;
+        }
+
+        let current = Pos::new(w as i32 / 2, h as i32 - 1);
+        regions[current] = Region::Forest;
+
+        Self {
+            regions,
+            explored: HashSet::new(),
+            current,
+            seed: rng.random(),
+            viewing: false,
+            visited: HashMap::new(),
+        }
+    }
+
+    /// The seed used to generate the local map for the region at `pos`
+    pub fn seed_for(&self, pos: Pos) -> u64 {
+        self.seed ^ (((pos.x as u32 as u64) << 32) | pos.y as u32 as u64)
+    }
+}
+
+/// Enter the region of the overworld at `pos`, generating its local map if this is the first
+/// visit. The player is placed at the open cell closest to `arrive_at` that can be reached from
+/// the starting position for the map if it is given and at the starting position otherwise. Any
+/// followers of the player come along with them.
+pub fn enter_region(pos: Pos, arrive_at: Option<Pos>, state: &mut State<'_>) -> anyhow::Result<()> {
+    let ow = match state.overworld.as_mut() {
+        Some(ow) => ow,
+        None => bail!("there is no overworld to travel in"),
+    };
+    ow.current = pos;
+    ow.explored.insert(pos);
+    let (region, seed, visited) = (
+        ow.regions[pos],
+        ow.seed_for(pos),
+        ow.visited.get(&pos).copied(),
+    );
+
+    let followers = followers_on_current_map(state);
+
+    let start = match visited {
+        Some((id, start)) => {
+            state.switch_map(id);
+            start
+        }
+        None => {
+            // swap in a seeded rng so that the map only depends on the overworld seed
+            let rng = std::mem::replace(&mut state.rng, RngHandle::seeded(seed));
+            let (start, map) = region.pipeline().new_map(
+                MAP_W as usize,
+                MAP_H as usize,
+                Default::default(),
+                state,
+            );
+            state.rng = rng;
+
+            if let Some(ow) = state.overworld.as_mut() {
+                ow.visited.insert(pos, (map.id, start));
+            }
+            state.set_map(map);
+            start
+        }
+    };
+
+    let map = state.mapset.current();
+    let map_id = map.id;
+    let reachable = dijkstra_map(&map.tiles, &[(start, 0)], |p| {
+        map.path_cost_through_doors(p)
+    });
+    let player_pos = arrive_at
+        .and_then(|p| nearest_open(p, &reachable, &[]))
+        .unwrap_or(start);
+    Player::warp(player_pos, state);
+
+    let mut taken = vec![player_pos];
+    for e in followers {
+        if let Some(p) = nearest_open(player_pos, &reachable, &taken) {
+            *state.world.get::<&mut Pos>(e)? = p;
+            state.world.insert_one(e, map_id)?;
+            taken.push(p);
+        }
+    }
+
+    Ok(())
+}
+
+/// The action for the player walking off the edge of the current local map onto `to`, taking
+/// them into the neighbouring region if there is one in that direction.
+pub fn travel(to: Pos, state: &State<'_>) -> Option<Action> {
+    let ow = state.overworld.as_ref()?;
+    let map = state.mapset.current();
+    let (step, arrive_at) = crossing(to, map.w as i32, map.h as i32);
+    let next = ow.current + step;
+    let region = *ow.regions.try_cell_at(next)?;
+
+    Some(Action::from(move |state: &mut State<'_>| {
+        enter_region(next, Some(arrive_at), state)?;
+        state.log(format!(
+            "You travel {} into {}.",
+            direction(step),
+            region.name()
+        ));
+
+        Ok(())
+    }))
+}
+
+/// The direction to move in on the overworld when stepping to `to` from inside of a w x h local
+/// map, along with where on the next local map this would place you.
+fn crossing(to: Pos, w: i32, h: i32) -> (Pos, Pos) {
+    let step = |v: i32, max: i32| {
+        if v < 0 {
+            -1
+        } else if v >= max {
+            1
+        } else {
+            0
+        }
+    };
+
+    (
+        Pos::new(step(to.x, w), step(to.y, h)),
+        Pos::new(to.x.rem_euclid(w), to.y.rem_euclid(h)),
+    )
+}
+
+fn direction(step: Pos) -> &'static str {
+    match (step.x, step.y) {
+        (0, -1) => "north",
+        (0, 1) => "south",
+        (1, 0) => "east",
+        (-1, 0) => "west",
+        (1, -1) => "north east",
+        (-1, -1) => "north west",
+        (1, 1) => "south east",
+        _ => "south west",
+    }
+}
+
+/// The cell closest to `target` that isn't in `taken` out of those that can be reached on the
+/// given dijkstra map from the starting position
+fn nearest_open(target: Pos, reachable: &Grid<i32>, taken: &[Pos]) -> Option<Pos> {
+    (0..reachable.len())
+        .map(|i| Pos::new((i % reachable.w) as i32, (i / reachable.w) as i32))
+        .filter(|&p| reachable[p] != i32::MAX && !taken.contains(&p))
+        .min_by_key(|&p| (p.x - target.x).pow(2) + (p.y - target.y).pow(2))
+}
+
+/// Followers of the player on the current map who are actively following them
+fn followers_on_current_map(state: &State<'_>) -> Vec<hecs::Entity> {
+    if state.mapset.is_empty() {
+        return Vec::new();
+    }
+    let map_id = state.mapset.current().id;
+
+    state
+        .world
+        .query::<(&Follower, Option<&MapId>)>()
+        .iter()
+        .filter(|(_, (f, id))| {
+            f.leader == state.e_player && f.following && id.is_none_or(|id| *id == map_id)
+        })
+        .map(|(e, _)| e)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{Map, map_tile::Terrain};
+    use sdl2::pixels::Color;
+
+    #[test]
+    fn crossing_an_edge_wraps_to_the_opposite_side() {
+        for (to, step, arrive_at) in [
+            (Pos::new(10, -1), Pos::new(0, -1), Pos::new(10, 19)),
+            (Pos::new(10, 20), Pos::new(0, 1), Pos::new(10, 0)),
+            (Pos::new(-1, 5), Pos::new(-1, 0), Pos::new(29, 5)),
+            (Pos::new(30, 5), Pos::new(1, 0), Pos::new(0, 5)),
+            (Pos::new(30, -1), Pos::new(1, -1), Pos::new(0, 19)),
+            (Pos::new(3, 4), Pos::new(0, 0), Pos::new(3, 4)),
+        ] {
+            assert_eq!(crossing(to, 30, 20), (step, arrive_at), "{to:?}");
+        }
+    }
+
+    #[test]
+    fn arrivals_are_never_sealed_off_from_the_start() {
+        let terrain = Terrain::for_tests([("rock", None), ("meadow", Some(1))]);
+        let mut map = Map::new(10, 5, terrain, Color::BLACK, Color::BLACK);
+        for x in 0..4 {
+            map.tiles[Pos::new(x, 2)] = 1;
+        }
+        // a pocket right by the edge that can't be reached from the start
+        map.tiles[Pos::new(9, 2)] = 1;
+
+        let reachable = dijkstra_map(&map.tiles, &[(Pos::new(0, 2), 0)], |p| {
+            map.path_cost_through_doors(p)
+        });
+
+        assert_eq!(
+            nearest_open(Pos::new(9, 2), &reachable, &[]),
+            Some(Pos::new(3, 2))
+        );
+        assert_eq!(
+            nearest_open(Pos::new(9, 2), &reachable, &[Pos::new(3, 2)]),
+            Some(Pos::new(2, 2))
+        );
+    }
+
+    #[test]
+    fn each_region_has_its_own_seed() {
+        let ow = Overworld::new(OVERWORLD_W, OVERWORLD_H, &mut rand::rng());
+        let seeds: HashSet<u64> = (0..OVERWORLD_H as i32)
+            .flat_map(|y| (0..OVERWORLD_W as i32).map(move |x| Pos::new(x, y)))
+            .map(|p| ow.seed_for(p))
+            .collect();
+
+        assert_eq!(seeds.len(), OVERWORLD_W * OVERWORLD_H);
+        assert_eq!(ow.regions[ow.current], Region::Forest);
+    }
+}
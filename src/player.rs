@@ -2,16 +2,146 @@
 use crate::{
     Pos,
     action::AvailableActions,
-    actor::Actor,
-    map::fov::{Fov, FovRange, Opacity},
+    actor::{Actor, Stats},
+    ai::follow::Follower,
+    faction::Faction,
+    map::{
+        MapId,
+        features::{Feature, FeatureKind},
+        fov::{Fov, FovRange, LightSource, Opacity},
+    },
+    mob::Recruitable,
+    sound::{DROP_LOUDNESS, Sound, SoundKind},
     state::State,
     ui::palette,
 };
-use hecs::EntityBuilder;
+use hecs::{Entity, EntityBuilder};
 
 #[derive(Debug)]
 pub struct Player;
 
+/// A character's attitude towards the faerie world and its impact on the human one.
+///
+/// See design/character_generation.md for details.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    /// Human, faerie, sentient plant? Who cares.
+    #[default]
+    Indifferent,
+    /// This world sucks, maybe theirs is better
+    BetterThanHere,
+    /// We must worship the old ways and traditions
+    Reverent,
+    /// The faerie world is something to learn about and from
+    Inquisitive,
+    /// The faerie world is heretical and daemonic, it must be destroyed
+    Righteous,
+    /// The faerie world is something that we can exploit for our benefit
+    Opportunistic,
+}
+
+impl Disposition {
+    /// Adjustment to how faerie factions initially feel about a character with this disposition
+    pub fn faerie_modifier(&self) -> i32 {
+        match self {
+            Self::Indifferent => 0,
+            Self::BetterThanHere => 10,
+            Self::Reverent => 20,
+            Self::Inquisitive => 10,
+            Self::Righteous => -30,
+            Self::Opportunistic => -10,
+        }
+    }
+
+    /// Parse a disposition from its snake_case name as used in data files
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "indifferent" => Some(Self::Indifferent),
+            "better_than_here" => Some(Self::BetterThanHere),
+            "reverent" => Some(Self::Reverent),
+            "inquisitive" => Some(Self::Inquisitive),
+            "righteous" => Some(Self::Righteous),
+            "opportunistic" => Some(Self::Opportunistic),
+            _ => None,
+        }
+    }
+}
+
+/// The vocations available to characters, see design/character_generation.md
+pub const VOCATIONS: [&str; 36] = [
+    "hermit",
+    "poacher",
+    "mercenary",
+    "physician",
+    "locksmith",
+    "sergeant",
+    "priest",
+    "landed_gentry",
+    "pilgrim",
+    "commissioned_officer",
+    "grave_digger",
+    "privateer",
+    "trophy_hunter",
+    "con_artist",
+    "industrialist",
+    "antiquarian",
+    "smuggler",
+    "fence",
+    "cartographer",
+    "natural_philosopher",
+    "journalist",
+    "inventor",
+    "surgeon",
+    "blacksmith",
+    "alchemist",
+    "medium",
+    "herbalist",
+    "circus_performer",
+    "stone_mason",
+    "butcher",
+    "gutter_rat",
+    "factory_worker",
+    "chimney_sweep",
+    "farm_hand",
+    "gambler",
+    "debt_collector",
+];
+
+/// What a character did before taking up with the guild: one of [VOCATIONS]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vocation(pub String);
+
+impl Default for Vocation {
+    fn default() -> Self {
+        Self(VOCATIONS[0].to_string())
+    }
+}
+
+/// The names of the items that a character is carrying
+#[derive(Debug, Default, Clone)]
+pub struct Inventory(pub Vec<String>);
+
+impl Inventory {
+    pub fn contains(&self, item: &str) -> bool {
+        self.0.iter().any(|i| i == item)
+    }
+
+    /// Remove a single copy of the given item, returning whether or not there was one to remove
+    pub fn take(&mut self, item: &str) -> bool {
+        match self.0.iter().position(|i| i == item) {
+            Some(idx) => {
+                self.0.remove(idx);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A light source carried by the player that is currently doused
+#[derive(Debug, Clone, Copy)]
+pub struct DousedLight(pub LightSource);
+
 impl Player {
     pub fn new_base_bundle(pos: Pos, fov_range: FovRange, state: &State<'_>) -> EntityBuilder {
         let mut builder = Self::new_bundle_without_fov(pos, state);
@@ -22,16 +152,141 @@ impl Player {
 
     pub fn new_bundle_without_fov(pos: Pos, state: &State<'_>) -> EntityBuilder {
         let mut builder = EntityBuilder::new();
-        builder.add(Player).add_bundle(Actor {
-            pos,
-            tile: state.tile_with_color("@", palette::WHITE),
-            opacity: Opacity(0.7),
-            actions: AvailableActions::default(),
-        });
+        builder
+            .add(Player)
+            .add(Faction::player())
+            .add(Disposition::default())
+            .add(Vocation::default())
+            .add(Inventory(vec!["biscuit".to_string()]))
+            .add(Stats {
+                hp: 10,
+                max_hp: 10,
+                attack: 2,
+                defence: 0,
+            })
+            .add_bundle(Actor {
+                pos,
+                tile: state.tile_with_color("@", palette::WHITE),
+                opacity: Opacity(0.7),
+                actions: AvailableActions::default(),
+            });
 
         builder
     }
 
+    /// Recruit an adjacent mob that is willing to follow the player, or if there isn't one then
+    /// tell any current followers on this map to either wait where they are or to start following
+    /// again.
+    pub fn command_followers(state: &mut State<'_>) -> anyhow::Result<()> {
+        let e = state.e_player;
+        let pos = *state.world.get::<&Pos>(e)?;
+        let map_id = state.mapset.current().id;
+
+        let recruit = state
+            .world
+            .query::<(&Pos, &Recruitable, Option<&MapId>)>()
+            .without::<&Follower>()
+            .iter()
+            .find(|(_, (p, _, id))| p.fdist(pos) < 1.5 && id.is_none_or(|id| *id == map_id))
+            .map(|(recruit, (_, r, _))| (recruit, r.leash));
+
+        if let Some((recruit, leash)) = recruit {
+            state.world.insert_one(recruit, Follower::new(e, leash))?;
+            let name = state.describe(recruit);
+            state.log(format!("You beckon to {name} and it falls in behind you."));
+            return Ok(());
+        }
+
+        let mut followers: Vec<(Entity, bool)> = state
+            .world
+            .query::<(&Follower, Option<&MapId>)>()
+            .iter()
+            .filter(|(_, (f, id))| f.leader == e && id.is_none_or(|id| *id == map_id))
+            .map(|(follower, (f, _))| (follower, f.following))
+            .collect();
+        if followers.is_empty() {
+            return Ok(());
+        }
+
+        followers.sort_by_key(|(follower, _)| follower.id());
+        let following = !followers.iter().any(|(_, following)| *following);
+        for (follower, _) in followers.iter() {
+            state.world.get::<&mut Follower>(*follower)?.following = following;
+            let name = state.describe(*follower);
+            if following {
+                state.log(format!("You call {name} to follow you."));
+            } else {
+                state.log(format!("You tell {name} to wait here."));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Close any open doors next to the player that nothing is currently standing in.
+    pub fn close_doors(state: &mut State<'_>) -> anyhow::Result<()> {
+        if state.mapset.is_empty() {
+            return Ok(());
+        }
+
+        let pos = *state.world.get::<&Pos>(state.e_player)?;
+        let doors: Vec<Pos> = state
+            .mapset
+            .current()
+            .features
+            .iter()
+            .filter(|(p, f)| {
+                f.kind == FeatureKind::Door && f.open && p.fdist(pos) < 1.5 && *p != &pos
+            })
+            .map(|(p, _)| *p)
+            .filter(|p| state.actor_at(*p).is_none())
+            .collect();
+
+        if doors.is_empty() {
+            state.log("There is no open door here to close.");
+            return Ok(());
+        }
+
+        for p in doors {
+            Feature::set_open(p, false, state);
+            state.log("You close the door.");
+        }
+
+        Ok(())
+    }
+
+    /// Douse the player's light if it is lit, or relight it if it was previously doused.
+    pub fn toggle_light(state: &mut State<'_>) -> anyhow::Result<()> {
+        let e = state.e_player;
+        if let Ok(light) = state.world.remove_one::<LightSource>(e) {
+            state.world.insert_one(e, DousedLight(light))?;
+            state.log("You douse your lantern and the shadows close in.");
+        } else if let Ok(DousedLight(light)) = state.world.remove_one::<DousedLight>(e) {
+            state.world.insert_one(e, light)?;
+            state.log("You relight your lantern.");
+        }
+
+        Ok(())
+    }
+
+    /// Drop the most recently picked up item, making a noise that nearby mobs may come to
+    /// investigate
+    pub fn drop_item(state: &mut State<'_>) -> anyhow::Result<()> {
+        let e = state.e_player;
+        let item = state.world.get::<&mut Inventory>(e)?.0.pop();
+        match item {
+            Some(item) => {
+                let pos = *state.world.get::<&Pos>(e)?;
+                state.log(format!("You drop the {item}."));
+                Sound::new(pos, DROP_LOUDNESS, SoundKind::Drop, Some(e)).emit(state)
+            }
+            None => {
+                state.log("You have nothing to drop.");
+                Ok(())
+            }
+        }
+    }
+
     pub fn warp(new_pos: Pos, state: &State<'_>) {
         let mut pos = state.world.get::<&mut Pos>(state.e_player).unwrap();
         *pos = new_pos;
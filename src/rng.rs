@@ -1,16 +1,30 @@
 use crate::Pos;
-use rand::{Rng as _, rngs::ThreadRng};
+use rand::{Rng as _, SeedableRng, rngs::StdRng};
 use sdl2::rect::Rect;
 use std::ops::{Deref, DerefMut};
 
-#[derive(Default)]
 pub struct RngHandle {
-    rng: ThreadRng,
+    rng: StdRng,
+}
+
+impl Default for RngHandle {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RngHandle {
     pub fn new() -> Self {
-        Self { rng: rand::rng() }
+        Self {
+            rng: StdRng::from_os_rng(),
+        }
+    }
+
+    /// A handle that always produces the same sequence of values for a given seed
+    pub fn seeded(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
     }
 
     pub fn roll(&mut self, sides: u16) -> u16 {
@@ -47,7 +61,7 @@ impl RngHandle {
 }
 
 impl Deref for RngHandle {
-    type Target = ThreadRng;
+    type Target = StdRng;
 
     fn deref(&self) -> &Self::Target {
         &self.rng
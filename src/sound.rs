@@ -0,0 +1,176 @@
+//! Sounds made by actions in the world and how they travel across the map.
+//!
+//! Sound spreads out from its source as a Dijkstra flood limited by its loudness. Each cell it
+//! passes through costs more the denser the cell is, so thick trees and walls muffle sounds that
+//! would carry a long way in the open.
+use crate::{
+    Grid, Pos,
+    ai::Memory,
+    grid::dijkstra_map_within,
+    map::{Map, MapId, fov::Fov, map_tile::MapTile},
+    mob::Mob,
+    state::State,
+};
+use hecs::Entity;
+use rand::Rng;
+
+/// Base loudness of a single footstep, increased by the move weight of the cell being entered
+pub const FOOTSTEP_LOUDNESS: i32 = 2;
+/// Loudness of an exchange of blows
+pub const COMBAT_LOUDNESS: i32 = 12;
+/// Loudness of a mob calling out
+pub const BARK_LOUDNESS: i32 = 16;
+/// Loudness of an item hitting the ground
+pub const DROP_LOUDNESS: i32 = 6;
+
+/// Additional cost for sound to pass through a fully opaque cell
+const DAMPING: f32 = 10.0;
+/// Sounds at least this loud are reported to the player when they can't see where they came from
+const NOTICEABLE: i32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundKind {
+    Footsteps,
+    Combat,
+    Bark,
+    Drop,
+}
+
+impl SoundKind {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Self::Footsteps => "footsteps",
+            Self::Combat => "the sounds of a fight",
+            Self::Bark => "a bark",
+            Self::Drop => "something being dropped",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sound {
+    pub pos: Pos,
+    pub loudness: i32,
+    pub kind: SoundKind,
+    /// The entity making the sound (who does not need to hear it)
+    pub source: Option<Entity>,
+}
+
+impl Sound {
+    pub fn new(pos: Pos, loudness: i32, kind: SoundKind, source: Option<Entity>) -> Self {
+        Self {
+            pos,
+            loudness,
+            kind,
+            source,
+        }
+    }
+
+    /// Let any mobs and the player within earshot know about this sound.
+    ///
+    /// Mobs that hear it remember roughly where it came from (fainter sounds are harder to place)
+    /// and the player is told about noticeable sounds that come from somewhere they can't see.
+    pub fn emit(self, state: &mut State<'_>) -> anyhow::Result<()> {
+        if state.mapset.is_empty() {
+            return Ok(());
+        }
+
+        let map = state.mapset.current();
+        let map_id = map.id;
+        let spread = self.propagate(map);
+        let mut rng = rand::rng();
+
+        for (e, (pos, memory, id)) in state
+            .world
+            .query::<(&Pos, &mut Memory, Option<&MapId>)>()
+            .with::<&Mob>()
+            .iter()
+        {
+            let cost = spread.try_cell_at(*pos).copied().unwrap_or(i32::MAX);
+            if Some(e) == self.source || cost == i32::MAX || id.is_some_and(|id| *id != map_id) {
+                continue;
+            }
+
+            let mut heard_at = self.pos;
+            if cost * 2 > self.loudness {
+                heard_at += Pos::new(rng.random_range(-1..=1), rng.random_range(-1..=1));
+            }
+            memory.hear(heard_at, state.tick);
+        }
+
+        if self.source == Some(state.e_player) || self.loudness < NOTICEABLE {
+            return Ok(());
+        }
+
+        let player_pos = match state.world.get::<&Pos>(state.e_player) {
+            Ok(pos) => *pos,
+            Err(_) => return Ok(()),
+        };
+        let in_view = state
+            .world
+            .get::<&Fov>(state.e_player)
+            .is_ok_and(|fov| fov.points.contains(&self.pos));
+        let heard = spread
+            .try_cell_at(player_pos)
+            .is_some_and(|&c| c != i32::MAX);
+
+        if heard && !in_view {
+            let what = self.kind.describe();
+            match direction(player_pos, self.pos) {
+                Some(dir) => state.log(format!("You hear {what} to the {dir}.")),
+                None => state.log(format!("You hear {what} nearby.")),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// How much of this sound remains at each cell of the map: unreachable cells are i32::MAX
+    pub fn propagate(&self, map: &Map) -> Grid<i32> {
+        dijkstra_map_within(&map.tiles, &[(self.pos, 0)], self.loudness, |p| {
+            Some(sound_cost(map.tile_at(p)))
+        })
+    }
+}
+
+/// The cost for sound to travel through a given tile
+fn sound_cost(tile: &MapTile) -> i32 {
+    1 + (tile.opacity * DAMPING) as i32
+}
+
+/// The compass direction from one position to another (with north being up the screen)
+fn direction(from: Pos, to: Pos) -> Option<&'static str> {
+    let (dx, dy) = ((to.x - from.x) as f32, (from.y - to.y) as f32);
+    if dx == 0.0 && dy == 0.0 {
+        return None;
+    }
+
+    const DIRS: [&str; 8] = [
+        "east",
+        "north east",
+        "north",
+        "north west",
+        "west",
+        "south west",
+        "south",
+        "south east",
+    ];
+    let octant = (dy.atan2(dx) / std::f32::consts::FRAC_PI_4).round() as i32;
+
+    Some(DIRS[octant.rem_euclid(8) as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directions_follow_the_screen() {
+        let origin = Pos::new(5, 5);
+
+        assert_eq!(direction(origin, Pos::new(5, 0)), Some("north"));
+        assert_eq!(direction(origin, Pos::new(9, 9)), Some("south east"));
+        assert_eq!(direction(origin, Pos::new(0, 6)), Some("west"));
+        assert_eq!(direction(origin, origin), None);
+    }
+}
@@ -2,20 +2,41 @@
 use crate::{
     FRAME_LEN_MS, Pos,
     action::{Action, AvailableActions},
+    actor::{Name, Stats, capitalise},
+    ai::{
+        Memory,
+        perception::{perceives_at, visibility},
+    },
+    data_files::{
+        parse_color_palette, parse_dialogues, parse_factions, parse_mob_defs, parse_prefabs,
+        parse_tile_defs, parse_turmite_rules,
+    },
+    dialogue::{Conversation, DialogueTree},
+    faction::{Faction, Relationship, Relationships},
     map::{
-        Map, MapSet,
+        Map, MapId, MapSet,
+        builders::{self, TurmiteRule},
+        desire_maps::DesireMaps,
         fov::{Fov, FovRange, LightMap, LightSource, Opacity},
+        map_tile::{Terrain, TileDef},
+        prefab::{Prefab, PrefabCell},
     },
-    mob::Mob,
+    mob::{BarkEvent, Barks, Mob, MobSpec},
+    overworld::{Overworld, Region},
     player::Player,
     rng::RngHandle,
     tileset::{Tile, TileSet},
-    ui::{Bork, Box, DisplayMode, LOGICAL_W, MAP_H, Sdl2UI, UI_H, palette},
+    ui::{
+        Bork, Box, ColorPalette, DisplayMode, LOGICAL_W, MAP_H, MAP_W, Sdl2UI, UI_H, palette,
+        wrap_text,
+    },
 };
+use anyhow::bail;
 use hecs::{Entity, World};
+use indexmap::IndexMap;
 use sdl2::{event::WindowEvent, pixels::Color, rect::Rect};
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     thread::sleep,
     time::{Duration, Instant},
 };
@@ -23,13 +44,26 @@ use std::{
 pub mod mode;
 pub use mode::{GameMode, LocalMap};
 
+/// Number of cells used to show how visible the player is in the UI
+const VIS_BAR_LEN: usize = 5;
+
 pub struct State<'a> {
     pub rng: RngHandle,
     pub world: World,
     pub e_player: Entity,
     pub mapset: MapSet,
+    pub overworld: Option<Overworld>,
     pub ui: Sdl2UI<'a>,
     pub ts: TileSet<'a>,
+    pub palette: ColorPalette,
+    pub mob_specs: IndexMap<String, MobSpec>,
+    pub tile_defs: IndexMap<String, TileDef>,
+    pub prefabs: HashMap<String, Prefab>,
+    pub turmite_rules: IndexMap<String, TurmiteRule>,
+    pub relationships: Relationships,
+    pub dialogues: HashMap<String, DialogueTree>,
+    pub conversation: Option<Conversation>,
+    pub quest_flags: HashSet<String>,
     pub running: bool,
     pub action_queue: VecDeque<Action>,
     pub log: Vec<String>,
@@ -44,14 +78,71 @@ impl<'a> State<'a> {
         let mut world = World::new();
         let e_player = world.spawn(());
         let mapset = MapSet::new();
+        let palette = parse_color_palette()?;
+        let mob_specs = parse_mob_defs("data/mobs", &palette)?;
+        let relationships = parse_factions("data/factions")?;
+        let dialogues = parse_dialogues("data/dialogue")?;
+        let tile_defs = parse_tile_defs("data/tiles", &palette)?;
+
+        let prefabs = parse_prefabs("data/prefabs")?;
+        let turmite_rules = parse_turmite_rules("data/turmites")?;
+
+        if !turmite_rules.contains_key(builders::DEFAULT_TURMITE_RULE) {
+            bail!("missing turmite rule {:?}", builders::DEFAULT_TURMITE_RULE);
+        }
+        if !prefabs.contains_key(builders::DEFAULT_WFC_SAMPLE) {
+            bail!("missing prefab {:?}", builders::DEFAULT_WFC_SAMPLE);
+        }
+
+        for def in tile_defs.values() {
+            def.resolve(&ts)?;
+        }
+
+        for prefab in prefabs.values() {
+            for name in prefab.terrain_names() {
+                if !tile_defs.contains_key(name) {
+                    bail!("prefab {:?} has unknown terrain {name:?}", prefab.name);
+                }
+            }
+            for cell in prefab.cells.cells.iter() {
+                if let PrefabCell::Mob(Some(name)) = cell
+                    && !mob_specs.contains_key(name)
+                {
+                    bail!("prefab {:?} has unknown mob {name:?}", prefab.name);
+                }
+            }
+        }
+
+        for spec in mob_specs.values() {
+            if let Some(faction) = spec.faction.as_ref()
+                && !relationships.contains(faction)
+            {
+                bail!("mob {:?} has unknown faction {faction:?}", spec.name);
+            }
+            if let Some(dialogue) = spec.dialogue.as_ref()
+                && !dialogues.contains_key(dialogue)
+            {
+                bail!("mob {:?} has unknown dialogue {dialogue:?}", spec.name);
+            }
+        }
 
         Ok(State {
             rng: RngHandle::new(),
             world,
             e_player,
             mapset,
+            overworld: None,
             ui,
             ts,
+            palette,
+            mob_specs,
+            tile_defs,
+            prefabs,
+            turmite_rules,
+            relationships,
+            dialogues,
+            conversation: None,
+            quest_flags: HashSet::new(),
             running: true,
             action_queue: VecDeque::new(),
             log: Vec::new(),
@@ -65,8 +156,20 @@ impl<'a> State<'a> {
 
         mode.init(self)?;
 
-        while self.running {
-            let event = self.ui.wait_event();
+        while self.running && !mode.is_finished(self) {
+            // keep redrawing while there are speech bubbles on screen so that they expire on time
+            let event = if self.world.query::<&Bork>().iter().next().is_some() {
+                match self.ui.wait_event_timeout(FRAME_LEN_MS as u32) {
+                    Some(event) => event,
+                    None => {
+                        self.wait_for_frame();
+                        mode.update_ui(self)?;
+                        continue;
+                    }
+                }
+            } else {
+                self.ui.wait_event()
+            };
             match event {
                 Event::Window {
                     win_event: WindowEvent::SizeChanged(w, h) | WindowEvent::Resized(w, h),
@@ -96,25 +199,55 @@ impl<'a> State<'a> {
         self.log.push(msg.into());
     }
 
-    pub fn bork(&mut self, mut pos: Pos, msg: impl Into<String>) -> Entity {
-        if pos.y == 0 {
-            pos.y = 2;
-        } else {
-            pos.y -= 1;
+    /// A short description of an entity for use in log messages
+    pub fn describe(&self, entity: Entity) -> String {
+        if entity == self.e_player {
+            return "you".to_string();
         }
-        if pos.x == 0 {
-            pos.x = 2;
-        } else {
-            pos.x -= 1;
+
+        match self.world.get::<&Name>(entity) {
+            Ok(name) => format!("the {}", name.0),
+            Err(_) => "something".to_string(),
+        }
+    }
+
+    /// The actor (anything with [Stats]) at the given position on the current map if there is one
+    pub fn actor_at(&self, pos: Pos) -> Option<Entity> {
+        if self.mapset.is_empty() {
+            return None;
         }
+        let map_id = self.mapset.current().id;
 
-        self.world.spawn((Bork {
-            pos,
-            msg: msg.into(),
-            fg: palette::IBM_WHITE,
-            bg: palette::FOREST_BG,
-            from_tick: self.tick,
-        },))
+        self.world
+            .query::<(&Pos, Option<&MapId>)>()
+            .with::<&Stats>()
+            .iter()
+            .find(|(_, (p, id))| **p == pos && id.is_none_or(|id| *id == map_id))
+            .map(|(e, _)| e)
+    }
+
+    /// How the entity `from` feels about the entity `to`
+    pub fn relationship(&self, from: Entity, to: Entity) -> Relationship {
+        self.relationships.between(&self.world, from, to)
+    }
+
+    /// Show a speech bubble for something said by whoever is at `speaker`
+    pub fn bork(&mut self, speaker: Pos, msg: impl Into<String>) -> Entity {
+        let others: Vec<_> = self
+            .world
+            .query::<&Bork>()
+            .iter()
+            .map(|(_, b)| b.rect())
+            .collect();
+        let bork = Bork::new(
+            speaker,
+            &msg.into(),
+            palette::IBM_WHITE,
+            palette::FOREST_BG,
+            &others,
+        );
+
+        self.world.spawn((bork,))
     }
 
     pub fn tick_with<M: GameMode>(&mut self, mode: &M) -> anyhow::Result<()> {
@@ -162,12 +295,17 @@ impl<'a> State<'a> {
     }
 
     fn run_actor_actions(&mut self) -> anyhow::Result<()> {
+        self.update_desire_maps()?;
+        self.update_memories()?;
+
+        let map_id = self.mapset.current().id;
         let actions: Vec<_> = self
             .world
-            .query::<&mut AvailableActions>()
+            .query::<(&mut AvailableActions, Option<&MapId>)>()
             .without::<&Player>()
             .iter()
-            .filter_map(|(e, aa)| aa.next_action(e, self))
+            .filter(|(_, (_, id))| id.is_none_or(|id| *id == map_id))
+            .filter_map(|(e, (aa, _))| aa.next_action(e, self))
             .collect();
 
         for action in actions {
@@ -208,10 +346,34 @@ impl<'a> State<'a> {
         tile
     }
 
+    /// Resolve the named terrain types from data/tiles against the current tileset for use on a
+    /// new map.
+    ///
+    /// # Panics
+    /// Panics if any of the names are not defined in data/tiles
+    pub fn terrain(&self, names: &[&str]) -> Terrain {
+        Terrain::new(names.iter().map(|&name| {
+            let def = self
+                .tile_defs
+                .get(name)
+                .unwrap_or_else(|| panic!("unknown terrain {name:?}"));
+            let tile = def.resolve(&self.ts).unwrap();
+
+            (name, tile)
+        }))
+    }
+
     pub fn set_map(&mut self, map: Map) {
-        self.ui.set_bg(map.bg);
+        let id = map.id;
         self.mapset.push(map);
-        self.mapset.next();
+        self.switch_map(id);
+    }
+
+    /// Switch back to a map that has previously been set with [State::set_map]
+    pub fn switch_map(&mut self, id: MapId) {
+        if self.mapset.switch_to(id) {
+            self.ui.set_bg(self.mapset.current().bg);
+        }
     }
 
     /// This will no-op rather than error if we are missing the correct player components
@@ -231,11 +393,13 @@ impl<'a> State<'a> {
             Err(_) => return Ok(()),
         };
 
+        let map_id = self.mapset.current().id;
         let objects: HashMap<Pos, Opacity> = self
             .world
-            .query::<(&Pos, &Opacity)>()
+            .query::<(&Pos, &Opacity, Option<&MapId>)>()
             .iter()
-            .map(|(_, (&pos, &op))| (pos, op))
+            .filter(|(_, (_, _, id))| id.is_none_or(|id| *id == map_id))
+            .map(|(_, (&pos, &op, _))| (pos, op))
             .collect();
 
         let map = self.mapset.current_mut();
@@ -255,9 +419,13 @@ impl<'a> State<'a> {
             Err(_) => return Ok(()),
         };
         let map = self.mapset.current_mut();
-        let mut sources = self.world.query::<(&Pos, &LightSource)>();
-        let light_map =
-            LightMap::from_sources(map, &fov, sources.iter().map(|(_, s)| s), map.hidden);
+        let map_id = map.id;
+        let mut sources = self.world.query::<(&Pos, &LightSource, Option<&MapId>)>();
+        let sources = sources
+            .iter()
+            .filter(|(_, (_, _, id))| id.is_none_or(|id| *id == map_id))
+            .map(|(_, (p, s, _))| (p, s));
+        let light_map = LightMap::from_sources(map, &fov, sources, map.hidden);
 
         for p in fov.points.iter() {
             if light_map.points.contains_key(p) {
@@ -271,6 +439,85 @@ impl<'a> State<'a> {
         Ok(())
     }
 
+    /// Rebuild the desire maps for the current map if the player or any mob has moved since they
+    /// were last computed.
+    pub fn update_desire_maps(&mut self) -> anyhow::Result<()> {
+        if self.mapset.is_empty() {
+            return Ok(());
+        }
+
+        let player_pos = match self.world.get::<&Pos>(self.e_player) {
+            Ok(pos) => *pos,
+            Err(_) => return Ok(()),
+        };
+
+        let map_id = self.mapset.current().id;
+        let mut faction_positions: HashMap<String, Vec<Pos>> = HashMap::new();
+        for (_, (pos, f, _)) in self
+            .world
+            .query::<(&Pos, &Faction, Option<&MapId>)>()
+            .with::<&Mob>()
+            .iter()
+            .filter(|(_, (_, _, id))| id.is_none_or(|id| *id == map_id))
+        {
+            faction_positions.entry(f.0.clone()).or_default().push(*pos);
+        }
+
+        let map = self.mapset.current_mut();
+        if let Some(dmaps) = map.desire_maps.as_ref()
+            && !dmaps.is_stale(player_pos, &faction_positions)
+        {
+            return Ok(());
+        }
+        map.desire_maps = Some(DesireMaps::new(map, player_pos, faction_positions));
+
+        Ok(())
+    }
+
+    /// Record the positions of any actors that mobs on the current map are able to perceive.
+    pub fn update_memories(&mut self) -> anyhow::Result<()> {
+        if self.mapset.is_empty() {
+            return Ok(());
+        }
+
+        let map_id = self.mapset.current().id;
+        let actors: Vec<(Entity, Pos, f32)> = self
+            .world
+            .query::<(&Pos, Option<&MapId>)>()
+            .with::<&Stats>()
+            .iter()
+            .filter(|(_, (_, id))| id.is_none_or(|id| *id == map_id))
+            .map(|(e, (pos, _))| (e, *pos, visibility(e, self)))
+            .collect();
+
+        let mut spotted_player = Vec::new();
+        for (e, (pos, fov, memory, id)) in self
+            .world
+            .query::<(&Pos, &FovRange, &mut Memory, Option<&MapId>)>()
+            .iter()
+        {
+            if id.is_some_and(|id| *id != map_id) {
+                continue;
+            }
+
+            memory.forget_stale(self.tick);
+            for &(other, p, vis) in actors.iter() {
+                if other != e && perceives_at(*pos, *fov, p, vis, self) {
+                    if other == self.e_player && memory.last_seen(other).is_none() {
+                        spotted_player.push(e);
+                    }
+                    memory.see(other, p, self.tick);
+                }
+            }
+        }
+
+        for e in spotted_player {
+            Barks::trigger(e, BarkEvent::SpotPlayer, self)?;
+        }
+
+        Ok(())
+    }
+
     pub fn clear_with_comp<T: hecs::Component>(&mut self) -> anyhow::Result<()> {
         let entities: Vec<_> = self
             .world
@@ -319,7 +566,7 @@ impl<'a> State<'a> {
             for (x, tile_idx) in line.iter().enumerate() {
                 r.x = x as i32 * dxy;
                 r.y = y as i32 * dxy;
-                let mut tile = map.tile_defs[*tile_idx];
+                let mut tile = map.terrain[*tile_idx];
 
                 if let Some((fov, light_map)) = fov_and_light_map.as_ref() {
                     let p = Pos::new(x as i32, y as i32);
@@ -364,10 +611,28 @@ impl<'a> State<'a> {
             )?;
         }
 
+        // How visible the player currently is
+        if !self.mapset.is_empty() {
+            let vis = visibility(self.e_player, self);
+            let filled = (vis * VIS_BAR_LEN as f32).round() as usize;
+            let bar = format!(
+                " visibility {}{} ",
+                "*".repeat(filled),
+                ".".repeat(VIS_BAR_LEN - filled)
+            );
+            self.ts.blit_text(
+                Pos::new((LOGICAL_W as usize - bar.len() - 1) as i32, MAP_H as i32),
+                &bar,
+                white,
+                self.ui.dxy,
+                &mut self.ui.buf,
+            )?;
+        }
+
         // Barks
         let mut to_remove = Vec::new();
         for (e, bork) in self.world.query::<&mut Bork>().iter() {
-            if self.tick - bork.from_tick >= 1 {
+            if bork.is_expired() {
                 to_remove.push(e);
                 continue;
             }
@@ -381,7 +646,121 @@ impl<'a> State<'a> {
         Ok(())
     }
 
+    /// Render the current conversation (if there is one) in a box over the bottom of the map
+    pub fn blit_conversation(&mut self) -> anyhow::Result<()> {
+        let conv = match self.conversation.as_ref() {
+            Some(conv) => conv,
+            None => return Ok(()),
+        };
+        let node = match conv.current_node(self) {
+            Some(node) => node,
+            None => return Ok(()),
+        };
+
+        let (x, w) = (2, LOGICAL_W - 5);
+        // text is rendered at 2/3 of the width of a map cell
+        let text_w = (w as usize - 2) * 3 / 2;
+
+        let mut lines = vec![(
+            format!("{}:", capitalise(&self.describe(conv.speaker))),
+            palette::IBM_WHITE,
+        )];
+        for para in node.text.iter() {
+            lines.extend(
+                wrap_text(para, text_w)
+                    .into_iter()
+                    .map(|l| (l, palette::IBM_WHITE)),
+            );
+        }
+        lines.push((String::new(), palette::IBM_WHITE));
+
+        let choices = conv.choices(self);
+        if choices.is_empty() {
+            lines.push(("1. (leave)".to_string(), palette::FIRE_1));
+        }
+        for (i, choice) in choices.iter().enumerate() {
+            let text = format!("{}. {}", i + 1, choice.text);
+            lines.extend(
+                wrap_text(&text, text_w)
+                    .into_iter()
+                    .map(|l| (l, palette::FIRE_1)),
+            );
+        }
+
+        let h = lines.len() as u32 + 1;
+        let y = MAP_H - h - 2;
+        let bg = self.mapset.current().bg;
+
+        let mut r = Rect::new(0, 0, self.ui.dxy, self.ui.dxy);
+        let mut t = self.ts.tile("square").unwrap();
+        t.color = bg;
+        for cy in y..=y + h {
+            for cx in x..=x + w {
+                r.x = (cx * self.ui.dxy) as i32;
+                r.y = (cy * self.ui.dxy) as i32;
+                self.ts.blit_tile(&t, r, &mut self.ui.buf)?;
+            }
+        }
+
+        let b = Box::new(x, y, w, h, palette::IBM_WHITE);
+        self.ts.blit_box(&b, self.ui.dxy, &mut self.ui.buf)?;
+        for (i, (line, color)) in lines.iter().enumerate() {
+            let pos = Pos::new(x as i32 + 1, (y + 1) as i32 + i as i32);
+            self.ts
+                .blit_text(pos, line, *color, self.ui.dxy, &mut self.ui.buf)?;
+        }
+
+        Ok(())
+    }
+
+    /// Render the explored regions of the overworld in place of the local map, marking the one
+    /// that the player is currently in
+    pub fn blit_overworld(&mut self) -> anyhow::Result<()> {
+        let ow = match self.overworld.as_ref() {
+            Some(ow) => ow,
+            None => return Ok(()),
+        };
+        let terrain = self.terrain(&Region::ALL.map(|r| r.terrain()));
+        let player = self.world.get::<&Tile>(self.e_player).ok().map(|t| *t);
+
+        // each region is drawn as a block of cells filling the map area
+        let (bw, bh) = (MAP_W / ow.regions.w as u32, MAP_H / ow.regions.h as u32);
+        let dxy = self.ui.dxy;
+        let mut r = Rect::new(0, 0, dxy, dxy);
+        let mut square = self.ts.tile("square").unwrap();
+
+        for &p in ow.explored.iter() {
+            let tile = terrain[terrain.idx(ow.regions[p].terrain())];
+            let (x, y) = (p.x as u32 * bw, p.y as u32 * bh);
+
+            if let Some(bg) = tile.bg {
+                square.color = bg;
+                for cy in y..y + bh {
+                    for cx in x..x + bw {
+                        r.x = (cx * dxy) as i32;
+                        r.y = (cy * dxy) as i32;
+                        self.ts.blit_tile(&square, r, &mut self.ui.buf)?;
+                    }
+                }
+            }
+
+            r.x = ((x + bw / 2) * dxy) as i32;
+            r.y = ((y + bh / 2) * dxy) as i32;
+            match player {
+                Some(t) if p == ow.current => {
+                    self.ts.blit_tile(&t, r, &mut self.ui.buf)?;
+                    let b = Box::new(x, y, bw - 1, bh - 1, palette::IBM_WHITE);
+                    self.ts.blit_box(&b, dxy, &mut self.ui.buf)?;
+                }
+                _ => self.ts.blit_tile(&tile.t, r, &mut self.ui.buf)?,
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn blit_tiles(&mut self) -> anyhow::Result<()> {
+        let map_id = (!self.mapset.is_empty()).then(|| self.mapset.current().id);
         let fov_lm = if self.mapset.is_empty() {
             None
         } else {
@@ -401,9 +780,12 @@ impl<'a> State<'a> {
         macro_rules! blit_tile_groups {
             ($($C:ty),+) => {
                 $(
-                    for (_entity, (pos, tile)) in
-                        self.world.query::<(&Pos, &Tile)>().with::<&$C>().iter()
+                    for (_entity, (pos, tile, id)) in
+                        self.world.query::<(&Pos, &Tile, Option<&MapId>)>().with::<&$C>().iter()
                     {
+                        if id.is_some_and(|id| Some(*id) != map_id) {
+                            continue;
+                        }
                         let mut tile = *tile;
                         if let Some((fov, light_map)) = fov_lm.as_ref() {
                             if !fov.points.contains(pos) {
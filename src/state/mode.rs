@@ -1,9 +1,12 @@
 //! Modes that the game can be in
 use crate::{
-    action::{Action, quit, toggle_explored, zoom_in, zoom_out},
+    Pos,
+    action::{Action, AvailableActions, quit, toggle_explored, zoom_in, zoom_out},
     actor::Actor,
+    dialogue::Conversation,
     map::{
-        builders::{BuildMap, Forest},
+        MapId,
+        builders::{BuildMap, Pipeline},
         fov::LightSource,
     },
     mob::Mob,
@@ -30,6 +33,12 @@ pub trait GameMode {
 
     /// Called per input event to obtain the next game action
     fn action_for_input_event(&self, event: &Event, state: &State<'_>) -> Option<Action>;
+
+    /// Checked before each input event: once this returns true control passes back to whatever
+    /// started this mode
+    fn is_finished(&self, _state: &State<'_>) -> bool {
+        false
+    }
 }
 
 // Allow closures to be used as simple UI update functions that don't have any additional handling
@@ -93,6 +102,12 @@ impl GameMode for LocalMap {
                 Keycode::N => Actor::try_move(1, 1, state.e_player, state),
 
                 Keycode::Z => Actor::wait(state.e_player, state),
+                Keycode::T => Some(Action::from(Player::toggle_light)),
+                Keycode::F => Some(Action::from(Player::command_followers)),
+                Keycode::S => Some(Action::from(talk)),
+                Keycode::O => Some(Action::from(Player::close_doors)),
+                Keycode::D => Some(Action::from(Player::drop_item)),
+                Keycode::M => Some(Action::from(view_overworld)),
 
                 Keycode::RightBracket => Some(zoom_in.into()),
                 Keycode::LeftBracket => Some(zoom_out.into()),
@@ -102,6 +117,13 @@ impl GameMode for LocalMap {
                 // Debug actions
                 Keycode::Space => Some(toggle_explored.into()),
 
+                Keycode::Num1 => spawn_nth_mob(0),
+                Keycode::Num2 => spawn_nth_mob(1),
+                Keycode::Num3 => spawn_nth_mob(2),
+                Keycode::Num4 => spawn_nth_mob(3),
+                Keycode::Num5 => spawn_nth_mob(4),
+                Keycode::I => Some(inspect_mobs.into()),
+
                 Keycode::C => Some(Action::from(move |state: &mut State<'_>| {
                     state.clear_with_comp::<LightSource>()
                 })),
@@ -110,7 +132,7 @@ impl GameMode for LocalMap {
                     state.clear_with_comp::<LightSource>()?;
                     state.clear_with_comp::<Mob>()?;
 
-                    let (pos, map) = Forest::default().new_map(
+                    let (pos, map) = Pipeline::forest().new_map(
                         MAP_W as usize,
                         MAP_H as usize,
                         Default::default(),
@@ -129,6 +151,25 @@ impl GameMode for LocalMap {
                 _ => None,
             },
 
+            // Attacking something that isn't a threat has to be done deliberately
+            Event::KeyDown {
+                keycode: Some(k),
+                repeat: false,
+                keymod: Mod::LCTRLMOD,
+                ..
+            } => match k {
+                Keycode::L | Keycode::Right => Actor::force_attack(1, 0, state.e_player, state),
+                Keycode::H | Keycode::Left => Actor::force_attack(-1, 0, state.e_player, state),
+                Keycode::K | Keycode::Up => Actor::force_attack(0, -1, state.e_player, state),
+                Keycode::J | Keycode::Down => Actor::force_attack(0, 1, state.e_player, state),
+                Keycode::Y => Actor::force_attack(-1, -1, state.e_player, state),
+                Keycode::U => Actor::force_attack(1, -1, state.e_player, state),
+                Keycode::B => Actor::force_attack(-1, 1, state.e_player, state),
+                Keycode::N => Actor::force_attack(1, 1, state.e_player, state),
+
+                _ => None,
+            },
+
             Event::KeyDown {
                 keycode: Some(k),
                 repeat: false,
@@ -175,3 +216,176 @@ impl GameMode for LocalMap {
         }
     }
 }
+
+/// Talk to an adjacent mob, dropping into the [Dialogue] mode until the conversation is over
+fn talk(state: &mut State<'_>) -> anyhow::Result<()> {
+    if Conversation::start_adjacent(state)? {
+        state.run_mode(Dialogue)?;
+    } else {
+        state.log("There is nobody here to talk to.");
+    }
+
+    Ok(())
+}
+
+/// Show the overworld map, dropping into the [OverworldMap] mode until it is closed
+fn view_overworld(state: &mut State<'_>) -> anyhow::Result<()> {
+    match state.overworld.as_mut() {
+        Some(ow) => {
+            ow.viewing = true;
+            state.run_mode(OverworldMap)?;
+        }
+        None => state.log("You have no map of the surrounding lands."),
+    }
+
+    Ok(())
+}
+
+/// The regions of the overworld that the player has explored so far, shown in place of the local
+/// map
+pub struct OverworldMap;
+impl GameMode for OverworldMap {
+    fn init(&self, state: &mut State<'_>) -> anyhow::Result<()> {
+        self.update_ui(state)
+    }
+
+    fn after_action(&self, _state: &mut State<'_>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn update_ui(&self, state: &mut State<'_>) -> anyhow::Result<()> {
+        state.ui.clear();
+        state.blit_overworld()?;
+        state.blit_ui()?;
+        state.ui.render()
+    }
+
+    fn action_for_input_event(&self, event: &Event, _state: &State<'_>) -> Option<Action> {
+        match *event {
+            Event::Quit { .. } => Some(quit.into()),
+            Event::KeyDown {
+                keycode: Some(Keycode::Escape | Keycode::M),
+                ..
+            } => Some(Action::from(|state: &mut State<'_>| {
+                if let Some(ow) = state.overworld.as_mut() {
+                    ow.viewing = false;
+                }
+                Ok(())
+            })),
+            _ => None,
+        }
+    }
+
+    fn is_finished(&self, state: &State<'_>) -> bool {
+        state.overworld.as_ref().is_none_or(|ow| !ow.viewing)
+    }
+}
+
+/// A conversation with an NPC rendered over the top of the local map
+pub struct Dialogue;
+impl GameMode for Dialogue {
+    fn init(&self, state: &mut State<'_>) -> anyhow::Result<()> {
+        self.update_ui(state)
+    }
+
+    fn after_action(&self, _state: &mut State<'_>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn update_ui(&self, state: &mut State<'_>) -> anyhow::Result<()> {
+        state.ui.clear();
+        state.blit_all()?;
+        state.blit_conversation()?;
+        state.ui.render()
+    }
+
+    fn action_for_input_event(&self, event: &Event, _state: &State<'_>) -> Option<Action> {
+        let n = match *event {
+            Event::Quit { .. } => return Some(quit.into()),
+            Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            } => {
+                return Some(Action::from(|state: &mut State<'_>| {
+                    state.conversation = None;
+                    Ok(())
+                }));
+            }
+            Event::KeyDown {
+                keycode: Some(k), ..
+            } => match k {
+                Keycode::Num1 => 0,
+                Keycode::Num2 => 1,
+                Keycode::Num3 => 2,
+                Keycode::Num4 => 3,
+                Keycode::Num5 => 4,
+                Keycode::Num6 => 5,
+                Keycode::Num7 => 6,
+                Keycode::Num8 => 7,
+                Keycode::Num9 => 8,
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(Action::from(move |state: &mut State<'_>| {
+            Conversation::choose(n, state)
+        }))
+    }
+
+    fn is_finished(&self, state: &State<'_>) -> bool {
+        state.conversation.is_none()
+    }
+}
+
+/// Debug helper for spawning the nth mob defined in data/mobs next to the player
+fn spawn_nth_mob(n: usize) -> Option<Action> {
+    Some(Action::from(move |state: &mut State<'_>| {
+        let name = match state.mob_specs.get_index(n) {
+            Some((name, _)) => name.clone(),
+            None => return Ok(()),
+        };
+        let pos = *state.world.get::<&Pos>(state.e_player)?;
+        let map = state.mapset.current();
+        let p = match map
+            .neighbouring_tiles(pos)
+            .find(|&p| !map.tile_at(p).blocks_movement())
+        {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let map_id = map.id;
+
+        if let Some(entity) = Mob::spawn_named(&name, p.x, p.y, state) {
+            state.world.insert_one(entity, map_id)?;
+            state.log(format!("DEBUG: spawned a {name}"));
+        }
+
+        Ok(())
+    }))
+}
+
+/// Debug helper for logging the behaviour tree nodes currently running for mobs on this map
+fn inspect_mobs(state: &mut State<'_>) -> anyhow::Result<()> {
+    let map_id = state.mapset.current().id;
+    let lines: Vec<String> = state
+        .world
+        .query::<(&AvailableActions, Option<&MapId>)>()
+        .with::<&Mob>()
+        .iter()
+        .filter(|(_, (_, id))| id.is_none_or(|id| *id == map_id))
+        .flat_map(|(e, (actions, _))| {
+            let desc = state.describe(e);
+            actions
+                .running_nodes()
+                .into_iter()
+                .map(move |node| format!("DEBUG: {desc}: {node}"))
+        })
+        .collect();
+
+    for line in lines {
+        state.log(line);
+    }
+
+    Ok(())
+}
@@ -22,6 +22,8 @@ pub struct TileSet<'a> {
     gap: u16,
     tiles: Vec<Pos>,
     idents: HashMap<String, usize>,
+    /// Tilesets sharing a family share the same tile idents
+    family: String,
 }
 
 impl<'a> Default for TileSet<'a> {
@@ -105,6 +107,7 @@ impl<'a> TileSet<'a> {
 
     pub(crate) fn new(
         path: impl AsRef<Path>,
+        family: impl Into<String>,
         dx: u16,
         dy: u16,
         start: Pos,
@@ -131,6 +134,7 @@ impl<'a> TileSet<'a> {
             gap,
             tiles: Vec::new(),
             idents: HashMap::new(),
+            family: family.into(),
         })
     }
 
@@ -167,6 +171,11 @@ impl<'a> TileSet<'a> {
             .map(|&idx| Tile::new_with_color(idx, color))
     }
 
+    /// The family of tilesets this belongs to: the name of the directory containing its tile.map
+    pub fn family(&self) -> &str {
+        &self.family
+    }
+
     pub fn tile_index(&self, ident: &str) -> Option<usize> {
         self.idents.get(ident).copied()
     }
@@ -259,7 +268,14 @@ impl<'a> TileSet<'a> {
     pub fn blit_bork(
         &mut self,
         Bork {
-            pos, msg, fg, bg, ..
+            pos,
+            w,
+            h,
+            lines,
+            tail: (tail, tail_glyph),
+            fg,
+            bg,
+            ..
         }: &Bork,
         dxy: u32,
         dest: &mut Surface,
@@ -267,19 +283,37 @@ impl<'a> TileSet<'a> {
         let tdxy = 2 * dxy / 3;
         let dxy = dxy as i32;
 
-        let mut r = Rect::new(pos.x * dxy, pos.y * dxy, tdxy, tdxy);
-        let mut buf = [0; 4];
-
-        for ch in msg.chars() {
-            let t = self.tile_with_color("square", *bg).unwrap();
-            self.blit_tile(&t, r, dest)?;
+        let t = self.tile_with_color("square", *bg).unwrap();
+        let mut r = Rect::new(0, 0, dxy as u32, dxy as u32);
+        for y in pos.y..pos.y + h {
+            for x in pos.x..pos.x + w {
+                r.x = x * dxy;
+                r.y = y * dxy;
+                self.blit_tile(&t, r, dest)?;
+            }
+        }
+        r.x = tail.x * dxy;
+        r.y = tail.y * dxy;
+        self.blit_tile(&t, r, dest)?;
 
-            let ident = ch.encode_utf8(&mut buf);
-            let tile = self.tile_with_color(ident, *fg).unwrap();
-            self.blit_tile(&tile, r, dest)?;
-            r.x += tdxy as i32;
+        let mut buf = [0; 4];
+        for (i, line) in lines.iter().enumerate() {
+            let mut r = Rect::new(pos.x * dxy, (pos.y + i as i32) * dxy, tdxy, tdxy);
+            for ch in line.chars() {
+                let ident = ch.encode_utf8(&mut buf);
+                let tile = self.tile_with_color(ident, *fg).unwrap();
+                self.blit_tile(&tile, r, dest)?;
+                r.x += tdxy as i32;
+            }
         }
 
+        let tile = self.tile_with_color(tail_glyph, *fg).unwrap();
+        self.blit_tile(
+            &tile,
+            Rect::new(tail.x * dxy, tail.y * dxy, tdxy, tdxy),
+            dest,
+        )?;
+
         Ok(())
     }
 
@@ -1,4 +1,6 @@
+use anyhow::anyhow;
 use sdl2::pixels::Color;
+use std::collections::HashMap;
 
 pub mod palette {
     use super::Color;
@@ -31,11 +33,54 @@ pub mod palette {
     pub const FIRE_2: Color = from_hex("ac4427"); // #ac4427
 
     pub const WATER_1: Color = from_hex("3d515b"); // #3d515b
+    pub const WATER_2: Color = from_hex("1f3741"); // #1f3741
 
     pub const GREY_13: Color = from_hex("504945"); // #504945
     pub const GREY_15: Color = from_hex("32302f"); // #32302f
 }
 
+/// Named colours loaded at runtime from data/color.palette
+#[derive(Debug, Default, Clone)]
+pub struct ColorPalette(HashMap<String, Color>);
+
+impl ColorPalette {
+    pub fn new(colors: HashMap<String, Color>) -> Self {
+        Self(colors)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Color> {
+        self.0.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Parse a colour given either as "#RRGGBB" or as the name of a colour in the palette
+    pub fn parse(&self, s: &str) -> anyhow::Result<Color> {
+        if s.starts_with('#') {
+            parse_hex(s).ok_or_else(|| anyhow!("invalid colour hex: {s:?}"))
+        } else {
+            self.get(s).ok_or_else(|| anyhow!("unknown colour: {s:?}"))
+        }
+    }
+}
+
+/// Parse a colour of the form "#RRGGBB"
+pub fn parse_hex(s: &str) -> Option<Color> {
+    let hex = s.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let [_, r, g, b] = u32::from_str_radix(hex, 16).ok()?.to_be_bytes();
+
+    Some(Color::RGB(r, g, b))
+}
+
 impl ColorExt for Color {
     fn rgba(&self) -> (u8, u8, u8, u8) {
         (self.r, self.g, self.b, self.a)
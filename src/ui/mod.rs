@@ -9,10 +9,11 @@ use sdl2::{
     surface::Surface,
     video::{Window, WindowContext},
 };
+use std::time::{Duration, Instant};
 
 mod color;
 
-pub use color::{ColorExt, palette};
+pub use color::{ColorExt, ColorPalette, palette, parse_hex};
 
 pub const LOGICAL_W: u32 = 75;
 pub const LOGICAL_H: u32 = 50;
@@ -176,12 +177,150 @@ impl Box {
     }
 }
 
-/// A temporary UI string pinned to a given map position
+/// Split text into lines of at most `width` characters, breaking on whitespace where possible.
+pub fn wrap_text(s: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in s.split_whitespace() {
+        let mut word = word;
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        // words longer than a full line have to be split
+        while word.chars().count() > width {
+            let split = word.char_indices().nth(width).map(|(i, _)| i).unwrap();
+            lines.push(word[..split].to_string());
+            word = &word[split..];
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Maximum number of characters on a single line of a [Bork]
+const BORK_WRAP: usize = 18;
+/// Minimum time that a [Bork] stays on screen
+const BORK_BASE_MS: u64 = 1500;
+/// Additional time that a [Bork] stays on screen per character so that longer lines can be read
+const BORK_MS_PER_CHAR: u64 = 60;
+
+/// A temporary speech bubble pinned next to whoever is speaking
 #[derive(Debug)]
 pub struct Bork {
+    /// Top left corner of the bubble in map cells
     pub pos: Pos,
-    pub msg: String,
+    /// Width and height of the bubble in map cells
+    pub w: i32,
+    pub h: i32,
+    pub lines: Vec<String>,
+    /// The cell between the bubble and the speaker along with the glyph pointing at the speaker
+    pub tail: (Pos, &'static str),
     pub fg: Color,
     pub bg: Color,
-    pub from_tick: usize,
+    pub expires: Instant,
+}
+
+impl Bork {
+    /// Place a new bubble next to `speaker`, keeping it on the map and avoiding `others` where
+    /// possible.
+    pub fn new(speaker: Pos, msg: &str, fg: Color, bg: Color, others: &[Rect]) -> Self {
+        let lines = wrap_text(msg, BORK_WRAP);
+        let chars = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        // text is rendered at 2/3 of the width of a map cell
+        let w = ((chars * 2).div_ceil(3) as i32).max(1);
+        let h = lines.len() as i32;
+
+        let above = (speaker.y - 1 - h, (Pos::new(speaker.x, speaker.y - 1), "v"));
+        let below = (speaker.y + 2, (Pos::new(speaker.x, speaker.y + 1), "^"));
+        let candidates = [
+            (speaker.x, above),
+            (speaker.x - w + 1, above),
+            (speaker.x, below),
+            (speaker.x - w + 1, below),
+        ]
+        .map(|(x, (y, tail))| (Rect::new(x, y, w as u32, h as u32), tail));
+
+        let on_map = |(r, (tail, _)): &(Rect, (Pos, &str))| {
+            let (w, h) = (MAP_W as i32, MAP_H as i32);
+            r.x() >= 0
+                && r.y() >= 0
+                && r.right() <= w
+                && r.bottom() <= h
+                && (0..w).contains(&tail.x)
+                && (0..h).contains(&tail.y)
+        };
+
+        let (r, tail) = candidates
+            .iter()
+            .filter(|c| on_map(c))
+            .find(|(r, _)| !others.iter().any(|o| overlaps(*o, *r)))
+            .or_else(|| candidates.iter().find(|c| on_map(c)))
+            .copied()
+            .unwrap_or_else(|| {
+                // squeeze the bubble back onto the map above the speaker
+                let (mut r, tail) = candidates[0];
+                r.x = r.x.clamp(0, (MAP_W as i32 - w).max(0));
+                r.y = r.y.max(0);
+                (r, tail)
+            });
+
+        let ms = BORK_BASE_MS + BORK_MS_PER_CHAR * msg.chars().count() as u64;
+
+        Self {
+            pos: Pos::new(r.x, r.y),
+            w,
+            h,
+            lines,
+            tail,
+            fg,
+            bg,
+            expires: Instant::now() + Duration::from_millis(ms),
+        }
+    }
+
+    /// The area covered by this bubble in map cells
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.pos.x, self.pos.y, self.w as u32, self.h as u32)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires
+    }
+}
+
+fn overlaps(a: Rect, b: Rect) -> bool {
+    a.x() < b.right() && b.x() < a.right() && a.y() < b.bottom() && b.y() < a.bottom()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_text_breaks_on_whitespace() {
+        let lines = wrap_text("the quick brown fox jumps", 10);
+
+        assert_eq!(lines, vec!["the quick", "brown fox", "jumps"]);
+    }
+
+    #[test]
+    fn borks_stay_on_the_map_and_avoid_each_other() {
+        let (fg, bg) = (Color::WHITE, Color::BLACK);
+        let corner = Bork::new(Pos::new(0, 0), "woof!", fg, bg, &[]);
+        assert!(corner.pos.x >= 0 && corner.pos.y >= 0);
+        assert_eq!(corner.tail.1, "^");
+
+        let first = Bork::new(Pos::new(20, 20), "woof!", fg, bg, &[]);
+        let second = Bork::new(Pos::new(20, 20), "woof!", fg, bg, &[first.rect()]);
+        assert!(!overlaps(first.rect(), second.rect()));
+    }
 }